@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures::future::poll_fn;
+
+use crate::{ApnsId, SendError};
+
+
+/// How a batch send adapts the number of notifications it sends at once,
+/// instead of sending a fixed number. See
+/// `SendOptions::adaptive_concurrency`.
+///
+/// The limit grows by one for each round of sends that succeed within
+/// `latency_target`, and is multiplied by `decrease` when a send is
+/// answered with a 429 or a 5xx, fails in transport or takes longer than
+/// `latency_target`, at most once per `latency_target` so that the sends
+/// already in flight when APNS started pushing back don't shrink it again.
+#[derive(Clone, Debug)]
+pub struct AdaptiveConcurrency {
+    /// The limit the batch starts at.
+    pub initial: usize,
+    pub min: usize,
+    pub max: usize,
+    /// Sends taking longer than this count as a sign of congestion.
+    pub latency_target: Duration,
+    /// The factor by which the limit shrinks on congestion, below 1.
+    pub decrease: f64,
+}
+
+impl AdaptiveConcurrency {
+    /// Adapt between 1 and `max` sends at once, starting at up to 8.
+    pub fn new(max: usize) -> Self {
+        AdaptiveConcurrency {
+            initial: max.min(8),
+            min: 1,
+            max,
+            latency_target: Duration::from_secs(1),
+            decrease: 0.5,
+        }
+    }
+}
+
+struct State {
+    limit: f64,
+    in_flight: usize,
+    decreased_at: Option<Instant>,
+    /// The next ticket to hand out, and the one whose turn it is.
+    next_ticket: u64,
+    serving: u64,
+    /// The tickets waiting for their turn or for room under the limit.
+    waiting: HashMap<u64, Waker>,
+}
+
+/// The current limit of an adaptive batch, and the sends counted against
+/// it.
+///
+/// Sends get under the limit in the order of their tickets, so that a send
+/// can't go out after a later one of the batch saw it cancelled. Every
+/// ticket handed out must be waited on with `acquire`, or the ones after it
+/// never get their turn.
+pub(crate) struct AdaptiveLimiter {
+    pub policy: AdaptiveConcurrency,
+    state: Mutex<State>,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(policy: AdaptiveConcurrency) -> Self {
+        let min = policy.min.max(1);
+        let max = policy.max.max(min);
+        AdaptiveLimiter {
+            state: Mutex::new(State {
+                limit: policy.initial.clamp(min, max) as f64,
+                in_flight: 0,
+                decreased_at: None,
+                next_ticket: 0,
+                serving: 0,
+                waiting: HashMap::new(),
+            }),
+            policy: AdaptiveConcurrency { min, max, ..policy },
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit as usize
+    }
+
+    /// A place in line for `acquire`, to be taken in the order of the sends.
+    pub fn ticket(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_ticket += 1;
+        state.next_ticket - 1
+    }
+
+    /// Wait until it is `ticket`'s turn and a send fits under the limit.
+    pub async fn acquire(&self, ticket: u64) -> Slot<'_> {
+        poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.serving != ticket || state.in_flight >= state.limit as usize {
+                state.waiting.insert(ticket, cx.waker().clone());
+                return Poll::Pending;
+            }
+            state.waiting.remove(&ticket);
+            state.in_flight += 1;
+            state.serving += 1;
+            // The next ticket may fit too, after the limit grew.
+            state.wake_next();
+            Poll::Ready(())
+        }).await;
+        Slot { limiter: self, started: Instant::now() }
+    }
+}
+
+/// A send counted against the limit until it is dropped.
+pub(crate) struct Slot<'a> {
+    limiter: &'a AdaptiveLimiter,
+    started: Instant,
+}
+
+impl Slot<'_> {
+    /// Adapt the limit to how the send went.
+    pub fn record(&self, result: &Result<ApnsId, SendError>) {
+        let policy = &self.limiter.policy;
+        let slow = self.started.elapsed() > policy.latency_target;
+        let mut state = self.limiter.state.lock().unwrap();
+        match result {
+            Err(e) if is_congested(e) => state.decrease(policy),
+            _ if slow => state.decrease(policy),
+            Ok(_) => {
+                state.limit = (state.limit + 1.0 / state.limit).min(policy.max as f64);
+                state.wake_next();
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.wake_next();
+    }
+}
+
+impl State {
+    /// Wake the ticket whose turn it is, if it is waiting and fits.
+    fn wake_next(&mut self) {
+        if self.in_flight < self.limit as usize {
+            if let Some(waker) = self.waiting.remove(&self.serving) {
+                waker.wake();
+            }
+        }
+    }
+
+    fn decrease(&mut self, policy: &AdaptiveConcurrency) {
+        let now = Instant::now();
+        if self.decreased_at.is_some_and(|at| now.duration_since(at) < policy.latency_target) {
+            return;
+        }
+        self.limit = (self.limit * policy.decrease).max(policy.min as f64);
+        self.decreased_at = Some(now);
+    }
+}
+
+/// Whether `e` means that APNS or the way to it is overloaded.
+fn is_congested(e: &SendError) -> bool {
+    match e {
+        SendError::Transport(_) => true,
+        SendError::Retried(history) => is_congested(history.last_error()),
+        _ => e.as_api_error().is_some_and(|e| e.status == 429 || e.status >= 500),
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+use hyper::{client::connect::Connection, service::Service, Uri};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::adaptive::{AdaptiveConcurrency, AdaptiveLimiter};
+use crate::send_rate::{SendRate, SendRateLimiter};
+use crate::types;
+use crate::{ApnsId, ApplePushClient, Notification, NotificationBuilder, Payload, SendError, ValidationError};
+
+
+/// A handle for aborting a batch send that is in progress.
+/// Clones share the same state, so one can be handed to an operator while the
+/// batch runs elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the batch. Notifications already sent are unaffected; the rest
+    /// complete with `SendError::Cancelled` without being sent.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Where an interrupted batch send stopped.
+/// Passing it to `SendOptions::resume_from` along with the same device tokens
+/// skips every token that was already attempted, so no device gets the
+/// notification twice.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ResumeToken {
+    position: usize,
+}
+
+impl ResumeToken {
+    /// The number of device tokens, from the start of the batch, that were
+    /// already attempted.
+    pub fn position(self) -> usize {
+        self.position
+    }
+}
+
+type ProgressFn = dyn FnMut(usize, usize, &Result<ApnsId, SendError>) + Send;
+
+/// A callback told of each result of a batch send. See
+/// `SendOptions::on_progress`.
+#[derive(Clone)]
+struct Progress(Arc<Mutex<ProgressFn>>);
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Progress(..)")
+    }
+}
+
+/// Options for a batch send.
+#[derive(Clone, Debug, Default)]
+pub struct SendOptions {
+    cancellation: Option<CancellationToken>,
+    resume_from: Option<ResumeToken>,
+    concurrency: Option<usize>,
+    adaptive: Option<AdaptiveConcurrency>,
+    pace: Option<f64>,
+    progress: Option<Progress>,
+    deduplicate: bool,
+}
+
+impl SendOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the batch to be aborted through `token`.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Continue an interrupted batch, skipping the device tokens it already
+    /// attempted.
+    pub fn resume_from(mut self, token: ResumeToken) -> Self {
+        self.resume_from = Some(token);
+        self
+    }
+
+    /// Send up to `concurrency` notifications of the batch at once, as
+    /// concurrent streams of the HTTP/2 connection. Results are still
+    /// reported in the order of the device tokens. Defaults to 1.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    /// Adapt the number of notifications of the batch sent at once to how
+    /// APNS keeps up, within the bounds of `policy`, rather than sending
+    /// the number set with `concurrency`. See
+    /// `BatchStream::concurrency` for the number it settled on.
+    pub fn adaptive_concurrency(mut self, policy: AdaptiveConcurrency) -> Self {
+        self.adaptive = Some(policy);
+        self
+    }
+
+    /// Send only once to each device token, skipping the tokens that
+    /// appear in the batch again, as they do in tables with duplicate
+    /// registrations. The number skipped is reported by
+    /// `BatchStream::duplicates_skipped`. Remembers every token of the
+    /// batch while it runs.
+    ///
+    /// Resume tokens count the tokens without the duplicates, so resume a
+    /// deduplicated batch with deduplication too.
+    pub fn deduplicate(mut self) -> Self {
+        self.deduplicate = true;
+        self
+    }
+
+    /// Start at most `per_second` sends of the batch a second, evenly
+    /// spaced, so that a large batch is spread over minutes rather than
+    /// having every device open the app at once. Unpaced by default.
+    ///
+    /// # Panics
+    ///
+    /// If `per_second` isn't a positive number.
+    ///
+    /// ```should_panic
+    /// apple_push::SendOptions::new().pace(0.0);
+    /// ```
+    pub fn pace(mut self, per_second: f64) -> Self {
+        assert!(per_second.is_finite() && per_second > 0.0, "pace must be a positive number of sends per second, not {}", per_second);
+        self.pace = Some(per_second);
+        self
+    }
+
+    /// Call `progress` with the number of device tokens done so far, the
+    /// number in the batch and the result for each device token as it is
+    /// reported, for progress bars and dashboards of long batches. Tokens
+    /// skipped by `resume_from` count as done.
+    ///
+    /// The number in the batch is what the iterator of device tokens says
+    /// it holds, which is exact for collections; for iterators that can't
+    /// tell, it is no less than the number done. Duplicates skipped by
+    /// `deduplicate` are counted in it, but never done.
+    pub fn on_progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(usize, usize, &Result<ApnsId, SendError>) + Send + 'static,
+    {
+        self.progress = Some(Progress(Arc::new(Mutex::new(progress))));
+        self
+    }
+
+    /// A token bucket without bursts, which spaces the sends evenly.
+    fn pacer(&self) -> Option<SendRateLimiter> {
+        self.pace.map(|per_second| SendRateLimiter::new(SendRate::new(per_second, 1)))
+    }
+
+    /// The adaptive limit, if enabled, and the number of sends to keep
+    /// waiting at once: as many as either limit allows.
+    fn limits(&self, concurrency: usize) -> (Option<Arc<AdaptiveLimiter>>, usize) {
+        match self.adaptive {
+            Some(ref policy) => {
+                let limiter = AdaptiveLimiter::new(policy.clone());
+                let max = limiter.policy.max;
+                (Some(Arc::new(limiter)), max)
+            }
+            None => (None, concurrency),
+        }
+    }
+}
+
+/// The stream of results of a batch send, one per device token in order.
+pub struct BatchStream<St> {
+    inner: Pin<Box<St>>,
+    position: usize,
+    interrupted: bool,
+    done: usize,
+    total: usize,
+    progress: Option<Progress>,
+    limiter: Option<Arc<AdaptiveLimiter>>,
+    duplicates: Arc<AtomicUsize>,
+    last_latency: Option<Duration>,
+}
+
+impl<St> BatchStream<St> {
+    fn new(inner: St, skip: usize, total: usize, opts: &SendOptions, limiter: Option<Arc<AdaptiveLimiter>>, duplicates: Arc<AtomicUsize>) -> Self {
+        BatchStream {
+            inner: Box::pin(inner),
+            position: skip,
+            interrupted: false,
+            done: skip,
+            total,
+            progress: opts.progress.clone(),
+            limiter,
+            duplicates,
+            last_latency: None,
+        }
+    }
+
+    /// How long the send of the last result took, from when it went out
+    /// after any pacing and adaptive limit, rather than from when the batch
+    /// started. Next to nothing for cancelled sends.
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.last_latency
+    }
+
+    /// The number of device tokens skipped so far for having been sent to
+    /// already. See `SendOptions::deduplicate`.
+    pub fn duplicates_skipped(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    /// The number of notifications currently sent at once, if the batch
+    /// adapts it. See `SendOptions::adaptive_concurrency`.
+    pub fn concurrency(&self) -> Option<usize> {
+        self.limiter.as_ref().map(|limiter| limiter.limit())
+    }
+
+    /// Where to resume the batch, if it was cancelled before every device
+    /// token was attempted. Only meaningful once the stream has ended.
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        if self.interrupted {
+            Some(ResumeToken { position: self.position })
+        } else {
+            None
+        }
+    }
+}
+
+impl<St> Stream for BatchStream<St>
+where
+    St: Stream<Item = (String, Result<ApnsId, SendError>, Duration)>,
+{
+    type Item = (String, Result<ApnsId, SendError>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let item = match self.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some((token, result, latency))) => {
+                self.last_latency = Some(latency);
+                Poll::Ready(Some((token, result)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        };
+        if let Poll::Ready(Some((_, ref result))) = item {
+            match result {
+                Err(e) if e.is_cancelled() => self.interrupted = true,
+                _ if !self.interrupted => self.position += 1,
+                _ => (),
+            }
+            self.done += 1;
+            self.total = self.total.max(self.done);
+            if let Some(ref progress) = self.progress {
+                (progress.0.lock().unwrap())(self.done, self.total, result);
+            }
+        }
+        item
+    }
+}
+
+/// The collected results of a batch send.
+#[derive(Debug)]
+pub struct BatchReport {
+    /// The result for each device token, in the order the tokens were given.
+    /// Tokens skipped by `SendOptions::resume_from` are not included.
+    pub results: Vec<(String, Result<ApnsId, SendError>)>,
+    /// Where to resume the batch, if it was cancelled.
+    pub resume_token: Option<ResumeToken>,
+    /// How long the send of each result took, in the order of `results`.
+    /// See `BatchStream::last_latency`.
+    pub latencies: Vec<Duration>,
+    /// See `BatchStream::duplicates_skipped`.
+    pub duplicates_skipped: usize,
+    /// The device tokens `ApplePushClient::send_batch_checked` didn't send
+    /// to, in order, and why.
+    pub rejected: Vec<(String, ValidationError)>,
+}
+
+impl BatchReport {
+    /// Wait for every result of a batch send.
+    pub async fn collect<St>(mut batch: BatchStream<St>) -> Self
+    where
+        St: Stream<Item = (String, Result<ApnsId, SendError>, Duration)>,
+    {
+        let mut results = Vec::new();
+        let mut latencies = Vec::new();
+        while let Some(result) = batch.next().await {
+            results.push(result);
+            latencies.extend(batch.last_latency());
+        }
+        BatchReport {
+            results,
+            latencies,
+            resume_token: batch.resume_token(),
+            duplicates_skipped: batch.duplicates_skipped(),
+            rejected: Vec::new(),
+        }
+    }
+
+    /// The number of notifications that were accepted by APNS.
+    pub fn sent(&self) -> usize {
+        self.results.iter().filter(|(_, result)| result.is_ok()).count()
+    }
+
+    /// The number of notifications that were attempted but failed.
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|(_, result)| matches!(result, Err(e) if !e.is_cancelled())).count()
+    }
+
+    /// The number of notifications that were not sent because the batch was
+    /// cancelled.
+    pub fn cancelled(&self) -> usize {
+        self.results.iter().filter(|(_, result)| matches!(result, Err(e) if e.is_cancelled())).count()
+    }
+}
+
+/// What `ApplePushClient::send_with_follow_up` sends after an alert.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FollowUp {
+    /// A badge-only notification, setting the badge to the number, so that
+    /// the badge matches the app's count even if the alert is collapsed or
+    /// cleared.
+    Badge(u32),
+    /// A silent background notification, waking the app to sync its state.
+    Sync,
+}
+
+/// The results of `ApplePushClient::send_with_follow_up`.
+#[derive(Debug)]
+pub struct FollowUpReport {
+    pub alert: Result<ApnsId, SendError>,
+    /// The result of the follow-up, unless it wasn't sent because the alert
+    /// failed.
+    pub follow_up: Option<Result<ApnsId, SendError>>,
+}
+
+impl FollowUpReport {
+    /// Whether APNS accepted the alert and the follow-up.
+    pub fn is_ok(&self) -> bool {
+        self.error().is_none()
+    }
+
+    /// The first failure, of the alert or else of the follow-up.
+    pub fn error(&self) -> Option<&SendError> {
+        self.alert.as_ref().err().or_else(|| self.follow_up.as_ref()?.as_ref().err())
+    }
+}
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Send a copy of `template` to each of `tokens`, as many at once as
+    /// `SendOptions::concurrency` allows.
+    /// Returns a stream of the result for each device token, in order, so
+    /// failures such as `BadDeviceToken` can be matched to their token.
+    /// The device token and id of the template are ignored.
+    pub fn send_batch<'a, I>(&'a self, template: Notification, tokens: I, opts: SendOptions) -> BatchStream<impl Stream<Item = (String, Result<ApnsId, SendError>, Duration)> + 'a>
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        self.send_batch_stream(template, stream::iter(tokens), opts)
+    }
+
+    /// Like `send_batch`, taking the device tokens from a stream, such as
+    /// the rows of a database cursor, so that they needn't all be in memory
+    /// at once. Only as many tokens are taken from `tokens` as are being
+    /// sent.
+    ///
+    /// ```no_run
+    /// # async fn run(client: apple_push::ApplePushClient<hyper::client::HttpConnector>, template: apple_push::Notification) {
+    /// use futures::stream::{self, StreamExt};
+    /// use apple_push::SendOptions;
+    ///
+    /// let tokens = stream::iter(0..1_000_000).map(|i| format!("{:064x}", i));
+    /// let mut results = client.send_batch_stream(template, tokens, SendOptions::new().concurrency(100));
+    /// while let Some((token, result)) = results.next().await {
+    ///     println!("{}: {:?}", token, result);
+    /// }
+    /// # }
+    /// ```
+    pub fn send_batch_stream<'a, St>(&'a self, template: Notification, tokens: St, opts: SendOptions) -> BatchStream<impl Stream<Item = (String, Result<ApnsId, SendError>, Duration)> + 'a>
+    where
+        St: Stream<Item = String> + 'a,
+    {
+        let skip = opts.resume_from.map_or(0, ResumeToken::position);
+        let (limiter, concurrency) = opts.limits(opts.concurrency.unwrap_or(1));
+        let pacer = opts.pacer();
+        let total = tokens.size_hint().0.max(skip);
+        let duplicates = Arc::default();
+        let tokens = deduplicated(tokens, opts.deduplicate, &duplicates).skip(skip);
+        let stream_opts = opts.clone();
+        let adaptive = limiter.clone();
+        let results = tokens.map(move |token| {
+            let n = template.for_token(&token);
+            let wait = pacer.as_ref().and_then(SendRateLimiter::take);
+            let turn = adaptive.as_ref().map(|limiter| (limiter.clone(), limiter.ticket()));
+            self.send_batched(n, token, wait, opts.cancellation.clone(), turn)
+        }).buffered(concurrency);
+        BatchStream::new(results, skip, total, &stream_opts, limiter, duplicates)
+    }
+
+    /// Like `send_batch`, waiting for every result.
+    ///
+    /// All of the sends run within the returned future rather than in
+    /// spawned tasks, so none outlive the caller: dropping the future, e.g.
+    /// because the request handler it runs in was cancelled, cancels every
+    /// send still in flight.
+    pub async fn send_batch_scoped<I>(&self, template: Notification, tokens: I, opts: SendOptions) -> BatchReport
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let skip = opts.resume_from.map_or(0, ResumeToken::position);
+        let (limiter, concurrency) = opts.limits(opts.concurrency.unwrap_or(1));
+        let pacer = opts.pacer();
+        let tokens = tokens.into_iter();
+        let total = tokens.size_hint().0.max(skip);
+        let duplicates = Arc::default();
+        let tokens = deduplicated(stream::iter(tokens), opts.deduplicate, &duplicates).skip(skip);
+        let results = tokens.map(|token| {
+            let n = template.for_token(&token);
+            let wait = pacer.as_ref().and_then(SendRateLimiter::take);
+            let turn = limiter.as_ref().map(|limiter| (limiter.clone(), limiter.ticket()));
+            self.send_batched(n, token, wait, opts.cancellation.clone(), turn)
+        }).buffered(concurrency);
+        BatchReport::collect(BatchStream::new(results, skip, total, &opts, limiter.clone(), duplicates)).await
+    }
+
+    /// Like `send_batch_scoped`, checking the notification for each of
+    /// `tokens` with `ApplePushClient::check` before sending any, and
+    /// sending only to the tokens that pass. The report lists the others
+    /// in `BatchReport::rejected`, rather than them failing one by one as
+    /// they come up.
+    ///
+    /// Resume tokens count only the tokens that passed.
+    pub async fn send_batch_checked<I>(&self, template: Notification, tokens: I, opts: SendOptions) -> BatchReport
+    where
+        I: IntoIterator<Item = String>,
+    {
+        // The device token is all that differs between the notifications.
+        let content = self.check_content(&template);
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+        for token in tokens {
+            match content.clone().and_then(|()| types::validate_device_token(&token)) {
+                Ok(()) => valid.push(token),
+                Err(error) => rejected.push((token, error)),
+            }
+        }
+        BatchReport { rejected, ..self.send_batch_scoped(template, valid, opts).await }
+    }
+
+    /// Send `payload` to the device token under each of `topics` at once,
+    /// for one logical notification going to an app and its extensions,
+    /// e.g. `com.example.app` and `com.example.app.complication`.
+    /// Returns the result for each topic, in order.
+    pub async fn send_multi_topic(&self, device_token: &str, payload: Payload, topics: &[&str]) -> Vec<(String, Result<ApnsId, SendError>)> {
+        let template = Notification::new("", device_token, payload);
+        let sends = topics.iter().map(|&topic| {
+            let mut n = template.clone();
+            n.topic = topic.to_owned();
+            async move { (n.topic.clone(), self.send(n).await) }
+        });
+        future::join_all(sends).await
+    }
+
+    /// Send `alert`, then once APNS has accepted it, `follow_up` to the same
+    /// device token, topic and environment. The follow-up isn't sent if the
+    /// alert fails, so the badge doesn't change without the alert that
+    /// explains it. APNS doesn't promise to deliver the two in order.
+    ///
+    /// ```no_run
+    /// # async fn run(client: apple_push::ApplePushClient<hyper::client::HttpConnector>) {
+    /// use apple_push::{FollowUp, NotificationBuilder};
+    ///
+    /// let alert = NotificationBuilder::message("com.example.app", "00fc13adff785122", "New message", "Hi!").build();
+    /// let report = client.send_with_follow_up(alert, FollowUp::Badge(3)).await;
+    /// if let Some(error) = report.error() {
+    ///     eprintln!("{}", error);
+    /// }
+    /// # }
+    /// ```
+    pub async fn send_with_follow_up(&self, alert: Notification, follow_up: FollowUp) -> FollowUpReport {
+        let builder = match follow_up {
+            FollowUp::Badge(badge) => NotificationBuilder::badge_only(&alert.topic, &alert.device_token, badge),
+            FollowUp::Sync => NotificationBuilder::silent_refresh(&alert.topic, &alert.device_token),
+        };
+        let mut follow_up = builder.build();
+        follow_up.environment = alert.environment;
+        let alert = self.send(alert).await;
+        let follow_up = match alert {
+            Ok(_) => Some(self.send(follow_up).await),
+            Err(_) => None,
+        };
+        FollowUpReport { alert, follow_up }
+    }
+
+    /// Send after waiting `wait` for the pace of the batch and for the turn
+    /// of the ticket under its adaptive limit, unless it was cancelled by
+    /// then.
+    async fn send_batched(&self, n: Notification, token: String, wait: Option<Duration>, cancellation: Option<CancellationToken>, turn: Option<(Arc<AdaptiveLimiter>, u64)>) -> (String, Result<ApnsId, SendError>, Duration) {
+        if let Some(wait) = wait {
+            tokio::time::delay_for(wait).await;
+        }
+        let slot = match turn {
+            Some((ref limiter, ticket)) => Some(limiter.acquire(ticket).await),
+            None => None,
+        };
+        let started = Instant::now();
+        let result = if cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            Err(SendError::Cancelled)
+        } else {
+            let result = self.send(n).await;
+            if let Some(ref slot) = slot {
+                slot.record(&result);
+            }
+            result
+        };
+        (token, result, started.elapsed())
+    }
+}
+
+/// `tokens`, without the ones seen before if `enabled`, counting those in
+/// `duplicates`.
+fn deduplicated<St>(tokens: St, enabled: bool, duplicates: &Arc<AtomicUsize>) -> impl Stream<Item = String>
+where
+    St: Stream<Item = String>,
+{
+    let mut seen = if enabled { Some(HashSet::new()) } else { None };
+    let duplicates = duplicates.clone();
+    tokens.filter(move |token| {
+        let first = match seen {
+            Some(ref mut seen) => seen.insert(token.clone()),
+            None => true,
+        };
+        if !first {
+            duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+        future::ready(first)
+    })
+}
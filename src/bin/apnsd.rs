@@ -0,0 +1,254 @@
+//! A daemon that keeps connections to APNS open and sends notifications on
+//! behalf of other processes, so that short-lived workers don't pay for a
+//! TLS handshake and a new provider token on every push.
+//!
+//! Configured from the environment:
+//!
+//! * `APNS_TEAM_ID`, `APNS_KEY_ID` and `APNS_KEY_PATH` (the `.p8` file)
+//! * `APNSD_SOCKET`: the Unix socket to listen on, `/tmp/apnsd.sock` by default
+//! * `APNSD_SANDBOX`: set to send through the development environment
+//!
+//! Clients connect to the socket and write one notification per line, as the
+//! JSON serialization of `Notification`. Each is answered, in order, with a
+//! line holding either `{"id":"<apns-id>"}` or
+//! `{"error":"<message>","status":<status>,"reason":"<reason>"}`, where
+//! `status` and `reason` are only present for errors returned by APNS.
+//!
+//! `apnsd doctor` instead checks, in parallel, what has to work for
+//! notifications to arrive, prints a report and exits with status 1 if
+//! anything failed: that the key signs a provider token, that both
+//! environments resolve and accept TLS connections on ports 443 and 2197,
+//! and, if `APNS_TOPIC` is set, that both accept the provider token for
+//! that topic, by sending them a background notification to a made-up
+//! device token.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use apple_push::consts::{ALTERNATIVE_PORT, DEVELOPMENT_HOST, PORT, PRODUCTION_HOST};
+use apple_push::{ApiErrorReason, ApnsId, ApplePushClient, Environment, Notification, NotificationBuilder, Priority, SendError, TokenSigner};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use hyper_tls::HttpsConnector;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+
+const DEFAULT_SOCKET: &str = "/tmp/apnsd.sock";
+
+/// How long each check of `apnsd doctor` may take.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A well-formed device token that no device has.
+const PROBE_TOKEN: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+type ApnsClient = ApplePushClient<HttpsConnector<HttpConnector>>;
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Reply {
+    Sent { id: ApnsId },
+    Failed {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+impl Reply {
+    fn error(error: &SendError) -> Self {
+        let api_error = error.as_api_error();
+        Reply::Failed {
+            error: error.to_string(),
+            status: api_error.map(|e| e.status),
+            reason: api_error.map(|e| e.reason.to_string()),
+        }
+    }
+}
+
+#[tokio::main(basic_scheduler)]
+async fn main() {
+    if env::args().nth(1).as_deref() == Some("doctor") {
+        let passed = doctor().await;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+    let client = match client_from_env() {
+        Ok(client) => Arc::new(client),
+        Err(e) => {
+            eprintln!("apnsd: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let path = env::var("APNSD_SOCKET").unwrap_or_else(|_| DEFAULT_SOCKET.to_owned());
+    // A socket left behind by a previous run would make bind fail.
+    let _ = fs::remove_file(&path);
+    let mut listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("apnsd: can't listen on {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve(client.clone(), stream));
+            }
+            Err(e) => eprintln!("apnsd: accept failed: {}", e),
+        }
+    }
+}
+
+fn client_from_env() -> Result<ApnsClient, apple_push::Error> {
+    let team_id = var("APNS_TEAM_ID")?;
+    let key_id = var("APNS_KEY_ID")?;
+    let key = apple_push::read_key_file(var("APNS_KEY_PATH")?)?;
+    let mut client = ApplePushClient::new_default(&team_id, &key_id, &key)?;
+    client.set_production(env::var_os("APNSD_SANDBOX").is_none());
+    Ok(client)
+}
+
+fn var(name: &str) -> Result<String, apple_push::Error> {
+    env::var(name).map_err(|_| format!("{} must be set", name).into())
+}
+
+/// The outcome of one check of `apnsd doctor`.
+struct Check {
+    name: String,
+    outcome: Result<String, String>,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.outcome {
+            Ok(ref detail) => write!(f, "ok    {}: {}", self.name, detail),
+            Err(ref problem) => write!(f, "FAIL  {}: {}", self.name, problem),
+        }
+    }
+}
+
+type Checking = Pin<Box<dyn Future<Output = Check>>>;
+
+/// Run every check at once and print a report, returning whether they all
+/// passed.
+async fn doctor() -> bool {
+    let mut checks: Vec<Checking> = vec![Box::pin(check_key())];
+    for &host in &[PRODUCTION_HOST, DEVELOPMENT_HOST] {
+        checks.push(Box::pin(check_dns(host)));
+        for &port in &[PORT, ALTERNATIVE_PORT] {
+            checks.push(Box::pin(check_tls(host, port)));
+        }
+    }
+    if let Ok(topic) = env::var("APNS_TOPIC") {
+        match client_from_env() {
+            Ok(client) => {
+                let client = Arc::new(client);
+                for &environment in &[Environment::Production, Environment::Sandbox] {
+                    checks.push(Box::pin(check_auth(client.clone(), topic.clone(), environment)));
+                }
+            }
+            Err(e) => println!("skip  provider token: {}", e),
+        }
+    } else {
+        println!("skip  provider token: set APNS_TOPIC to check that APNS accepts it");
+    }
+    let checks = futures::future::join_all(checks).await;
+    for check in &checks {
+        println!("{}", check);
+    }
+    checks.iter().all(|check| check.outcome.is_ok())
+}
+
+async fn check_key() -> Check {
+    let signed = (|| {
+        let signer = TokenSigner::from_key_file(&var("APNS_TEAM_ID")?, &var("APNS_KEY_ID")?, var("APNS_KEY_PATH")?)?;
+        signer.sign()
+    })();
+    Check {
+        name: "key".to_owned(),
+        outcome: signed
+            .map(|_| "signed a provider token".to_owned())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+async fn check_dns(host: &'static str) -> Check {
+    let outcome = match tokio::time::timeout(CHECK_TIMEOUT, tokio::net::lookup_host((host, PORT))).await {
+        Ok(Ok(addrs)) => {
+            let addrs: Vec<_> = addrs.map(|addr| addr.ip().to_string()).collect();
+            if addrs.is_empty() {
+                Err("no addresses".to_owned())
+            } else {
+                Ok(addrs.join(", "))
+            }
+        }
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no answer in {:?}", CHECK_TIMEOUT)),
+    };
+    Check { name: format!("dns {}", host), outcome }
+}
+
+/// Connect to `host` on `port` and complete a TLS handshake.
+async fn check_tls(host: &'static str, port: u16) -> Check {
+    let started = Instant::now();
+    let uri: Uri = format!("https://{}:{}/", host, port).parse().expect("host and port make a URI");
+    let outcome = match tokio::time::timeout(CHECK_TIMEOUT, HttpsConnector::new().call(uri)).await {
+        Ok(Ok(_)) => Ok(format!("connected in {} ms", started.elapsed().as_millis())),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("not connected in {:?}", CHECK_TIMEOUT)),
+    };
+    Check { name: format!("tls {}:{}", host, port), outcome }
+}
+
+/// Send a notification to a device token no device has: APNS checks the
+/// provider token and topic before the device token, so `BadDeviceToken`
+/// is the answer when everything else is right.
+async fn check_auth(client: Arc<ApnsClient>, topic: String, environment: Environment) -> Check {
+    let probe = NotificationBuilder::new(&topic, PROBE_TOKEN)
+        .content_available()
+        .priority(Priority::Low)
+        .environment(environment)
+        .build();
+    let outcome = match tokio::time::timeout(CHECK_TIMEOUT, client.send(probe)).await {
+        Err(_) => Err(format!("no response in {:?}", CHECK_TIMEOUT)),
+        Ok(Err(SendError::Api(ref e))) if matches!(e.reason, ApiErrorReason::BadDeviceToken) => {
+            Ok(format!("accepted for {}", topic))
+        }
+        Ok(Ok(_)) => Ok(format!("accepted for {}", topic)),
+        Ok(Err(e)) => Err(e.to_string()),
+    };
+    Check { name: format!("provider token {:?}", environment), outcome }
+}
+
+/// Send the notifications written to `stream`, one per line, replying to
+/// each before reading the next.
+async fn serve(client: Arc<ApnsClient>, stream: UnixStream) -> io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Notification>(&line) {
+            Ok(n) => match client.send(n).await {
+                Ok(id) => Reply::Sent { id },
+                Err(e) => Reply::error(&e),
+            },
+            Err(e) => Reply::Failed { error: format!("invalid notification: {}", e), status: None, reason: None },
+        };
+        let mut reply = serde_json::to_vec(&reply)?;
+        reply.push(b'\n');
+        writer.write_all(&reply).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,337 @@
+//! Bookkeeping for Live Activity broadcast channels.
+//!
+//! Apple limits how many channels an app may have open, and broadcasts are
+//! billed against the channel they're sent on, so `ChannelUsage` keeps a
+//! count of the broadcasts sent on each channel and when the last one went
+//! out, for auditing usage against those limits. It also refuses to record
+//! more channels for an app than Apple allows, so that the cap is hit
+//! locally rather than by a failing request.
+//!
+//! The bodies of the channel management API are modelled by
+//! `ChannelConfig`, which is sent to create a channel and returned when
+//! reading one, and `ChannelList`, returned when listing them. Deleting a
+//! channel sends its id in the `apns-channel-id` header, with no body.
+//!
+//! Channels are managed on one host and broadcast to on another, each with
+//! a production and a development variant on their own ports. `Endpoint`
+//! picks between them, so that switching a channel client to the sandbox is
+//! only a matter of passing `Environment::Sandbox`, as for device pushes:
+//!
+//! ```
+//! use apple_push::Environment;
+//! use apple_push::broadcast::Endpoint;
+//!
+//! assert_eq!(Endpoint::ChannelManagement.url(Environment::Sandbox), "https://api-manage-broadcast.sandbox.push.apple.com:2195");
+//! assert_eq!(Endpoint::Broadcast.url(Environment::Production), "https://api.push.apple.com");
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::consts::{
+    BROADCAST_PATH, CHANNELS_DEVELOPMENT_HOST, CHANNELS_DEVELOPMENT_PORT, CHANNELS_PRODUCTION_HOST,
+    CHANNELS_PRODUCTION_PORT, DEVELOPMENT_HOST, MAX_CHANNELS_PER_APP, PORT, PRODUCTION_HOST,
+};
+use crate::Environment;
+
+
+/// What APNS keeps of the broadcasts sent on a channel, for devices that
+/// were offline when they were sent.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum MessageStoragePolicy {
+    /// Broadcasts are only delivered to devices online when they're sent.
+    NoStorage,
+    /// The most recent broadcast is kept for devices that come online.
+    MostRecent,
+}
+
+impl From<MessageStoragePolicy> for u8 {
+    fn from(policy: MessageStoragePolicy) -> u8 {
+        match policy {
+            MessageStoragePolicy::NoStorage => 0,
+            MessageStoragePolicy::MostRecent => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for MessageStoragePolicy {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, String> {
+        match value {
+            0 => Ok(MessageStoragePolicy::NoStorage),
+            1 => Ok(MessageStoragePolicy::MostRecent),
+            _ => Err(format!("message-storage-policy {} isn't 0 or 1", value)),
+        }
+    }
+}
+
+/// The push types a channel can carry. Channels only carry Live Activity
+/// updates, so any other push type fails to deserialize.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelPushType {
+    LiveActivity,
+}
+
+/// The settings of a channel: the body of a request creating one, and of
+/// the response to reading one.
+///
+/// ```
+/// use apple_push::broadcast::{ChannelConfig, MessageStoragePolicy};
+///
+/// let config = ChannelConfig::new(MessageStoragePolicy::MostRecent);
+/// let body = serde_json::to_value(&config).unwrap();
+/// assert_eq!(body, serde_json::json!({ "message-storage-policy": 1, "push-type": "LiveActivity" }));
+/// assert_eq!(serde_json::from_value::<ChannelConfig>(body).unwrap(), config);
+///
+/// let alert = serde_json::json!({ "message-storage-policy": 0, "push-type": "alert" });
+/// assert!(serde_json::from_value::<ChannelConfig>(alert).is_err());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelConfig {
+    #[serde(rename = "message-storage-policy")]
+    pub message_storage_policy: MessageStoragePolicy,
+    #[serde(rename = "push-type")]
+    pub push_type: ChannelPushType,
+}
+
+impl ChannelConfig {
+    /// A Live Activity channel storing broadcasts according to `policy`.
+    pub fn new(policy: MessageStoragePolicy) -> Self {
+        ChannelConfig { message_storage_policy: policy, push_type: ChannelPushType::LiveActivity }
+    }
+}
+
+/// The body of the response to listing the channels of an app.
+///
+/// ```
+/// use apple_push::broadcast::ChannelList;
+///
+/// let list: ChannelList = serde_json::from_str(r#"{"channels":["dHN0LXU3","dHN0LXU4"]}"#).unwrap();
+/// assert_eq!(list.channels.len(), 2);
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelList {
+    pub channels: Vec<String>,
+}
+
+/// The hosts of the broadcast APIs, which differ by environment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Where channels are created, read, listed and deleted.
+    ChannelManagement,
+    /// Where broadcasts are sent on a channel.
+    Broadcast,
+}
+
+impl Endpoint {
+    pub fn host(self, environment: Environment) -> &'static str {
+        match (self, environment) {
+            (Endpoint::ChannelManagement, Environment::Production) => CHANNELS_PRODUCTION_HOST,
+            (Endpoint::ChannelManagement, Environment::Sandbox) => CHANNELS_DEVELOPMENT_HOST,
+            (Endpoint::Broadcast, Environment::Production) => PRODUCTION_HOST,
+            (Endpoint::Broadcast, Environment::Sandbox) => DEVELOPMENT_HOST,
+        }
+    }
+
+    pub fn port(self, environment: Environment) -> u16 {
+        match (self, environment) {
+            (Endpoint::ChannelManagement, Environment::Production) => CHANNELS_PRODUCTION_PORT,
+            (Endpoint::ChannelManagement, Environment::Sandbox) => CHANNELS_DEVELOPMENT_PORT,
+            (Endpoint::Broadcast, _) => PORT,
+        }
+    }
+
+    /// The base URL of the endpoint, with the port unless it is 443.
+    pub fn url(self, environment: Environment) -> String {
+        match self.port(environment) {
+            PORT => format!("https://{}", self.host(environment)),
+            port => format!("https://{}:{}", self.host(environment), port),
+        }
+    }
+}
+
+/// The URL that creates, reads and deletes the channels of `bundle_id`,
+/// with the channel id in the `apns-channel-id` header.
+pub fn channels_url(environment: Environment, bundle_id: &str) -> String {
+    format!("{}/1/apps/{}/channels", Endpoint::ChannelManagement.url(environment), bundle_id)
+}
+
+/// The URL that lists every channel of `bundle_id`.
+pub fn all_channels_url(environment: Environment, bundle_id: &str) -> String {
+    format!("{}/1/apps/{}/all-channels", Endpoint::ChannelManagement.url(environment), bundle_id)
+}
+
+/// The URL that broadcasts to a channel of `bundle_id`, with the channel
+/// id in the `apns-channel-id` header.
+///
+/// ```
+/// # use apple_push::Environment;
+/// let url = apple_push::broadcast::broadcast_url(Environment::Sandbox, "com.example.app");
+/// assert_eq!(url, "https://api.development.push.apple.com/4/broadcasts/apps/com.example.app");
+/// ```
+pub fn broadcast_url(environment: Environment, bundle_id: &str) -> String {
+    format!("{}{}{}", Endpoint::Broadcast.url(environment), BROADCAST_PATH, bundle_id)
+}
+
+/// What has been sent on a broadcast channel.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    /// The bundle id of the app the channel belongs to.
+    pub bundle_id: String,
+    /// When the channel was first recorded.
+    pub created_at: SystemTime,
+    /// How many broadcasts have been sent on the channel.
+    pub sends: u64,
+    /// When the last broadcast was sent, if any has been.
+    pub last_sent: Option<SystemTime>,
+}
+
+/// A channel that wasn't recorded because its app already has as many
+/// channels as the limit allows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelLimitExceeded {
+    pub bundle_id: String,
+    pub open: usize,
+    pub limit: usize,
+}
+
+impl ::std::fmt::Display for ChannelLimitExceeded {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{} already has {} channels (the limit is {})", self.bundle_id, self.open, self.limit)
+    }
+}
+
+impl ::std::error::Error for ChannelLimitExceeded {}
+
+/// Per-channel send counters, shared by everything sending broadcasts.
+pub struct ChannelUsage {
+    limit: Option<usize>,
+    channels: Mutex<HashMap<String, ChannelStats>>,
+}
+
+impl Default for ChannelUsage {
+    fn default() -> Self {
+        ChannelUsage {
+            limit: Some(MAX_CHANNELS_PER_APP),
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ChannelUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how many channels each app may have. Defaults to
+    /// `MAX_CHANNELS_PER_APP`; raise it if Apple granted an app more, or
+    /// remove it with `None`.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    /// How many more channels can be created for `bundle_id`, or `None` if
+    /// there is no limit.
+    pub fn remaining(&self, bundle_id: &str) -> Option<usize> {
+        let open = self.open(&self.channels.lock().unwrap(), bundle_id);
+        self.limit.map(|limit| limit.saturating_sub(open))
+    }
+
+    /// Check that a channel can be created for `bundle_id`, before asking
+    /// APNS to create it.
+    pub fn check_create(&self, bundle_id: &str) -> Result<(), ChannelLimitExceeded> {
+        self.check(&self.channels.lock().unwrap(), bundle_id)
+    }
+
+    /// Record a channel created for `bundle_id`, and return how many more
+    /// can be created. Recording a channel that is already known keeps its
+    /// counters.
+    ///
+    /// ```
+    /// use apple_push::broadcast::ChannelUsage;
+    ///
+    /// let mut usage = ChannelUsage::new();
+    /// usage.set_limit(Some(1));
+    /// assert_eq!(usage.record_created("com.example.app", "dHN0LXU3").unwrap(), Some(0));
+    /// assert!(usage.record_created("com.example.app", "dHN0LXU4").is_err());
+    /// ```
+    pub fn record_created(&self, bundle_id: &str, channel_id: &str) -> Result<Option<usize>, ChannelLimitExceeded> {
+        let mut channels = self.channels.lock().unwrap();
+        if !channels.contains_key(channel_id) {
+            self.check(&channels, bundle_id)?;
+            channels.insert(channel_id.into(), ChannelStats {
+                bundle_id: bundle_id.into(),
+                created_at: SystemTime::now(),
+                sends: 0,
+                last_sent: None,
+            });
+        }
+        let open = self.open(&channels, bundle_id);
+        Ok(self.limit.map(|limit| limit.saturating_sub(open)))
+    }
+
+    fn open(&self, channels: &HashMap<String, ChannelStats>, bundle_id: &str) -> usize {
+        channels.values().filter(|stats| stats.bundle_id == bundle_id).count()
+    }
+
+    fn check(&self, channels: &HashMap<String, ChannelStats>, bundle_id: &str) -> Result<(), ChannelLimitExceeded> {
+        let open = self.open(channels, bundle_id);
+        match self.limit {
+            Some(limit) if open >= limit => Err(ChannelLimitExceeded { bundle_id: bundle_id.into(), open, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a broadcast sent on a channel of `bundle_id`, and return how
+    /// many have now been sent on it. Channels that weren't recorded as
+    /// created are recorded now, even over the limit, since APNS evidently
+    /// has them open.
+    ///
+    /// ```
+    /// use apple_push::broadcast::ChannelUsage;
+    ///
+    /// let usage = ChannelUsage::new();
+    /// usage.record_send("com.example.app", "dHN0LXU3");
+    /// assert_eq!(usage.record_send("com.example.app", "dHN0LXU3"), 2);
+    /// assert!(usage.stats("dHN0LXU3").unwrap().last_sent.is_some());
+    /// ```
+    pub fn record_send(&self, bundle_id: &str, channel_id: &str) -> u64 {
+        let now = SystemTime::now();
+        let mut channels = self.channels.lock().unwrap();
+        let stats = channels.entry(channel_id.into()).or_insert_with(|| ChannelStats {
+            bundle_id: bundle_id.into(),
+            created_at: now,
+            sends: 0,
+            last_sent: None,
+        });
+        stats.sends += 1;
+        stats.last_sent = Some(now);
+        stats.sends
+    }
+
+    /// Forget a deleted channel, returning what was sent on it.
+    pub fn remove(&self, channel_id: &str) -> Option<ChannelStats> {
+        self.channels.lock().unwrap().remove(channel_id)
+    }
+
+    /// What has been sent on a channel, if it is known.
+    pub fn stats(&self, channel_id: &str) -> Option<ChannelStats> {
+        self.channels.lock().unwrap().get(channel_id).cloned()
+    }
+
+    /// The known channels of `bundle_id`, by channel id.
+    pub fn channels(&self, bundle_id: &str) -> Vec<(String, ChannelStats)> {
+        let mut channels: Vec<_> = self.channels.lock().unwrap().iter()
+            .filter(|(_, stats)| stats.bundle_id == bundle_id)
+            .map(|(id, stats)| (id.clone(), stats.clone()))
+            .collect();
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+        channels
+    }
+}
@@ -0,0 +1,181 @@
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "client")]
+use std::sync::Mutex;
+#[cfg(feature = "client")]
+use std::time::Instant;
+
+use crate::Error;
+
+#[cfg(feature = "client")]
+use crate::events::Event;
+#[cfg(feature = "client")]
+use crate::health::Health;
+
+
+/// An APNS client certificate, for keeping an eye on when it expires.
+///
+/// Certificates stop working silently the moment they expire; see
+/// `ApplePushClient::set_certificate_expiry_warning` to be warned through
+/// the event bus in advance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate {
+    not_after: SystemTime,
+}
+
+impl Certificate {
+    /// Parse a DER encoded X.509 certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let bad = || "malformed certificate";
+        let (certificate, _) = element(der, SEQUENCE).ok_or_else(bad)?;
+        let (mut tbs, _) = element(certificate, SEQUENCE).ok_or_else(bad)?;
+        // The version is optional, and explicitly tagged.
+        if tbs.first() == Some(&0xa0) {
+            tbs = element(tbs, 0xa0).ok_or_else(bad)?.1;
+        }
+        // Skip the serial number, signature algorithm and issuer.
+        for &tag in &[INTEGER, SEQUENCE, SEQUENCE] {
+            tbs = element(tbs, tag).ok_or_else(bad)?.1;
+        }
+        let (validity, _) = element(tbs, SEQUENCE).ok_or_else(bad)?;
+        let rest = skip(validity).ok_or_else(bad)?;
+        let not_after = time(rest).ok_or_else(bad)?;
+        Ok(Certificate { not_after })
+    }
+
+    /// Parse the first certificate in a PEM file.
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        let body: String = pem.lines()
+            .skip_while(|line| !line.starts_with("-----BEGIN CERTIFICATE"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("-----END"))
+            .collect();
+        if body.is_empty() {
+            return Err("no certificate in PEM".into());
+        }
+        Self::from_der(&base64::decode(body.trim())?)
+    }
+
+    /// When the certificate stops being valid.
+    pub fn expires_at(&self) -> SystemTime {
+        self.not_after
+    }
+
+    /// How long until the certificate expires, zero if it already has.
+    pub fn expires_in(&self) -> Duration {
+        self.not_after.duration_since(SystemTime::now()).unwrap_or_default()
+    }
+}
+
+const INTEGER: u8 = 0x02;
+const SEQUENCE: u8 = 0x30;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+
+/// Split the DER element with `tag` at the start of `input` into its
+/// contents and what follows it.
+fn element(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *input.first()? != tag {
+        return None;
+    }
+    let (len, header) = match *input.get(1)? {
+        len if len < 0x80 => (len as usize, 2),
+        0x81..=0x84 => {
+            let octets = (input[1] & 0x7f) as usize;
+            let len = input.get(2..2 + octets)?.iter().fold(0, |len, &b| len << 8 | b as usize);
+            (len, 2 + octets)
+        }
+        _ => return None,
+    };
+    let end = header.checked_add(len)?;
+    Some((input.get(header..end)?, &input[end..]))
+}
+
+/// What follows the element at the start of `input`, whatever its tag.
+fn skip(input: &[u8]) -> Option<&[u8]> {
+    element(input, *input.first()?).map(|(_, rest)| rest)
+}
+
+/// Parse a UTCTime or GeneralizedTime, which are UTC in certificates.
+fn time(input: &[u8]) -> Option<SystemTime> {
+    let (year, rest) = match *input.first()? {
+        UTC_TIME => {
+            let (digits, _) = element(input, UTC_TIME)?;
+            let year = number(digits.get(..2)?)?;
+            (if year < 50 { 2000 + year } else { 1900 + year }, digits.get(2..)?)
+        }
+        GENERALIZED_TIME => {
+            let (digits, _) = element(input, GENERALIZED_TIME)?;
+            (number(digits.get(..4)?)?, digits.get(4..)?)
+        }
+        _ => return None,
+    };
+    let field = |i: usize| rest.get(i * 2..i * 2 + 2).and_then(number);
+    let days = days_from_civil(year as i64, field(0)?, field(1)?);
+    let seconds = days * 86400 + (field(2)? * 3600 + field(3)? * 60 + field(4)?) as i64;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+fn number(digits: &[u8]) -> Option<u32> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// How often the expiry warning is repeated while the certificate is within
+/// the warning window.
+#[cfg(feature = "client")]
+const WARNING_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Warns of a certificate expiring within `within`.
+#[cfg(feature = "client")]
+pub(crate) struct ExpiryMonitor {
+    certificate: Certificate,
+    within: Duration,
+    last_warned: Mutex<Option<Instant>>,
+}
+
+#[cfg(feature = "client")]
+impl ExpiryMonitor {
+    pub fn new(certificate: Certificate, within: Duration) -> Self {
+        ExpiryMonitor { certificate, within, last_warned: Mutex::new(None) }
+    }
+
+    /// The health of the certificate: unhealthy once it expired, degraded
+    /// within the window.
+    pub fn health(&self) -> Health {
+        let expires_in = self.certificate.expires_in();
+        if expires_in == Duration::from_secs(0) {
+            Health::Unhealthy("the client certificate expired".to_owned())
+        } else if expires_in <= self.within {
+            Health::Degraded(format!("the client certificate expires in {} hours", expires_in.as_secs() / 3600))
+        } else {
+            Health::Ok
+        }
+    }
+
+    /// The warning to emit now, if the certificate is within the window and
+    /// wasn't warned about recently.
+    pub fn check(&self) -> Option<Event> {
+        let expires_in = self.certificate.expires_in();
+        if expires_in > self.within {
+            return None;
+        }
+        let mut last_warned = self.last_warned.lock().unwrap();
+        if last_warned.is_some_and(|at| at.elapsed() < WARNING_INTERVAL) {
+            return None;
+        }
+        *last_warned = Some(Instant::now());
+        Some(Event::CertificateExpiring { expires_at: self.certificate.expires_at(), expires_in })
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use bytes::Bytes;
+use hyper::Response;
+
+use crate::error::{ApiError, ErrorResponse};
+use crate::rng::{self, Rng};
+
+
+/// Failures to inject into sends, for testing how an application copes with
+/// them. Rates are fractions of requests between 0 and 1, rolled
+/// independently for each request.
+///
+/// Only available with the `chaos` feature; not meant for production.
+#[derive(Clone, Debug, Default)]
+pub struct ChaosPolicy {
+    /// Fraction of requests delayed by `delay` before being sent.
+    pub delay_rate: f64,
+    pub delay: Duration,
+    /// Fraction of requests that aren't sent, and instead get one of
+    /// `failures`, chosen at random, as the response from APNS.
+    pub failure_rate: f64,
+    pub failures: Vec<ApiError>,
+    /// Fraction of requests that fail as though the connection was reset.
+    /// With the h2 transport the connection is really dropped.
+    pub reset_rate: f64,
+}
+
+/// What the chaos layer did to a request.
+pub(crate) enum Injected {
+    Nothing,
+    Reset,
+    Response(Response<Bytes>),
+}
+
+impl ChaosPolicy {
+    /// Roll for each kind of failure, waiting out any injected delay.
+    pub(crate) async fn inject(&self, rng: &dyn Rng) -> Injected {
+        if roll(self.delay_rate, rng) {
+            tokio::time::delay_for(self.delay).await;
+        }
+        if roll(self.reset_rate, rng) {
+            return Injected::Reset;
+        }
+        if !self.failures.is_empty() && roll(self.failure_rate, rng) {
+            let failure = &self.failures[rng::below(rng, self.failures.len() as u64 - 1) as usize];
+            let body = ErrorResponse {
+                reason: failure.reason.to_string(),
+                timestamp: failure.timestamp
+                    .and_then(|timestamp| timestamp.duration_since(UNIX_EPOCH).ok())
+                    .map(|since| since.as_millis() as u64),
+            };
+            let response = Response::builder()
+                .status(failure.status as u16)
+                .body(serde_json::to_vec(&body).unwrap_or_default().into());
+            if let Ok(response) = response {
+                return Injected::Response(response);
+            }
+        }
+        Injected::Nothing
+    }
+}
+
+/// Whether an event happening at `rate` happens this time.
+fn roll(rate: f64, rng: &dyn Rng) -> bool {
+    rate > 0.0 && rng::fraction(rng) < rate
+}
@@ -0,0 +1,1140 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::Error;
+use hyper::{Body, client::connect::Connection, Client, Request, service::Service, StatusCode, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "chaos")]
+use crate::chaos::{ChaosPolicy, Injected};
+use crate::certificate::{Certificate, ExpiryMonitor};
+use crate::health::{Health, HealthTracker};
+use crate::credentials::{RevocationTracker, DEFAULT_REVOCATION_THRESHOLD};
+use crate::consts;
+use crate::closing::Closer;
+use crate::queues::{Queue, QueueStats};
+use crate::config::ClientConfig;
+#[cfg(feature = "unstable-correlation")]
+use crate::correlation::CorrelationStore;
+use crate::defaults::TopicDefaults;
+use crate::error::{ApiError, ApiErrorReason, CloseReason, ErrorResponse, ReasonClass, SendError, ValidationError};
+use crate::events::{Event, EventBus};
+use crate::expiration::ExpirationPolicy;
+use crate::fire;
+#[cfg(feature = "h2")]
+use crate::h2_transport::{H2Options, H2Transport};
+use crate::id::{self, ApnsId};
+use crate::limits::PayloadLimits;
+use crate::localize::{self, Localizer};
+use crate::memory::{MemoryBudget, Reservation};
+use crate::metrics::{self, names, Metrics, Tags, TopicTagger};
+use crate::middleware::{PayloadMiddleware, RequestMiddleware, ResponseMiddleware};
+use crate::policy::{Policy, Verdict};
+#[cfg(feature = "unstable-payload-signing")]
+use crate::payload_signing::{self, PayloadSigner};
+use crate::retry::{AttemptHistory, AttemptRecord, RetryPolicy};
+use crate::retry_budget::{Budget, RetryBudget};
+use crate::rng::{Rng, SystemRng};
+use crate::sanitize::SanitizePolicy;
+use crate::compat::Compatibility;
+use crate::tasks::TaskSet;
+use crate::signing::{SigningKey, SigningKeys, TokenInfo};
+use crate::token_cache::{self, TokenCache, GUIDED_SIGNINGS_PER_HOUR};
+use crate::pem::{pkcs8_from_pem, read_key_file};
+use crate::rate_limit::RateLimiter;
+use crate::throttle::{Throttle, ThrottlePolicy};
+use crate::send_rate::{SendRate, SendRateLimiter};
+use crate::timings::{SendResponse, Stopwatch, Timings};
+use crate::transport::Transport;
+use crate::trim::TrimPolicy;
+use crate::types::{ApnsRequest, Environment, Notification};
+
+
+/// APNS responses are small JSON objects; anything much longer didn't come
+/// from APNS.
+const DEFAULT_RESPONSE_BODY_LIMIT: usize = 16 * 1024;
+
+/// See `ApplePushClient::set_reason_classifier`.
+type ReasonClassifier = dyn Fn(&str, u32) -> Option<ReasonClass> + Send + Sync;
+
+pub struct ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    production: bool,
+    sandbox_fallback: bool,
+    trim_policy: TrimPolicy,
+    payload_limits: PayloadLimits,
+    sanitize_policy: SanitizePolicy,
+    expiration_policy: ExpirationPolicy,
+    compatibility: Option<Compatibility>,
+    localizers: HashMap<String, Arc<dyn Localizer>>,
+    topic_defaults: HashMap<String, TopicDefaults>,
+    payload_middleware: Vec<Arc<dyn PayloadMiddleware>>,
+    request_middleware: Vec<Arc<dyn RequestMiddleware>>,
+    response_middleware: Vec<Arc<dyn ResponseMiddleware>>,
+    policies: Vec<Arc<dyn Policy>>,
+    reason_classifier: Option<Arc<ReasonClassifier>>,
+    throttle: Option<Throttle>,
+    rate_limiter: Option<Arc<dyn RateLimiter>>,
+    #[cfg(feature = "unstable-correlation")]
+    correlation_store: Option<Arc<dyn CorrelationStore>>,
+    #[cfg(feature = "unstable-payload-signing")]
+    payload_signer: Option<Arc<dyn PayloadSigner>>,
+    send_rate: Option<SendRateLimiter>,
+    certificate: Option<ExpiryMonitor>,
+    revocation: RevocationTracker,
+    health: HealthTracker,
+    retry_policy: Option<RetryPolicy>,
+    retry_budget: Option<Budget>,
+    transport: Transport<S>,
+    team_id: String,
+    /// `None` for clients authenticating with a client certificate.
+    keys: RwLock<Option<SigningKeys>>,
+    tokens: TokenCache,
+    events: EventBus,
+    metrics: Option<Arc<dyn Metrics>>,
+    metric_topics: TopicTagger,
+    record_timings: bool,
+    response_body_limit: usize,
+    pub(crate) permits: Option<Arc<tokio::sync::Semaphore>>,
+    pub(crate) concurrency_limit: Option<usize>,
+    pub(crate) memory: Option<Arc<MemoryBudget>>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosPolicy>,
+    pub(crate) tasks: Arc<TaskSet>,
+    closer: Closer,
+    rng: Arc<dyn Rng>,
+    pub(crate) fire_buffer: usize,
+    pub(crate) fire_queue: Arc<Queue>,
+    permit_queue: Arc<Queue>,
+    retry_queue: Arc<Queue>,
+    pub(crate) fire: Mutex<Option<futures::channel::mpsc::Sender<fire::Queued>>>,
+}
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub fn new(client: Client<S, Body>, team_id: &str, jwt_kid: &str, jwt_key: &[u8]) -> Result<Self, Error> {
+        Self::with_transport(Transport::Hyper(client), Arc::default(), team_id, jwt_kid, jwt_key)
+    }
+
+    /// Create a client with the `.p8` signing key in PEM, as downloaded
+    /// from the developer account.
+    pub fn from_pem(client: Client<S, Body>, team_id: &str, jwt_kid: &str, pem: &[u8]) -> Result<Self, Error> {
+        Self::new(client, team_id, jwt_kid, &pkcs8_from_pem(pem)?)
+    }
+
+    /// Create a client with the signing key in the `.p8` file at `path`.
+    pub fn from_key_file<P: AsRef<Path>>(client: Client<S, Body>, team_id: &str, jwt_kid: &str, path: P) -> Result<Self, Error> {
+        Self::new(client, team_id, jwt_kid, &read_key_file(path)?)
+    }
+
+    /// Create a client that speaks HTTP/2 directly over the connections made
+    /// by `connector`, without going through hyper's client.
+    #[cfg(feature = "h2")]
+    pub fn new_h2(connector: S, options: H2Options, team_id: &str, jwt_kid: &str, jwt_key: &[u8]) -> Result<Self, Error> {
+        let tasks = Arc::new(TaskSet::default());
+        Self::with_transport(Transport::H2(H2Transport::new(connector, options, tasks.clone())), tasks, team_id, jwt_kid, jwt_key)
+    }
+
+    /// Create a client that authenticates with the client certificate its
+    /// connector presents instead of provider tokens, so requests are sent
+    /// without an `authorization` header. The certificate decides the
+    /// environment: call `set_production(false)` for development
+    /// certificates.
+    pub fn new_certificate_auth(client: Client<S, Body>) -> Self {
+        Self::with_keys(Transport::Hyper(client), Arc::default(), "", None)
+    }
+
+    fn with_transport(transport: Transport<S>, tasks: Arc<TaskSet>, team_id: &str, jwt_kid: &str, jwt_key: &[u8]) -> Result<Self, Error> {
+        let key = SigningKey::from_pkcs8(jwt_kid, jwt_key)?;
+        Ok(Self::with_keys(transport, tasks, team_id, Some(SigningKeys { active: key, secondary: None })))
+    }
+
+    fn with_keys(transport: Transport<S>, tasks: Arc<TaskSet>, team_id: &str, keys: Option<SigningKeys>) -> Self {
+        Self {
+            production: true,
+            sandbox_fallback: false,
+            trim_policy: TrimPolicy::default(),
+            payload_limits: PayloadLimits::default(),
+            sanitize_policy: SanitizePolicy::default(),
+            expiration_policy: ExpirationPolicy::default(),
+            compatibility: None,
+            localizers: HashMap::new(),
+            topic_defaults: HashMap::new(),
+            payload_middleware: Vec::new(),
+            request_middleware: Vec::new(),
+            response_middleware: Vec::new(),
+            policies: Vec::new(),
+            reason_classifier: None,
+            throttle: None,
+            rate_limiter: None,
+            #[cfg(feature = "unstable-correlation")]
+            correlation_store: None,
+            #[cfg(feature = "unstable-payload-signing")]
+            payload_signer: None,
+            send_rate: None,
+            certificate: None,
+            revocation: RevocationTracker::new(DEFAULT_REVOCATION_THRESHOLD),
+            health: HealthTracker::default(),
+            retry_policy: None,
+            retry_budget: None,
+            transport,
+            team_id: team_id.to_owned(),
+            keys: RwLock::new(keys),
+            tokens: TokenCache::new(),
+            events: EventBus::new(),
+            metrics: None,
+            metric_topics: TopicTagger::new(metrics::DEFAULT_TOPIC_LIMIT),
+            record_timings: false,
+            response_body_limit: DEFAULT_RESPONSE_BODY_LIMIT,
+            permits: None,
+            concurrency_limit: None,
+            memory: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            tasks,
+            closer: Closer::new(),
+            rng: Arc::new(SystemRng),
+            fire_buffer: fire::DEFAULT_FIRE_BUFFER,
+            fire_queue: Arc::default(),
+            permit_queue: Arc::default(),
+            retry_queue: Arc::default(),
+            fire: Mutex::new(None),
+        }
+    }
+
+    /// A snapshot of the configuration the client runs with.
+    ///
+    /// ```no_run
+    /// # fn run(client: &apple_push::ApplePushClient<hyper::client::HttpConnector>) {
+    /// let config = client.config();
+    /// println!("sending to {} as team {}: {:?}", config.endpoint, config.team_id, config);
+    /// # }
+    /// ```
+    pub fn config(&self) -> ClientConfig {
+        let keys = self.keys.read().unwrap();
+        ClientConfig {
+            environment: self.environment(),
+            endpoint: self.environment().url(),
+            sandbox_fallback: self.sandbox_fallback,
+            team_id: self.team_id.clone(),
+            key_id: keys.as_ref().map(|keys| keys.active.kid.clone()),
+            secondary_key_id: keys.as_ref().and_then(|keys| keys.secondary.as_ref()).map(|key| key.kid.clone()),
+            token_refresh_interval: self.tokens.refresh_interval,
+            token_refresh_jitter: self.tokens.refresh_jitter,
+            token_backdate: self.tokens.backdate,
+            retry_policy: self.retry_policy.clone(),
+            retry_budget: self.retry_budget.as_ref().map(|budget| budget.budget.clone()),
+            throttle_policy: self.throttle.as_ref().map(|throttle| throttle.policy.clone()),
+            send_rate: self.send_rate.as_ref().map(|send_rate| send_rate.rate.clone()),
+            trim_policy: self.trim_policy,
+            sanitize_policy: self.sanitize_policy,
+            expiration_policy: self.expiration_policy,
+            compatibility: self.compatibility,
+            payload_limits: self.payload_limits.clone(),
+            response_body_limit: self.response_body_limit,
+            concurrency_limit: self.concurrency_limit,
+            memory_limit: self.memory.as_ref().map(|memory| memory.limit),
+            fire_buffer: self.fire_buffer,
+            #[cfg(feature = "h2")]
+            h2: match self.transport {
+                Transport::H2(ref transport) => Some(transport.options.clone()),
+                Transport::Hyper(_) => None,
+            },
+        }
+    }
+
+    /// PING the APNS connection and return the round trip time.
+    /// Only supported by clients created with `new_h2`.
+    #[cfg(feature = "h2")]
+    pub async fn ping(&self) -> Result<Duration, SendError> {
+        match self.transport {
+            Transport::H2(ref transport) => transport.ping().await,
+            Transport::Hyper(_) => Err(SendError::Transport("ping requires the h2 transport".into())),
+        }
+    }
+
+    /// How many connections to APNS are open. Only known for clients
+    /// created with `new_h2`.
+    #[cfg(feature = "h2")]
+    pub async fn connection_count(&self) -> Option<usize> {
+        match self.transport {
+            Transport::H2(ref transport) => Some(transport.open_connections().await),
+            Transport::Hyper(_) => None,
+        }
+    }
+
+    /// How many background tasks the client is running: the `fire` worker,
+    /// and with the h2 transport the connection and its keep-alive. Tasks
+    /// hyper runs for its own connections aren't counted.
+    pub fn background_task_count(&self) -> usize {
+        self.tasks.running()
+    }
+
+    /// Stop the background tasks of the client and wait for them to
+    /// finish. Notifications already queued by `fire` are sent first, and
+    /// the h2 connection closes once the requests in flight on it are done.
+    ///
+    /// Sends still waiting to go out, for a concurrency permit, a throttle,
+    /// the send rate or a retry, fail with `SendError::ClientClosed`. The
+    /// client stays usable: sending again starts what it needs anew.
+    pub async fn shutdown(&self) {
+        self.closer.close(CloseReason::Shutdown);
+        self.fire.lock().unwrap().take();
+        self.transport.reset().await;
+        self.tasks.join().await;
+    }
+
+    /// Set API endpoint to use (production or development sandbox).
+    /// Notifications with an `environment` of their own are sent there
+    /// instead.
+    pub fn set_production(&mut self, production: bool) {
+        self.production = production;
+    }
+
+    /// Send notifications that production refuses with `BadDeviceToken`
+    /// again through the sandbox, for backends serving both App Store and
+    /// development builds with one key. Only applies to notifications
+    /// without an `environment` of their own, when the client is set to
+    /// production. Off by default. Tokens that the sandbox refuses too are
+    /// ones `SendError::should_remove_token` holds for.
+    pub fn set_sandbox_fallback(&mut self, sandbox_fallback: bool) {
+        self.sandbox_fallback = sandbox_fallback;
+    }
+
+    /// Set what to do with notifications whose payload is too large.
+    /// Defaults to `TrimPolicy::Disabled`.
+    pub fn set_trim_policy(&mut self, trim_policy: TrimPolicy) {
+        self.trim_policy = trim_policy;
+    }
+
+    /// Set the maximum payload sizes. Notifications with larger payloads
+    /// (after trimming) fail with `ValidationError::PayloadTooLarge`.
+    pub fn set_payload_limits(&mut self, payload_limits: PayloadLimits) {
+        self.payload_limits = payload_limits;
+    }
+
+    /// Set what to do with control characters in alert strings.
+    /// Defaults to `SanitizePolicy::Allow`.
+    pub fn set_sanitize_policy(&mut self, sanitize_policy: SanitizePolicy) {
+        self.sanitize_policy = sanitize_policy;
+    }
+
+    /// Set what to do with notifications whose expiration makes no sense
+    /// for their push type. Defaults to `ExpirationPolicy::Warn`.
+    pub fn set_expiration_policy(&mut self, expiration_policy: ExpirationPolicy) {
+        self.expiration_policy = expiration_policy;
+    }
+
+    /// Adjust payloads for the oldest version of iOS the apps support, and
+    /// emit `Event::UnsupportedFeature` for notifications using features it
+    /// doesn't have. By default payloads are sent as they are built.
+    pub fn set_compatibility(&mut self, compatibility: Option<Compatibility>) {
+        self.compatibility = compatibility;
+    }
+
+    /// Resolve the localization keys of notifications to `topic` with
+    /// `localizer` before sending them.
+    pub fn set_localizer(&mut self, topic: &str, localizer: Arc<dyn Localizer>) {
+        self.localizers.insert(topic.to_owned(), localizer);
+    }
+
+    /// Merge `defaults` into every notification to `topic` before sending
+    /// it, replacing any defaults set for the topic before.
+    pub fn set_topic_defaults(&mut self, topic: &str, defaults: TopicDefaults) {
+        self.topic_defaults.insert(topic.to_owned(), defaults);
+    }
+
+    /// Enable adaptive throttling of topics that APNS responds to with 429s.
+    /// Disabled by default. See `SendRate` for how this works with the
+    /// other limits on sends.
+    pub fn set_throttle_policy(&mut self, policy: Option<ThrottlePolicy>) {
+        self.throttle = policy.map(Throttle::new);
+    }
+
+    /// Limit how often notifications are sent to each device token. Sends
+    /// over the limit fail with `SendError::RateLimited` without being sent.
+    /// Disabled by default. See `SendRate` for how this works with the
+    /// other limits on sends.
+    pub fn set_rate_limiter(&mut self, limiter: Option<Arc<dyn RateLimiter>>) {
+        self.rate_limiter = limiter;
+    }
+
+    /// Record the metadata passed to `send_correlated` in `store`. Unset by
+    /// default, which drops it.
+    #[cfg(feature = "unstable-correlation")]
+    pub fn set_correlation_store(&mut self, store: Option<Arc<dyn CorrelationStore>>) {
+        self.correlation_store = store;
+    }
+
+    /// Sign the custom data of every notification with `signer`, adding the
+    /// signature as a custom key, before payload middleware runs. Unset by
+    /// default.
+    #[cfg(feature = "unstable-payload-signing")]
+    pub fn set_payload_signer(&mut self, signer: Option<Arc<dyn PayloadSigner>>) {
+        self.payload_signer = signer;
+    }
+
+    /// Limit the rate at which requests are sent, across every device
+    /// token and topic, and back off from 429 responses: all sends pause
+    /// for a while, and sends to a device token APNS answered
+    /// `TooManyRequests` for fail with `SendError::RateLimited` until it
+    /// has cooled down. Sends over the rate wait for their turn. Disabled
+    /// by default.
+    pub fn set_send_rate(&mut self, rate: Option<SendRate>) {
+        self.send_rate = rate.map(SendRateLimiter::new);
+    }
+
+    /// Enable retrying failed sends. Disabled by default.
+    /// With retries enabled, errors are returned as `SendError::Retried`
+    /// with the history of every attempt.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+    }
+
+    /// Cap the retries of every send together, on top of the retry policy
+    /// of each. Disabled by default.
+    pub fn set_retry_budget(&mut self, budget: Option<RetryBudget>) {
+        self.retry_budget = budget.map(Budget::new);
+    }
+
+    /// Report measurements to `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Set how many distinct topics metrics are tagged with. Notifications
+    /// to further topics are counted under the `other` topic. Defaults to 100.
+    pub fn set_metrics_topic_limit(&mut self, limit: usize) {
+        self.metric_topics.limit = limit;
+    }
+
+    /// The metric tags of `n`, if metrics are enabled.
+    pub(crate) fn tags(&self, n: &Notification) -> Option<Tags> {
+        self.metrics.as_ref().map(|_| self.metric_topics.tags(n))
+    }
+
+    pub(crate) fn count(&self, name: &'static str, tags: Option<&Tags>) {
+        if let (Some(metrics), Some(tags)) = (&self.metrics, tags) {
+            metrics.tagged_counter(name, 1, &tags.as_pairs());
+        }
+    }
+
+    fn measure(&self, name: &'static str, value: f64, tags: Option<&Tags>) {
+        if let (Some(metrics), Some(tags)) = (&self.metrics, tags) {
+            metrics.histogram(name, value, &tags.as_pairs());
+        }
+    }
+
+    /// Measure an attempt that took `elapsed`, and count it by reason if
+    /// APNS refused it.
+    fn observe(&self, result: &Result<SendResponse, SendError>, elapsed: Duration, tags: Option<&Tags>) {
+        let (metrics, tags) = match (&self.metrics, tags) {
+            (Some(metrics), Some(tags)) => (metrics, tags),
+            _ => return,
+        };
+        metrics.histogram(names::LATENCY_MS, elapsed.as_secs_f64() * 1000.0, &tags.as_pairs());
+        if let Err(SendError::Api(ref error)) = result {
+            let reason = error.reason.to_string();
+            let [topic, push_type] = tags.as_pairs();
+            metrics.tagged_counter(names::API_ERROR, 1, &[topic, push_type, (crate::metrics::tags::REASON, &reason)]);
+        }
+    }
+
+    /// The notifications queued by `fire` and not yet being sent.
+    pub fn fire_queue_stats(&self) -> QueueStats {
+        self.fire_queue.stats()
+    }
+
+    /// The sends waiting for a concurrency permit. See
+    /// `set_concurrency_limit`.
+    pub fn permit_queue_stats(&self) -> QueueStats {
+        self.permit_queue.stats()
+    }
+
+    /// The sends waiting out the backoff before a retry. See
+    /// `set_retry_policy`.
+    pub fn retry_queue_stats(&self) -> QueueStats {
+        self.retry_queue.stats()
+    }
+
+    /// Record the stats of every internal queue in the metrics, as the
+    /// `queue.depth` and `queue.oldest_age_ms` histograms. Call it
+    /// periodically, e.g. when metrics are scraped, to alert on a growing
+    /// backlog before it shows as latency.
+    pub fn report_queue_stats(&self) {
+        let metrics = match self.metrics {
+            Some(ref metrics) => metrics,
+            None => return,
+        };
+        let queues = [("fire", &self.fire_queue), ("permits", &self.permit_queue), ("retries", &self.retry_queue)];
+        for (name, queue) in queues.iter() {
+            let stats = queue.stats();
+            let tags = [(metrics::tags::QUEUE, *name)];
+            metrics.histogram(names::QUEUE_DEPTH, stats.depth as f64, &tags);
+            if let Some(age) = stats.oldest_age {
+                metrics.histogram(names::QUEUE_OLDEST_AGE_MS, age.as_secs_f64() * 1000.0, &tags);
+            }
+        }
+    }
+
+    /// Inject delays and failures into sends according to `policy`.
+    #[cfg(feature = "chaos")]
+    pub fn set_chaos_policy(&mut self, policy: Option<ChaosPolicy>) {
+        self.chaos = policy;
+    }
+
+    /// Draw the jitter of retries and provider token refreshes, and the
+    /// rolls of the chaos policy, from `rng`, e.g. a `DeterministicRng` so
+    /// that tests run the same way every time. Defaults to `SystemRng`.
+    pub fn set_rng(&mut self, rng: Arc<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// Set whether `send_detailed` reports where the time of each send went.
+    /// Off by default.
+    pub fn set_record_timings(&mut self, record: bool) {
+        self.record_timings = record;
+    }
+
+    /// Set how many bytes of a response body are read before the send
+    /// fails with `SendError::ResponseTooLarge`, in case something other
+    /// than APNS answers, such as a misconfigured proxy. Defaults to 16 KiB.
+    pub fn set_response_body_limit(&mut self, limit: usize) {
+        self.response_body_limit = limit;
+    }
+
+    /// Run `middleware` on the payload of every notification once it is
+    /// serialized, after any middleware added before.
+    pub fn add_payload_middleware(&mut self, middleware: Arc<dyn PayloadMiddleware>) {
+        self.payload_middleware.push(middleware);
+    }
+
+    /// Run `middleware` on every request before it is sent, after any
+    /// middleware added before.
+    pub fn add_request_middleware(&mut self, middleware: Arc<dyn RequestMiddleware>) {
+        self.request_middleware.push(middleware);
+    }
+
+    /// Run `middleware` on every response before it is turned into a result,
+    /// after any middleware added before.
+    pub fn add_response_middleware(&mut self, middleware: Arc<dyn ResponseMiddleware>) {
+        self.response_middleware.push(middleware);
+    }
+
+    /// Check every notification against `policy` before it is sent, after
+    /// any policy added before.
+    pub fn add_policy(&mut self, policy: Arc<dyn Policy>) {
+        self.policies.push(policy);
+    }
+
+    /// Decide how to treat error reasons the crate doesn't know, such as
+    /// ones Apple added since it was released, by calling `classifier` with
+    /// the reason and the status of the response. By default they are
+    /// retried only for 429, 500 and 503 responses, and the device token is
+    /// removed only for 410 responses; returning `None` keeps that.
+    ///
+    /// ```
+    /// # fn run(client: &mut apple_push::ApplePushClient<hyper::client::HttpConnector>) {
+    /// use apple_push::ReasonClass;
+    ///
+    /// client.set_reason_classifier(|reason, _status| match reason {
+    ///     "DeviceTokenRevoked" => Some(ReasonClass { retriable: false, remove_token: true }),
+    ///     _ => None,
+    /// });
+    /// # }
+    /// ```
+    pub fn set_reason_classifier<F>(&mut self, classifier: F)
+    where
+        F: Fn(&str, u32) -> Option<ReasonClass> + Send + Sync + 'static,
+    {
+        self.reason_classifier = Some(Arc::new(classifier));
+    }
+
+    /// Emit `Event::CertificateExpiring` from sends once `certificate`, the
+    /// client certificate of the connector, expires within `within`.
+    pub fn set_certificate_expiry_warning(&mut self, certificate: Certificate, within: Duration) {
+        self.certificate = Some(ExpiryMonitor::new(certificate, within));
+    }
+
+    /// Set after how many consecutive `InvalidProviderToken` responses the
+    /// signing key is considered revoked, and `Event::CredentialRevoked`
+    /// emitted. Defaults to 5.
+    pub fn set_revocation_threshold(&mut self, threshold: u32) {
+        self.revocation.threshold = threshold.max(1);
+    }
+
+    /// Whether the signing key looks revoked: APNS refused the last
+    /// provider tokens, at least as many in a row as the revocation
+    /// threshold. Cleared by the next response that doesn't refuse it.
+    pub fn credentials_revoked(&self) -> bool {
+        self.revocation.is_revoked()
+    }
+
+    /// The health of the client, from the state of its credentials and the
+    /// requests of the last minute, for readiness probes. The worst of:
+    ///
+    /// * unhealthy if the signing key looks revoked (see
+    ///   `credentials_revoked`) or the certificate given to
+    ///   `set_certificate_expiry_warning` expired, degraded if the
+    ///   certificate expires within its warning window
+    /// * unhealthy if the last connections to APNS failed, or half of the
+    ///   recent requests failed to reach APNS or got a server error
+    /// * degraded if a tenth of the recent requests failed or were
+    ///   throttled
+    pub fn health(&self) -> Health {
+        let certificate = self.certificate.as_ref().map_or(Health::Ok, ExpiryMonitor::health);
+        vec![self.revocation.health(), certificate, self.health.health()]
+            .into_iter()
+            .max_by_key(|health| match health {
+                Health::Ok => 0,
+                Health::Degraded(_) => 1,
+                Health::Unhealthy(_) => 2,
+            })
+            .unwrap_or(Health::Ok)
+    }
+
+    /// Set a secondary signing key, for rotating keys. If APNS refuses
+    /// tokens signed with the current key, the client switches to the
+    /// secondary key for good, retries the refused notification and emits
+    /// `Event::KeyFailover`. Fails for clients authenticating with a client
+    /// certificate.
+    pub fn set_secondary_key(&mut self, jwt_kid: &str, jwt_key: &[u8]) -> Result<(), Error> {
+        let keys = self.keys.get_mut().unwrap().as_mut()
+            .ok_or("clients authenticating with a certificate don't sign tokens")?;
+        keys.secondary = Some(SigningKey::from_pkcs8(jwt_kid, jwt_key)?);
+        Ok(())
+    }
+
+    /// Subscribe to events about the client. Events that happen while
+    /// nobody is subscribed are not kept.
+    pub fn events(&self) -> tokio::sync::broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Set how long a provider token is sent before a new one is signed.
+    /// Defaults to 50 minutes, and is kept between the 20 minutes APNS
+    /// wants between refreshes and the hour it accepts a token for. The
+    /// first send once it is due signs the new token, while concurrent
+    /// sends keep using the old one.
+    pub fn set_token_refresh_interval(&mut self, interval: Duration) {
+        self.tokens.set_refresh_interval(interval);
+    }
+
+    /// Set the upper bound of the random amount of time by which the signing
+    /// token is refreshed early. Defaults to 5 minutes.
+    pub fn set_token_refresh_jitter(&mut self, jitter: Duration) {
+        self.tokens.refresh_jitter = jitter;
+    }
+
+    /// Set how far in the past provider tokens claim to be issued, so that
+    /// APNS doesn't refuse them when the local clock runs a little ahead of
+    /// Apple's. Defaults to 10 seconds.
+    pub fn set_token_backdate(&mut self, backdate: Duration) {
+        self.tokens.backdate = backdate;
+    }
+
+    /// The environment notifications are sent to.
+    pub(crate) fn environment(&self) -> Environment {
+        if self.production {
+            Environment::Production
+        } else {
+            Environment::Sandbox
+        }
+    }
+
+    /// Build the url for a device token.
+    fn build_url(environment: Environment, device_token: &str) -> String {
+        format!("{}{}{}", environment.url(), consts::DEVICE_PATH, device_token)
+    }
+
+    /// A link to the delivery log entry of a notification that was sent to
+    /// `topic`, for following it from the logs, if APNS gave it a unique id
+    /// and the client knows the team. See `Environment::console_url`.
+    pub fn console_url(&self, topic: &str, response: &SendResponse) -> Option<String> {
+        let unique_id = response.unique_id.as_ref()?;
+        if self.team_id.is_empty() {
+            return None;
+        }
+        Some(response.environment.console_url(&self.team_id, topic, unique_id))
+    }
+
+    /// The cached provider token, if one has been signed.
+    pub fn token_info(&self) -> Option<TokenInfo> {
+        self.tokens.info()
+    }
+
+    /// The number of provider tokens signed in the last hour. APNS refuses
+    /// tokens with `TooManyProviderTokenUpdates` when they are refreshed
+    /// more often than every 20 minutes.
+    pub fn token_signings_last_hour(&self) -> usize {
+        self.tokens.signings_last_hour()
+    }
+
+    /// Drop the cached provider token, so the next notification is sent with
+    /// a newly signed one. Useful after revoking a key out of band. Has no
+    /// effect while the client is cooling down after APNS answered
+    /// `TooManyProviderTokenUpdates`; see `Event::TokenCoolDown`.
+    pub fn invalidate_token(&self) {
+        self.tokens.clear();
+    }
+
+    /// The provider token to send, or `None` if the client authenticates
+    /// with a certificate.
+    fn generate_jwt(&self) -> Result<Option<String>, Error> {
+        let keys = self.keys.read().unwrap();
+        let key = match *keys {
+            Some(ref keys) => &keys.active,
+            None => return Ok(None),
+        };
+        let (token, signed) = self.tokens.get(key, &self.team_id, self.rng.as_ref())?;
+        if signed {
+            let last_hour = self.tokens.signings_last_hour();
+            #[cfg(feature = "tracing")]
+            tracing::debug!(kid = %key.kid, last_hour, "signed a provider token");
+            if let Some(ref metrics) = self.metrics {
+                metrics.counter(names::JWT_SIGNED, 1);
+                metrics.histogram(names::JWT_SIGNED_LAST_HOUR, last_hour as f64, &[]);
+            }
+            if last_hour > GUIDED_SIGNINGS_PER_HOUR {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(kid = %key.kid, last_hour, "signing provider tokens more often than APNS allows");
+                self.events.emit(Event::FrequentTokenSigning { kid: key.kid.clone(), last_hour });
+            }
+        }
+        Ok(Some(token))
+    }
+
+    /// Check what can be checked of `n` without sending it: its device
+    /// token, its push type, custom keys and extra headers, its expiration
+    /// under `ExpirationPolicy::Reject`, and its size after trimming.
+    /// Payload middleware, topic defaults and localization are not applied,
+    /// so a notification that passes can still be refused when sent.
+    pub fn check(&self, n: &Notification) -> Result<(), ValidationError> {
+        n.validate_device_token()?;
+        self.check_content(n)
+    }
+
+    /// `check`, but for the device token.
+    pub(crate) fn check_content(&self, n: &Notification) -> Result<(), ValidationError> {
+        n.validate_push_type()?;
+        if let Some(key) = n.reserved_custom_key() {
+            return Err(ValidationError::ReservedKey { key: key.to_owned() });
+        }
+        n.validate_extra_apns_headers()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut resolved = n.clone();
+        if let (None, Some(ttl)) = (n.expiration, n.ttl) {
+            resolved.expiration = Some((now + ttl).as_secs());
+        }
+        self.expiration_policy.check(&resolved, now.as_secs())?;
+        let limit = self.payload_limits.limit_for(n.effective_push_type(), &n.topic);
+        let mut body = ApnsRequest { aps: resolved.payload, media_url: resolved.media_url, custom: resolved.custom };
+        let size = self.trim_policy.serialize(&mut body, limit).map_or(0, |body| body.len());
+        if size > limit {
+            return Err(ValidationError::PayloadTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    /// Send a notification, retrying according to the retry policy.
+    /// Returns the apns-id of the notification.
+    pub async fn send(&self, n: Notification) -> Result<ApnsId, SendError> {
+        self.send_detailed(n).await.map(|response| response.id)
+    }
+
+    /// Like `send`, but first records `metadata` under the apns-id of the
+    /// notification in the correlation store, so that it can be looked up
+    /// by apns-id later, even if the send fails. See
+    /// `set_correlation_store`.
+    #[cfg(feature = "unstable-correlation")]
+    pub async fn send_correlated(&self, mut n: Notification, metadata: serde_json::Value) -> Result<ApnsId, SendError> {
+        let id = *n.id.get_or_insert_with(id::generate);
+        if let Some(ref store) = self.correlation_store {
+            store.record(id, metadata).await?;
+        }
+        self.send(n).await
+    }
+
+    /// Like `send`, but also returns the timings of the final attempt if
+    /// enabled with `set_record_timings`.
+    pub async fn send_detailed(&self, n: Notification) -> Result<SendResponse, SendError> {
+        self.send_limited(n, None).await
+    }
+
+    /// Send once a concurrency permit is free and the payload fits in the
+    /// memory limit, unless `reservation` already holds its memory.
+    pub(crate) async fn send_limited(&self, n: Notification, reservation: Option<Reservation>) -> Result<SendResponse, SendError> {
+        let mut stopwatch = Stopwatch::start();
+        let _permit = match self.permits {
+            Some(ref permits) => {
+                let _waiting = self.permit_queue.enter();
+                Some(self.closer.unless_closed(permits.clone().acquire_owned()).await?)
+            }
+            None => None,
+        };
+        let _reservation = match reservation {
+            Some(reservation) => Some(reservation),
+            None => self.closer.unless_closed(self.reserve(&n)).await?,
+        };
+        let waited = stopwatch.lap();
+        let mut response = self.send_counted(n).await?;
+        if let Some(ref mut timings) = response.timings {
+            timings.queue_wait += waited;
+        }
+        Ok(response)
+    }
+
+    pub(crate) async fn send_counted(&self, mut n: Notification) -> Result<SendResponse, SendError> {
+        if n.has_conflicting_priority() {
+            if let Some(interruption_level) = n.payload.interruption_level {
+                self.events.emit(Event::ConflictingPriority { topic: n.topic.clone(), interruption_level });
+            }
+        }
+        n.priority = n.effective_priority();
+        if let Some(event) = self.certificate.as_ref().and_then(ExpiryMonitor::check) {
+            self.events.emit(event);
+        }
+        let tags = self.tags(&n);
+        let result = self.send_retrying(n, tags.as_ref()).await;
+        self.count(if result.is_ok() { names::SENT } else { names::FAILED }, tags.as_ref());
+        result
+    }
+
+    async fn send_retrying(&self, mut n: Notification, tags: Option<&Tags>) -> Result<SendResponse, SendError> {
+        for policy in &self.policies {
+            if let Verdict::Veto(reason) = policy.check(&mut n).await? {
+                self.count(names::VETOED, tags);
+                return Err(SendError::Vetoed(reason));
+            }
+        }
+        // Retries keep the expiration of the first attempt.
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(Error::from)?;
+        if let (None, Some(ttl)) = (n.expiration, n.ttl) {
+            n.expiration = Some((now + ttl).as_secs());
+        }
+        if let Some(problem) = self.expiration_policy.check(&n, now.as_secs())? {
+            self.events.emit(Event::UnsuitableExpiration { topic: n.topic.clone(), push_type: n.effective_push_type(), problem });
+        }
+        let policy = match self.retry_policy {
+            Some(ref policy) => policy,
+            None => return self.send_attempt(n).await,
+        };
+        // Every attempt uses the same apns-id.
+        n.id.get_or_insert_with(id::generate);
+        if let Some(ref budget) = self.retry_budget {
+            budget.record_attempt();
+        }
+        let mut attempts = Vec::new();
+        loop {
+            let at = SystemTime::now();
+            let error = match self.send_attempt(n.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+            let backoff = policy.backoff(attempts.len() as u32 + 1, &error, self.rng.as_ref()).filter(|_| self.take_retry(tags));
+            attempts.push(AttemptRecord { at, error, backoff });
+            match backoff {
+                Some(backoff) => {
+                    let _waiting = self.retry_queue.enter();
+                    self.closer.unless_closed(tokio::time::delay_for(backoff)).await?
+                }
+                None => return Err(SendError::Retried(AttemptHistory { attempts })),
+            }
+        }
+    }
+
+    /// Whether the retry budget allows another retry, counting the retry
+    /// if so.
+    fn take_retry(&self, tags: Option<&Tags>) -> bool {
+        if self.retry_budget.as_ref().is_some_and(|budget| !budget.try_retry()) {
+            self.count(names::RETRY_BUDGET_EXHAUSTED, tags);
+            return false;
+        }
+        self.count(names::RETRIED, tags);
+        true
+    }
+
+    /// Send once, keeping track of whether the key looks revoked.
+    async fn send_attempt(&self, n: Notification) -> Result<SendResponse, SendError> {
+        let result = self.send_failing_over(n).await;
+        if let Err(SendError::Api(ApiError { reason: ApiErrorReason::ExpiredProviderToken, .. })) = result {
+            // Our clock is probably off; a new token is the best bet.
+            self.invalidate_token();
+        }
+        if let Err(SendError::Api(ApiError { reason: ApiErrorReason::TooManyProviderTokenUpdates, .. })) = result {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                last_hour = self.tokens.signings_last_hour(),
+                "TooManyProviderTokenUpdates: {}",
+                ApiErrorReason::TooManyProviderTokenUpdates.remediation().unwrap_or_default(),
+            );
+            // Signing again would only make it worse.
+            let kid = self.keys.read().unwrap().as_ref().map(|keys| keys.active.kid.clone());
+            if let Some(kid) = kid {
+                if self.tokens.cool_down() {
+                    self.events.emit(Event::TokenCoolDown { kid, duration: token_cache::COOL_DOWN });
+                }
+            }
+        }
+        self.health.record(&result);
+        if let Some(consecutive) = self.revocation.record(&result) {
+            let kid = self.keys.read().unwrap().as_ref().map(|keys| keys.active.kid.clone());
+            if let Some(kid) = kid {
+                self.events.emit(Event::CredentialRevoked { kid: kid.clone(), consecutive });
+                // The sends waiting would be refused the same way.
+                self.closer.close(CloseReason::CredentialRevoked { kid });
+            }
+        }
+        result
+    }
+
+    /// Send once, and again with the secondary key if APNS refuses the
+    /// token signed with the active one.
+    async fn send_failing_over(&self, mut n: Notification) -> Result<SendResponse, SendError> {
+        let kid = self.keys.read().unwrap().as_ref().and_then(SigningKeys::failover_kid);
+        let kid = match kid {
+            Some(kid) => kid,
+            None => return self.send_once(n).await,
+        };
+        n.id.get_or_insert_with(id::generate);
+        match self.send_once(n.clone()).await {
+            Err(SendError::Api(ApiError { reason: ApiErrorReason::InvalidProviderToken, .. })) if self.fail_over(&kid) => {
+                self.send_once(n).await
+            }
+            result => result,
+        }
+    }
+
+    /// Switch away from the key `kid`, returning whether the active key is
+    /// now another one.
+    fn fail_over(&self, kid: &str) -> bool {
+        let (switched, failover) = match *self.keys.write().unwrap() {
+            Some(ref mut keys) => keys.fail_over(kid),
+            None => (false, None),
+        };
+        if let Some((from_kid, to_kid)) = failover {
+            self.events.emit(Event::KeyFailover { from_kid, to_kid });
+        }
+        switched
+    }
+
+    async fn send_once(&self, mut n: Notification) -> Result<SendResponse, SendError> {
+        if let Some(environment) = n.environment {
+            return self.send_once_in(n, environment).await;
+        }
+        let environment = self.environment();
+        if !self.sandbox_fallback || environment == Environment::Sandbox {
+            return self.send_once_in(n, environment).await;
+        }
+        n.id.get_or_insert_with(id::generate);
+        match self.send_once_in(n.clone(), environment).await {
+            Err(SendError::Api(ApiError { reason: ApiErrorReason::BadDeviceToken, .. })) => {
+                match self.send_once_in(n, Environment::Sandbox).await {
+                    // Bad in both environments, so bad for good.
+                    Err(SendError::Api(mut e)) if e.is_bad_device_token() => {
+                        e.class = Some(ReasonClass { retriable: false, remove_token: true });
+                        Err(SendError::Api(e))
+                    }
+                    result => result,
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Send once to `environment`, whichever the client is set to.
+    pub(crate) async fn send_once_in(&self, mut n: Notification, environment: Environment) -> Result<SendResponse, SendError> {
+        let tags = self.tags(&n);
+        let started = Instant::now();
+        let id = *n.id.get_or_insert_with(id::generate);
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "apns.send",
+            apns_id = %id,
+            topic = %n.topic,
+            push_type = n.effective_push_type().as_str(),
+            status = tracing::field::Empty,
+        );
+        #[cfg(not(feature = "tracing"))]
+        let _ = id;
+        let sending = self.send_unobserved(n, environment, tags.as_ref());
+        #[cfg(feature = "tracing")]
+        let sending = tracing::Instrument::instrument(sending, span.clone());
+        let result = sending.await;
+        #[cfg(feature = "tracing")]
+        {
+            let status = match result {
+                Ok(_) => Some(200),
+                Err(SendError::Api(ref error)) => Some(error.status),
+                Err(_) => None,
+            };
+            if let Some(status) = status {
+                span.record("status", status);
+            }
+        }
+        self.observe(&result, started.elapsed(), tags.as_ref());
+        result
+    }
+
+    async fn send_unobserved(&self, mut n: Notification, environment: Environment, tags: Option<&Tags>) -> Result<SendResponse, SendError> {
+        let mut timings = Timings::default();
+        let mut stopwatch = Stopwatch::start();
+        if let Some(defaults) = self.topic_defaults.get(&n.topic) {
+            defaults.apply(&mut n)?;
+        }
+        if let Some(localizer) = self.localizers.get(&n.topic) {
+            localize::apply(localizer.as_ref(), &mut n.payload);
+        }
+        self.sanitize_policy.apply(&mut n.payload)?;
+        if let Some(compatibility) = self.compatibility {
+            for unsupported in compatibility.apply(n.effective_push_type(), &mut n.payload) {
+                self.events.emit(Event::UnsupportedFeature { topic: n.topic.clone(), feature: unsupported.feature, since: unsupported.since });
+            }
+        }
+        if let Some(delay) = self.throttle.as_ref().and_then(|t| t.apply(&mut n)) {
+            stopwatch.lap();
+            self.closer.unless_closed(tokio::time::delay_for(delay)).await?;
+            timings.queue_wait = stopwatch.lap();
+        }
+        if let Some(retry_after) = self.send_rate.as_ref().and_then(|r| r.device_backoff(&n.device_token)) {
+            return Err(SendError::RateLimited { retry_after });
+        }
+        if let Some(ref limiter) = self.rate_limiter {
+            if let Some(retry_after) = limiter.acquire(&n.device_token).await.map_err(SendError::Other)? {
+                return Err(SendError::RateLimited { retry_after });
+            }
+        }
+        if let Some(delay) = self.send_rate.as_ref().and_then(SendRateLimiter::take) {
+            stopwatch.lap();
+            self.closer.unless_closed(tokio::time::delay_for(delay)).await?;
+            timings.queue_wait += stopwatch.lap();
+        }
+        if let Some(expiration) = n.expiration.filter(|&expiration| expiration != 0) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(Error::from)?.as_secs();
+            if expiration < now {
+                self.count(names::EXPIRED, tags);
+                return Err(SendError::Expired { expiration, late_by: now - expiration });
+            }
+        }
+        let id = n.id.unwrap_or_else(id::generate);
+        n.validate_push_type()?;
+        let push_type = n.effective_push_type();
+        if let Some(key) = n.reserved_custom_key() {
+            return Err(ValidationError::ReservedKey { key: key.to_owned() }.into());
+        }
+        n.validate_extra_apns_headers()?;
+        let mut body = ApnsRequest { aps: n.payload, media_url: n.media_url, custom: n.custom };
+        #[cfg(feature = "unstable-payload-signing")]
+        if let Some(ref signer) = self.payload_signer {
+            payload_signing::sign(signer.as_ref(), &mut body.custom)?;
+        }
+        stopwatch.lap();
+        let jwt = self.generate_jwt().map_err(SendError::from)?;
+        timings.jwt = stopwatch.lap();
+        let limit = self.payload_limits.limit_for(push_type, &n.topic);
+        let mut body = self.trim_policy.serialize(&mut body, limit)?;
+        for middleware in &self.payload_middleware {
+            middleware.on_payload(&n.topic, &mut body)?;
+        }
+        if body.len() > limit {
+            return Err(ValidationError::PayloadTooLarge { size: body.len(), limit }.into());
+        }
+        self.measure(names::PAYLOAD_BYTES, body.len() as f64, tags);
+
+        let mut req = Request::post(&Self::build_url(environment, &n.device_token));
+        let headers = req.headers_mut().unwrap();
+        if let Some(jwt) = jwt {
+            headers.insert(consts::AUTHORIZATION, format!("bearer {}", jwt).parse()?);
+        }
+        headers.insert(consts::APNS_ID, id.to_string().parse()?);
+        headers.insert(consts::APNS_TOPIC, n.topic.parse()?);
+        
+        if let Some(expiration) = n.expiration {
+            headers.insert(consts::APNS_EXPIRATION, expiration.to_string().parse()?);
+        }
+        if let Some(priority) = n.priority {
+            headers.insert(consts::APNS_PRIORITY, priority.to_int().to_string().parse()?);
+        }
+        headers.insert(consts::APNS_PUSH_TYPE, push_type.as_str().parse()?);
+        if let Some(collapse_id) = n.collapse_id {
+            headers.insert(consts::APNS_COLLAPSE_ID, collapse_id.as_str().parse()?);
+        }
+        for (name, value) in n.extra_apns_headers.into_iter().flatten() {
+            headers.insert(http::header::HeaderName::from_lowercase(name.as_bytes()).map_err(Error::from)?, value.parse()?);
+        }
+
+        let mut req = req.body(body)?;
+        for middleware in &self.request_middleware {
+            middleware.on_request(&mut req)?;
+        }
+        let res = self.request(req, &mut timings).await?;
+        let overridden = self.response_middleware.iter().fold(None, |error, middleware| {
+            let replacement = middleware.on_response(&res);
+            error.or(replacement)
+        });
+        if let Some(error) = overridden {
+            return Err(error);
+        }
+        let status = res.status();
+        if status.is_success() {
+            Ok(SendResponse {
+                id,
+                environment,
+                unique_id: res.headers().get(consts::APNS_UNIQUE_ID)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned),
+                timings: if self.record_timings { Some(timings) } else { None },
+            })
+        }
+        else {
+            let mut error = ErrorResponse::parse_error(status.as_u16() as u32, res.body().as_ref());
+            if let (ApiErrorReason::Other(ref reason), Some(ref classifier)) = (&error.reason, &self.reason_classifier) {
+                error.class = classifier(reason, error.status);
+            }
+            error.retry_after = res.headers().get(consts::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                if let Some(ref throttle) = self.throttle {
+                    throttle.record(&n.topic);
+                }
+                if let Some(ref send_rate) = self.send_rate {
+                    let too_many_requests = matches!(error.reason, ApiErrorReason::TooManyRequests);
+                    send_rate.record_429(&n.device_token, too_many_requests, error.retry_after);
+                }
+            }
+            Err(error.into())
+        }
+    }
+
+    /// Send a request through the transport, unless the chaos layer
+    /// intervenes.
+    async fn request(&self, req: Request<Vec<u8>>, timings: &mut Timings) -> Result<hyper::Response<bytes::Bytes>, SendError> {
+        #[cfg(feature = "chaos")]
+        if let Some(ref chaos) = self.chaos {
+            match chaos.inject(self.rng.as_ref()).await {
+                Injected::Nothing => (),
+                Injected::Reset => {
+                    self.transport.reset().await;
+                    return Err(SendError::Transport("connection reset by chaos policy".into()));
+                }
+                Injected::Response(res) => return Ok(res),
+            }
+        }
+        self.transport.request(req, self.response_body_limit, timings).await
+    }
+}
+
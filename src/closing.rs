@@ -0,0 +1,44 @@
+use std::future::Future;
+use std::sync::Mutex;
+
+use futures::channel::oneshot;
+use futures::future::{self, Either, FutureExt, Shared};
+
+use crate::error::{CloseReason, SendError};
+
+
+/// Ends the waits of sends that are pending when the client is closed, so
+/// that they fail with `SendError::ClientClosed` instead of carrying on.
+/// Sends started after closing wait as usual.
+pub(crate) struct Closer {
+    current: Mutex<(oneshot::Sender<CloseReason>, Shared<oneshot::Receiver<CloseReason>>)>,
+}
+
+impl Closer {
+    pub fn new() -> Self {
+        Closer { current: Mutex::new(Self::pair()) }
+    }
+
+    fn pair() -> (oneshot::Sender<CloseReason>, Shared<oneshot::Receiver<CloseReason>>) {
+        let (closer, closed) = oneshot::channel();
+        (closer, closed.shared())
+    }
+
+    /// End every pending wait with `reason`.
+    pub fn close(&self, reason: CloseReason) {
+        let (closer, _) = std::mem::replace(&mut *self.current.lock().unwrap(), Self::pair());
+        let _ = closer.send(reason);
+    }
+
+    /// Wait for `waiting`, unless the client is closed first.
+    pub async fn unless_closed<F: Future>(&self, waiting: F) -> Result<F::Output, SendError> {
+        let closed = self.current.lock().unwrap().1.clone();
+        futures::pin_mut!(waiting);
+        match future::select(waiting, closed).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right((Ok(reason), _)) => Err(SendError::ClientClosed(reason)),
+            // The sender is only dropped after sending.
+            Either::Right((Err(_), waiting)) => Ok(waiting.await),
+        }
+    }
+}
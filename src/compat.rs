@@ -0,0 +1,112 @@
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Alert, CriticalSound, InterruptionLevel, Payload, PushType, Sound};
+
+
+/// A version of iOS.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct IosVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl IosVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        IosVersion { major, minor }
+    }
+}
+
+impl fmt::Display for IosVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iOS {}.{}", self.major, self.minor)
+    }
+}
+
+/// A feature of a notification that devices on the oldest supported
+/// version of iOS don't understand, and that was sent anyway since there is
+/// no older equivalent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unsupported {
+    pub feature: &'static str,
+    /// The first version of iOS supporting the feature.
+    pub since: IosVersion,
+}
+
+/// Adjusts payloads for the oldest version of iOS an app supports. See
+/// `ApplePushClient::set_compatibility`.
+///
+/// Keys that older devices ignore, and that only tune how newer ones
+/// present the notification, are left out, saving payload space: the
+/// `interruption-level`, `relevance-score` and `filter-criteria` before iOS
+/// 15. A critical interruption level is also sent as the critical alert
+/// sound iOS 12 to 14 understand.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Compatibility {
+    pub min_ios: IosVersion,
+}
+
+const IOS_12: IosVersion = IosVersion::new(12, 0);
+const IOS_13: IosVersion = IosVersion::new(13, 0);
+const IOS_15: IosVersion = IosVersion::new(15, 0);
+const IOS_16_1: IosVersion = IosVersion::new(16, 1);
+
+impl Compatibility {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Compatibility { min_ios: IosVersion::new(major, minor) }
+    }
+
+    /// Adjust `payload`, and return the features it uses that the oldest
+    /// supported version doesn't have. A shared payload is only copied if
+    /// something needs to change.
+    ///
+    /// ```
+    /// use apple_push::{Compatibility, InterruptionLevel, NotificationBuilder, PushType};
+    ///
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .alert("Hello")
+    ///     .interruption_level(InterruptionLevel::TimeSensitive)
+    ///     .target_content_id("inbox")
+    ///     .build();
+    /// let mut payload = n.payload.clone();
+    /// let unsupported = Compatibility::new(12, 0).apply(PushType::Alert, &mut payload);
+    /// assert!(payload.interruption_level.is_none());
+    /// assert_eq!(unsupported[0].feature, "target-content-id");
+    /// ```
+    pub fn apply(&self, push_type: PushType, payload: &mut Arc<Payload>) -> Vec<Unsupported> {
+        let mut unsupported = Vec::new();
+        let mut check = |used: bool, feature: &'static str, since: IosVersion| {
+            if used && self.min_ios < since {
+                unsupported.push(Unsupported { feature, since });
+            }
+        };
+        check(push_type == PushType::LiveActivity, "live activities", IOS_16_1);
+        check(payload.target_content_id.is_some(), "target-content-id", IOS_13);
+        let critical = payload.interruption_level == Some(InterruptionLevel::Critical);
+        check(critical || matches!(payload.sound, Some(Sound::Critical(_))), "critical alerts", IOS_12);
+        if let Some(Alert::Payload(ref alert)) = payload.alert {
+            check(alert.summary_arg.is_some() || alert.summary_arg_count.is_some(), "summary-arg", IOS_12);
+        }
+
+        if self.min_ios >= IOS_15 {
+            return unsupported;
+        }
+        let legacy_sound = critical && self.min_ios >= IOS_12 && !matches!(payload.sound, Some(Sound::Critical(_)));
+        if payload.interruption_level.is_some() || payload.relevance_score.is_some() || payload.filter_criteria.is_some() || legacy_sound {
+            let payload = Arc::make_mut(payload);
+            payload.interruption_level = None;
+            payload.relevance_score = None;
+            payload.filter_criteria = None;
+            if legacy_sound {
+                let name = match payload.sound {
+                    Some(Sound::Named(ref name)) => name.clone(),
+                    _ => "default".to_owned(),
+                };
+                payload.sound = Some(Sound::Critical(CriticalSound::new(name, 1.0)));
+            }
+        }
+        unsupported
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use crate::{Compatibility, Environment, ExpirationPolicy, PayloadLimits, RetryBudget, RetryPolicy, SanitizePolicy, SendRate, ThrottlePolicy, TrimPolicy};
+#[cfg(feature = "h2")]
+use crate::H2Options;
+
+
+/// The configuration a client is running with, for logging at startup and
+/// for support requests. See `ApplePushClient::config`.
+///
+/// The signing keys and certificates themselves are left out.
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    /// The environment notifications are sent to unless they say otherwise.
+    pub environment: Environment,
+    /// The API endpoint of `environment`.
+    pub endpoint: &'static str,
+    pub sandbox_fallback: bool,
+    pub team_id: String,
+    /// The id of the key provider tokens are signed with, or `None` for
+    /// clients authenticating with a certificate.
+    pub key_id: Option<String>,
+    /// The id of the key used if APNS refuses the active one.
+    pub secondary_key_id: Option<String>,
+    pub token_refresh_interval: Duration,
+    pub token_refresh_jitter: Duration,
+    pub token_backdate: Duration,
+    pub retry_policy: Option<RetryPolicy>,
+    pub retry_budget: Option<RetryBudget>,
+    pub throttle_policy: Option<ThrottlePolicy>,
+    pub send_rate: Option<SendRate>,
+    pub trim_policy: TrimPolicy,
+    pub sanitize_policy: SanitizePolicy,
+    pub expiration_policy: ExpirationPolicy,
+    pub compatibility: Option<Compatibility>,
+    pub payload_limits: PayloadLimits,
+    pub response_body_limit: usize,
+    /// The limit on concurrent sends, if any.
+    pub concurrency_limit: Option<usize>,
+    /// The limit on buffered payload bytes, if any.
+    pub memory_limit: Option<usize>,
+    /// How many notifications `fire` buffers.
+    pub fire_buffer: usize,
+    /// The connection options of clients created with `new_h2`.
+    #[cfg(feature = "h2")]
+    pub h2: Option<H2Options>,
+}
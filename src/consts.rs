@@ -0,0 +1,95 @@
+//! Canonical protocol values used by the client: endpoints, header names and
+//! size limits.
+
+/// APNS production endpoint.
+pub const APN_URL_PRODUCTION: &str = "https://api.push.apple.com";
+
+/// APNS development endpoint.
+pub const APN_URL_DEV: &str = "https://api.development.push.apple.com";
+
+/// Host name of the APNS production endpoint.
+pub const PRODUCTION_HOST: &str = "api.push.apple.com";
+
+/// Host name of the APNS development endpoint.
+pub const DEVELOPMENT_HOST: &str = "api.development.push.apple.com";
+
+/// Default APNS port.
+pub const PORT: u16 = 443;
+
+/// Alternative APNS port, for networks that block outgoing traffic to 443.
+pub const ALTERNATIVE_PORT: u16 = 2197;
+
+/// Path prefix of the device notification endpoint; the device token follows.
+pub const DEVICE_PATH: &str = "/3/device/";
+
+/// Maximum size in bytes of the JSON payload of a notification.
+pub const MAX_PAYLOAD_SIZE: usize = 4096;
+
+/// Maximum size in bytes of the JSON payload of a VoIP notification.
+pub const MAX_VOIP_PAYLOAD_SIZE: usize = 5120;
+
+/// Maximum size in bytes of a collapse id.
+pub const MAX_COLLAPSE_ID_SIZE: usize = 64;
+
+/// Channel management endpoint of the production environment.
+pub const CHANNELS_URL_PRODUCTION: &str = "https://api-manage-broadcast.push.apple.com:2196";
+
+/// Channel management endpoint of the development environment.
+pub const CHANNELS_URL_DEV: &str = "https://api-manage-broadcast.sandbox.push.apple.com:2195";
+
+/// Host name of the production channel management endpoint.
+pub const CHANNELS_PRODUCTION_HOST: &str = "api-manage-broadcast.push.apple.com";
+
+/// Host name of the development channel management endpoint.
+pub const CHANNELS_DEVELOPMENT_HOST: &str = "api-manage-broadcast.sandbox.push.apple.com";
+
+/// Port of the production channel management endpoint.
+pub const CHANNELS_PRODUCTION_PORT: u16 = 2196;
+
+/// Port of the development channel management endpoint.
+pub const CHANNELS_DEVELOPMENT_PORT: u16 = 2195;
+
+/// Path prefix of the broadcast endpoint; the bundle id follows.
+pub const BROADCAST_PATH: &str = "/4/broadcasts/apps/";
+
+/// Request and response header carrying the id of a broadcast channel.
+pub const APNS_CHANNEL_ID: &str = "apns-channel-id";
+
+/// Maximum number of broadcast channels an app may have open at once.
+pub const MAX_CHANNELS_PER_APP: usize = 10_000;
+
+/// Request header carrying the provider token.
+pub const AUTHORIZATION: &str = "authorization";
+
+/// Request and response header carrying the notification id.
+pub const APNS_ID: &str = "apns-id";
+
+/// Request header carrying the topic.
+pub const APNS_TOPIC: &str = "apns-topic";
+
+/// Request header carrying the expiration, in seconds since the epoch.
+pub const APNS_EXPIRATION: &str = "apns-expiration";
+
+/// Request header carrying the priority.
+pub const APNS_PRIORITY: &str = "apns-priority";
+
+/// Request header carrying the collapse id.
+pub const APNS_COLLAPSE_ID: &str = "apns-collapse-id";
+
+/// Request header carrying the push type.
+pub const APNS_PUSH_TYPE: &str = "apns-push-type";
+
+/// Response header asking to wait that many seconds before trying again.
+pub const RETRY_AFTER: &str = "retry-after";
+
+/// Response header carrying the id under which a notification appears in
+/// the delivery log, in the development environment only.
+pub const APNS_UNIQUE_ID: &str = "apns-unique-id";
+
+/// The notifications page of the CloudKit Console, which holds the delivery
+/// log.
+pub const PUSH_CONSOLE_URL: &str = "https://icloud.developer.apple.com/dashboard/notifications";
+
+/// Key next to `aps` holding the URL of a media attachment. See
+/// `NotificationBuilder::with_media_attachment`.
+pub const MEDIA_URL_KEY: &str = "media-url";
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+use futures::future::{self, BoxFuture};
+use serde_json::Value;
+
+use crate::id::ApnsId;
+
+
+/// Remembers what each notification was sent for, by apns-id, so that an
+/// apns-id turning up later, in a support ticket or the delivery log of the
+/// Push Notifications Console, can be traced back to it. See
+/// `ApplePushClient::send_correlated`.
+pub trait CorrelationStore: Send + Sync {
+    /// Record the metadata of the notification sent as `id`.
+    fn record<'a>(&'a self, id: ApnsId, metadata: Value) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// The metadata recorded for `id`, unless it has expired.
+    fn get<'a>(&'a self, id: &'a ApnsId) -> BoxFuture<'a, Result<Option<Value>, Error>>;
+}
+
+/// A store keeping metadata for `ttl` after it is recorded, within a single
+/// process.
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use std::time::Duration;
+/// use apple_push::{ApnsId, CorrelationStore, MemoryCorrelationStore};
+///
+/// let store = MemoryCorrelationStore::new(Duration::from_secs(3600));
+/// let id: ApnsId = "9c2ec8f1-52a4-4c60-9a41-5e9831e6a9c7".parse().unwrap();
+/// store.record(id, serde_json::json!({ "order": 1234 })).await.unwrap();
+/// assert_eq!(store.get(&id).await.unwrap().unwrap()["order"], 1234);
+/// # });
+/// ```
+pub struct MemoryCorrelationStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<ApnsId, (Instant, Value)>>,
+}
+
+impl MemoryCorrelationStore {
+    pub fn new(ttl: Duration) -> Self {
+        MemoryCorrelationStore {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl CorrelationStore for MemoryCorrelationStore {
+    fn record<'a>(&'a self, id: ApnsId, metadata: Value) -> BoxFuture<'a, Result<(), Error>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (recorded, _)| now.duration_since(*recorded) < self.ttl);
+        entries.insert(id, (now, metadata));
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn get<'a>(&'a self, id: &'a ApnsId) -> BoxFuture<'a, Result<Option<Value>, Error>> {
+        let entries = self.entries.lock().unwrap();
+        let metadata = entries.get(id)
+            .filter(|(recorded, _)| recorded.elapsed() < self.ttl)
+            .map(|(_, metadata)| metadata.clone());
+        Box::pin(future::ready(Ok(metadata)))
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::error::{ApiErrorReason, SendError};
+use crate::health::Health;
+
+
+/// Default number of consecutive `InvalidProviderToken` responses after
+/// which the signing key is considered revoked.
+pub(crate) const DEFAULT_REVOCATION_THRESHOLD: u32 = 5;
+
+/// Tells a revoked key apart from a bad response now and then, by counting
+/// consecutive `InvalidProviderToken` responses.
+pub(crate) struct RevocationTracker {
+    pub threshold: u32,
+    consecutive: AtomicU32,
+    revoked: AtomicBool,
+}
+
+impl RevocationTracker {
+    pub fn new(threshold: u32) -> Self {
+        RevocationTracker {
+            threshold,
+            consecutive: AtomicU32::new(0),
+            revoked: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked.load(Ordering::Relaxed)
+    }
+
+    pub fn health(&self) -> Health {
+        if self.is_revoked() {
+            Health::Unhealthy("APNS keeps refusing the provider token; the signing key may have been revoked".to_owned())
+        } else {
+            Health::Ok
+        }
+    }
+
+    /// Count the outcome of a request. Returns the number of consecutive
+    /// refusals if this one crossed the threshold.
+    pub fn record<T>(&self, result: &Result<T, SendError>) -> Option<u32> {
+        match result {
+            Err(SendError::Api(e)) if matches!(e.reason, ApiErrorReason::InvalidProviderToken) => {
+                let consecutive = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+                if consecutive >= self.threshold && !self.revoked.swap(true, Ordering::Relaxed) {
+                    return Some(consecutive);
+                }
+            }
+            // Anything else APNS answers means it accepted the token.
+            Ok(_) | Err(SendError::Api(_)) => {
+                self.consecutive.store(0, Ordering::Relaxed);
+                self.revoked.store(false, Ordering::Relaxed);
+            }
+            Err(_) => (),
+        }
+        None
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::types::{Notification, Payload, PushType};
+
+
+/// Defaults for the notifications to one topic, so that each one needn't
+/// repeat them. See `ApplePushClient::set_topic_defaults`.
+///
+/// Whatever a notification sets itself wins. The payload defaults only
+/// apply to alert notifications, since keys like `sound` would make
+/// background and Live Activity notifications invalid; the custom keys
+/// apply to every notification.
+///
+/// ```
+/// use apple_push::{NotificationBuilder, Payload, TopicDefaults};
+///
+/// let mut defaults = TopicDefaults::default();
+/// defaults.payload = Payload { category: Some("MESSAGE".to_owned()), ..Payload::default() };
+/// defaults.custom.insert("app".to_owned(), "chat".into());
+///
+/// let mut n = NotificationBuilder::new("com.example.chat", "device-token").title("Hi").build();
+/// defaults.apply(&mut n).unwrap();
+/// assert_eq!(n.payload.category.as_deref(), Some("MESSAGE"));
+/// assert_eq!(n.custom.unwrap()["app"], "chat");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TopicDefaults {
+    /// Merged into the `aps` dictionary of alert notifications.
+    pub payload: Payload,
+    /// Keys sent next to `aps`.
+    pub custom: Map<String, Value>,
+}
+
+impl TopicDefaults {
+    /// Merge the defaults into `n`, keeping what it already sets. Fails if
+    /// the payload defaults can't be merged into its payload.
+    pub fn apply(&self, n: &mut Notification) -> Result<(), serde_json::Error> {
+        if n.effective_push_type() == PushType::Alert {
+            let defaults = serde_json::to_value(&self.payload)?;
+            if defaults.as_object().is_some_and(|defaults| !defaults.is_empty()) {
+                Arc::make_mut(&mut n.payload).merge_raw(defaults)?;
+            }
+        }
+        if !self.custom.is_empty() {
+            let custom = n.custom.get_or_insert_with(Map::new);
+            for (key, value) in &self.custom {
+                custom.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        Ok(())
+    }
+}
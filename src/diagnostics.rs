@@ -0,0 +1,39 @@
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{ApiError, ApiErrorReason, ApplePushClient, Environment, NotificationBuilder, Priority, SendError};
+
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Find out which environment `device_token` belongs to, for admin
+    /// tooling; not meant for the send path. Returns `None` if neither
+    /// environment knows the token.
+    ///
+    /// The token is probed with a silent background notification to
+    /// `topic` in production, then in the sandbox if production refuses
+    /// the token, so the device may receive one. Fails with the error of a
+    /// probe that couldn't tell, e.g. because the provider token was
+    /// refused.
+    pub async fn detect_environment(&self, topic: &str, device_token: &str) -> Result<Option<Environment>, SendError> {
+        for &environment in &[Environment::Production, Environment::Sandbox] {
+            let probe = NotificationBuilder::new(topic, device_token)
+                .content_available()
+                .priority(Priority::Low)
+                .build();
+            match self.send_once_in(probe, environment).await {
+                Ok(_) => return Ok(Some(environment)),
+                // Only tokens of the environment can have been unregistered.
+                Err(SendError::Api(ApiError { reason: ApiErrorReason::Unregistered, .. })) => return Ok(Some(environment)),
+                Err(SendError::Api(ApiError { reason: ApiErrorReason::BadDeviceToken, .. })) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
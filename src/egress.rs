@@ -0,0 +1,107 @@
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::client::HttpConnector;
+use hyper::{service::Service, Uri};
+use tokio::net::TcpStream;
+
+
+/// Default number of consecutive failed connections after which the next
+/// source address is used.
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+type Connecting = Pin<Box<dyn Future<Output = Result<TcpStream, Box<dyn std::error::Error + Send + Sync>>> + Send>>;
+
+/// A connector for hosts with several ways out to the internet, such as
+/// several network interfaces or NAT gateways, which not all reach Apple
+/// equally well.
+///
+/// Connections are made from one source address until connecting fails a
+/// number of times in a row, then from the next; clones share that state.
+/// Wrap it in a TLS connector as usual:
+///
+/// ```no_run
+/// # use apple_push::EgressConnector;
+/// let egress = EgressConnector::new(vec!["10.0.0.2".parse().unwrap(), "10.0.1.2".parse().unwrap()]);
+/// let https = hyper_tls::HttpsConnector::new_with_connector(egress);
+/// let http = hyper::Client::builder().http2_only(true).build(https);
+/// # let key: &[u8] = &[];
+/// let client = apple_push::ApplePushClient::new(http, "TEAMID1234", "KEYID12345", key);
+/// ```
+#[derive(Clone)]
+pub struct EgressConnector {
+    connectors: Arc<Vec<(IpAddr, HttpConnector)>>,
+    current: Arc<AtomicUsize>,
+    failures: Arc<AtomicU32>,
+    failure_threshold: u32,
+}
+
+impl EgressConnector {
+    /// Connect from `addresses`, starting with the first. Panics if there
+    /// are none.
+    pub fn new(addresses: Vec<IpAddr>) -> Self {
+        assert!(!addresses.is_empty(), "EgressConnector needs a source address");
+        let connectors = addresses.into_iter().map(|address| {
+            let mut connector = HttpConnector::new();
+            connector.enforce_http(false);
+            connector.set_nodelay(true);
+            connector.set_local_address(Some(address));
+            (address, connector)
+        }).collect();
+        EgressConnector {
+            connectors: Arc::new(connectors),
+            current: Arc::new(AtomicUsize::new(0)),
+            failures: Arc::new(AtomicU32::new(0)),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+        }
+    }
+
+    /// Set after how many consecutive failed connections the next source
+    /// address is used. Defaults to 3.
+    pub fn set_failure_threshold(&mut self, threshold: u32) {
+        self.failure_threshold = threshold.max(1);
+    }
+
+    /// The source address new connections are made from.
+    pub fn current_address(&self) -> IpAddr {
+        self.connectors[self.current.load(Ordering::SeqCst)].0
+    }
+}
+
+impl Service<Uri> for EgressConnector {
+    type Response = TcpStream;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Connecting;
+
+    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let index = self.current.load(Ordering::SeqCst);
+        let mut connector = self.connectors[index].1.clone();
+        let this = self.clone();
+        Box::pin(async move {
+            match connector.call(uri).await {
+                Ok(stream) => {
+                    this.failures.store(0, Ordering::SeqCst);
+                    Ok(stream)
+                }
+                Err(e) => {
+                    if this.failures.fetch_add(1, Ordering::SeqCst) + 1 >= this.failure_threshold {
+                        // Only the first of several concurrent failures moves on.
+                        let next = (index + 1) % this.connectors.len();
+                        if this.current.compare_exchange(index, next, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                            this.failures.store(0, Ordering::SeqCst);
+                        }
+                    }
+                    Err(e.into())
+                }
+            }
+        })
+    }
+}
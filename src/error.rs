@@ -1,8 +1,18 @@
+#[cfg(feature = "client")]
 use std::io::Read;
+use std::time::{Duration, SystemTime};
+#[cfg(feature = "client")]
+use std::time::UNIX_EPOCH;
 
-use failure::{Error, Fail};
 use serde::{Deserialize, Serialize};
 
+use crate::retry::{AttemptHistory, AttemptRecord};
+use crate::types::PushType;
+
+
+/// An error from outside the crate, such as a store, a connection or a key
+/// that couldn't be used, which the crate passes on.
+pub type Error = Box<dyn ::std::error::Error + Send + Sync>;
 
 /// The reason for a failure returned by the APN api.
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -16,6 +26,7 @@ pub enum ApiErrorReason {
     DeviceTokenNotForTopic,
     DuplicateHeaders,
     IdleTimeout,
+    InvalidPushType,
     MissingDeviceToken,
     MissingTopic,
     PayloadEmpty,
@@ -26,8 +37,11 @@ pub enum ApiErrorReason {
     Forbidden,
     InvalidProviderToken,
     MissingProviderToken,
+    UnrelatedKeyIdInToken,
+    BadEnvironmentKeyInToken,
     BadPath,
     MethodNotAllowed,
+    ExpiredToken,
     Unregistered,
     PayloadTooLarge,
     TooManyProviderTokenUpdates,
@@ -39,7 +53,8 @@ pub enum ApiErrorReason {
 }
 
 impl ApiErrorReason {
-    fn from_str(value: &str) -> Self {
+    #[cfg(feature = "client")]
+    pub(crate) fn from_str(value: &str) -> Self {
         use self::ApiErrorReason::*;
         match value {
             "BadCollapseId" => BadCollapseId,
@@ -51,6 +66,7 @@ impl ApiErrorReason {
             "DeviceTokenNotForTopic" => DeviceTokenNotForTopic,
             "DuplicateHeaders" => DuplicateHeaders,
             "IdleTimeout" => IdleTimeout,
+            "InvalidPushType" => InvalidPushType,
             "MissingDeviceToken" => MissingDeviceToken,
             "MissingTopic" => MissingTopic,
             "PayloadEmpty" => PayloadEmpty,
@@ -61,8 +77,11 @@ impl ApiErrorReason {
             "Forbidden" => Forbidden,
             "InvalidProviderToken" => InvalidProviderToken,
             "MissingProviderToken" => MissingProviderToken,
+            "UnrelatedKeyIdInToken" => UnrelatedKeyIdInToken,
+            "BadEnvironmentKeyInToken" => BadEnvironmentKeyInToken,
             "BadPath" => BadPath,
             "MethodNotAllowed" => MethodNotAllowed,
+            "ExpiredToken" => ExpiredToken,
             "Unregistered" => Unregistered,
             "PayloadTooLarge" => PayloadTooLarge,
             "TooManyProviderTokenUpdates" => TooManyProviderTokenUpdates,
@@ -86,6 +105,7 @@ impl ApiErrorReason {
             DeviceTokenNotForTopic => "DeviceTokenNotForTopic",
             DuplicateHeaders => "DuplicateHeaders",
             IdleTimeout => "IdleTimeout",
+            InvalidPushType => "InvalidPushType",
             MissingDeviceToken => "MissingDeviceToken",
             MissingTopic => "MissingTopic",
             PayloadEmpty => "PayloadEmpty",
@@ -96,8 +116,11 @@ impl ApiErrorReason {
             Forbidden => "Forbidden",
             InvalidProviderToken => "InvalidProviderToken",
             MissingProviderToken => "MissingProviderToken",
+            UnrelatedKeyIdInToken => "UnrelatedKeyIdInToken",
+            BadEnvironmentKeyInToken => "BadEnvironmentKeyInToken",
             BadPath => "BadPath",
             MethodNotAllowed => "MethodNotAllowed",
+            ExpiredToken => "ExpiredToken",
             Unregistered => "Unregistered",
             PayloadTooLarge => "PayloadTooLarge",
             TooManyProviderTokenUpdates => "TooManyProviderTokenUpdates",
@@ -110,11 +133,57 @@ impl ApiErrorReason {
     }
 
     pub fn is_bad_device_token(&self) -> bool {
+        matches!(self, ApiErrorReason::BadDeviceToken)
+    }
+
+    /// Whether sending the same notification again later may succeed: APNS
+    /// was overloaded or shutting down, or the provider token needs
+    /// refreshing first.
+    pub fn is_retriable(&self) -> bool {
+        use self::ApiErrorReason::*;
+        matches!(self, TooManyRequests | InternalServerError | ServiceUnavailable | Shutdown | IdleTimeout
+            | ExpiredProviderToken | TooManyProviderTokenUpdates)
+    }
+
+    /// Whether the device token will never be valid again, so it should be
+    /// removed from storage and not sent to again.
+    ///
+    /// `BadDeviceToken` isn't one of these reasons: it is also what a valid
+    /// token of the other environment gets, such as a TestFlight build's
+    /// sent to production. Only once the sandbox fallback has tried both
+    /// environments does `ApiError::should_remove_token` hold for it; see
+    /// `ApplePushClient::set_sandbox_fallback`.
+    pub fn should_remove_token(&self) -> bool {
+        matches!(self, ApiErrorReason::Unregistered | ApiErrorReason::ExpiredToken)
+    }
+
+    /// What to do about the failure, for the reasons that call for more
+    /// than fixing the notification.
+    ///
+    /// ```
+    /// # use apple_push::ApiErrorReason;
+    /// assert!(ApiErrorReason::TooManyProviderTokenUpdates.remediation().unwrap().contains("20 minutes"));
+    /// assert!(ApiErrorReason::BadDeviceToken.remediation().is_none());
+    /// ```
+    pub fn remediation(&self) -> Option<&'static str> {
         match self {
-            ApiErrorReason::BadDeviceToken => true,
-            _ => false,
+            ApiErrorReason::TooManyProviderTokenUpdates => Some(
+                "provider tokens were refreshed more often than every 20 minutes; share one client, and so one \
+                 token, across the process instead of creating clients per request, and check that tokens aren't \
+                 being invalidated in a loop (see ApplePushClient::token_signings_last_hour)",
+            ),
+            _ => None,
         }
     }
+
+    /// Whether the failure is due to the provider's credentials rather than
+    /// the notification, so that every notification will fail until they
+    /// are fixed.
+    pub fn is_auth_error(&self) -> bool {
+        use self::ApiErrorReason::*;
+        matches!(self, InvalidProviderToken | ExpiredProviderToken | MissingProviderToken | TooManyProviderTokenUpdates
+            | UnrelatedKeyIdInToken | BadEnvironmentKeyInToken | BadCertificate | BadCertificateEnvironment | Forbidden)
+    }
 }
 
 impl ::std::fmt::Display for ApiErrorReason {
@@ -123,64 +192,308 @@ impl ::std::fmt::Display for ApiErrorReason {
     }
 }
 
+/// How to treat an error reason the crate doesn't know, as decided by the
+/// classifier set with `ApplePushClient::set_reason_classifier`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReasonClass {
+    /// Whether sending again later may succeed, so that the retry policy
+    /// retries it.
+    pub retriable: bool,
+    /// Whether the device token will never be valid again.
+    pub remove_token: bool,
+}
+
 /// Error returned by the APN api.
-#[derive(Fail, Serialize, Deserialize, Clone, Debug)]
-#[fail(display = "{} (status: {}", reason, status)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ApiError {
     pub status: u32,
     pub reason: ApiErrorReason,
+    /// For 410 responses, when APNS last confirmed that the device token
+    /// was no longer valid. Tokens registered again since are still valid.
+    #[serde(default)]
+    pub timestamp: Option<SystemTime>,
+    /// How long the response asked to wait before trying again, from its
+    /// `retry-after` header. APNS itself doesn't send one, but proxies in
+    /// front of it may.
+    #[serde(default)]
+    pub retry_after: Option<Duration>,
+    /// For reasons the crate doesn't know, how the reason classifier said
+    /// to treat them, in place of the defaults. Also set for
+    /// `BadDeviceToken` once both environments refused the token.
+    #[serde(default)]
+    pub class: Option<ReasonClass>,
 }
 
+impl ::std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{} (status: {}", self.reason, self.status)
+    }
+}
+
+impl ::std::error::Error for ApiError {}
+
 impl ApiError {
+    pub fn new(status: u32, reason: ApiErrorReason) -> Self {
+        ApiError { status, reason, timestamp: None, retry_after: None, class: None }
+    }
+
+    /// Whether the device token was found invalid after `registered_at`, so
+    /// that a registration made then should be removed. Without a
+    /// timestamp, whether the token should be removed at all.
+    ///
+    /// ```
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// # use apple_push::{ApiError, ApiErrorReason};
+    /// let mut error = ApiError::new(410, ApiErrorReason::Unregistered);
+    /// error.timestamp = Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    /// assert!(error.invalidates_registration(UNIX_EPOCH + Duration::from_secs(1_500_000_000)));
+    /// assert!(!error.invalidates_registration(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+    /// ```
+    pub fn invalidates_registration(&self, registered_at: SystemTime) -> bool {
+        match self.timestamp {
+            Some(timestamp) => self.should_remove_token() && timestamp >= registered_at,
+            None => self.should_remove_token(),
+        }
+    }
+
     pub fn is_bad_device_token(&self) -> bool {
         self.reason.is_bad_device_token()
     }
+
+    /// See `ApiErrorReason::is_retriable`. Server errors with a reason the
+    /// crate doesn't know are retriable too, unless the classifier says
+    /// otherwise.
+    pub fn is_retriable(&self) -> bool {
+        match self.class {
+            Some(class) => class.retriable,
+            None => self.reason.is_retriable() || matches!(self.status, 429 | 500 | 503),
+        }
+    }
+
+    /// See `ApiErrorReason::should_remove_token`, or the classifier for
+    /// reasons the crate doesn't know. A 410 always means the token is no
+    /// longer valid.
+    pub fn should_remove_token(&self) -> bool {
+        self.class.map_or_else(|| self.reason.should_remove_token(), |class| class.remove_token) || self.status == 410
+    }
+
+    /// See `ApiErrorReason::is_auth_error`.
+    pub fn is_auth_error(&self) -> bool {
+        self.reason.is_auth_error()
+    }
 }
 
+#[cfg(feature = "client")]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct ErrorResponse {
     pub reason: String,
+    /// Milliseconds since the epoch, on 410 responses.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub timestamp: Option<u64>,
 }
 
+#[cfg(feature = "client")]
 impl ErrorResponse {
-    pub fn parse_payload<R: Read>(reader: R) -> ApiErrorReason {
+    /// The error APNS answered with `status` and the body read from
+    /// `reader`.
+    pub fn parse_error<R: Read>(status: u32, reader: R) -> ApiError {
         match ::serde_json::from_reader::<_, ErrorResponse>(reader) {
-            Ok(response) => ApiErrorReason::from_str(&response.reason),
-            Err(_) => {
-                let msg = format!("Unknown API response");
-                ApiErrorReason::Other(msg)
-            }
+            Ok(response) => ApiError {
+                status,
+                reason: ApiErrorReason::from_str(&response.reason),
+                timestamp: response.timestamp.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+                retry_after: None,
+                class: None,
+            },
+            Err(_) => ApiError::new(status, ApiErrorReason::Other("Unknown API response".to_string())),
+        }
+    }
+}
+
+/// A notification that was refused before being sent to the APN api.
+#[derive(Clone, Debug)]
+pub enum ValidationError {
+    ControlCharacter { field: &'static str, character: char },
+    PayloadTooLarge { size: usize, limit: usize },
+    /// The notification doesn't meet the requirements of its push type.
+    PushType { push_type: PushType, problem: String },
+    /// A custom key of the notification is one the crate sends itself.
+    ReservedKey { key: String },
+    /// An extra header of the notification can't be sent.
+    ExtraHeader { name: String, problem: &'static str },
+    /// The expiration of the notification makes no sense for its push
+    /// type. See `Notification::expiration_problem`.
+    Expiration { push_type: PushType, problem: &'static str },
+    /// The device token can't be one APNS issued.
+    DeviceToken { problem: &'static str },
+}
+
+impl ::std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            ValidationError::ControlCharacter { field, character } => write!(f, "alert {} contains control character {:?}", field, character),
+            ValidationError::PayloadTooLarge { size, limit } => write!(f, "payload is {} bytes (must be at most {})", size, limit),
+            ValidationError::PushType { push_type, problem } => write!(f, "{} notifications {}", push_type, problem),
+            ValidationError::ReservedKey { key } => write!(f, "custom key {:?} is reserved", key),
+            ValidationError::ExtraHeader { name, problem } => write!(f, "extra header {:?} {}", name, problem),
+            ValidationError::Expiration { push_type, problem } => write!(f, "{} notifications {}", push_type, problem),
+            ValidationError::DeviceToken { problem } => write!(f, "device token {}", problem),
+        }
+    }
+}
+
+impl ::std::error::Error for ValidationError {}
+
+/// Why `NotificationBuilder::try_build` refused a notification.
+#[derive(Debug)]
+pub struct BuildError {
+    pub error: ValidationError,
+    /// The request body the notification would be sent with, pretty-printed,
+    /// so that the field at fault can be picked out.
+    pub preview: String,
+}
+
+impl ::std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{}, in payload:\n{}", self.error, self.preview)
+    }
+}
+
+impl ::std::error::Error for BuildError {}
+
+/// Why a client stopped the sends that were waiting to go out. See
+/// `SendError::ClientClosed`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    /// `ApplePushClient::shutdown` was called.
+    Shutdown,
+    /// APNS refused so many provider tokens signed with the key `kid` in a
+    /// row that it looks revoked. See `Event::CredentialRevoked`.
+    CredentialRevoked { kid: String },
+}
+
+impl ::std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            CloseReason::Shutdown => write!(f, "shut down"),
+            CloseReason::CredentialRevoked { kid } => write!(f, "key {} looks revoked", kid),
         }
     }
 }
 
-#[derive(Fail, Debug)]
+#[derive(Debug)]
 pub enum SendError {
-    #[fail(display = "{}", _0)]
     Api(ApiError),
-    #[fail(display = "{}", _0)]
+    Validation(ValidationError),
+    Cancelled,
+    /// The send was still waiting to go out, for a concurrency permit, a
+    /// throttle, the send rate or a retry, when the client was closed.
+    ClientClosed(CloseReason),
+    /// The rate limiter refused the send; the device token is below its
+    /// limit again after `retry_after`.
+    RateLimited { retry_after: Duration },
+    /// The notification expired while it waited to go out, e.g. in the
+    /// `fire` buffer or for a retry during an outage, so it was dropped
+    /// rather than sent for APNS to discard.
+    Expired { expiration: u64, late_by: u64 },
+    /// A policy vetoed the notification, for the reason it gave. See
+    /// `ApplePushClient::add_policy`.
+    Vetoed(String),
+    /// The response was longer than the limit set with
+    /// `ApplePushClient::set_response_body_limit`, and was dropped.
+    ResponseTooLarge { limit: usize },
+    /// Connecting to or communicating with APNS failed.
+    Transport(Error),
+    /// The send failed with retries enabled.
+    Retried(AttemptHistory),
     Other(Error),
 }
 
+impl ::std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            SendError::Api(e) => write!(f, "{}", e),
+            SendError::Validation(e) => write!(f, "{}", e),
+            SendError::Cancelled => f.write_str("send cancelled"),
+            SendError::ClientClosed(reason) => write!(f, "client closed: {}", reason),
+            SendError::RateLimited { retry_after } => write!(f, "rate limited (retry after {:?})", retry_after),
+            SendError::Expired { late_by, .. } => write!(f, "expired {} seconds before it could be sent", late_by),
+            SendError::Vetoed(reason) => write!(f, "vetoed: {}", reason),
+            SendError::ResponseTooLarge { limit } => write!(f, "response body is over {} bytes", limit),
+            SendError::Transport(e) => write!(f, "{}", e),
+            SendError::Retried(history) => write!(f, "{}", history),
+            SendError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for SendError {}
+
 impl SendError {
     pub fn as_api_error(&self) -> Option<&ApiError> {
         match self {
             SendError::Api(ref e) => Some(e),
+            SendError::Retried(ref history) => history.last_error().as_api_error(),
             _ => None,
         }
     }
 
     pub fn is_bad_device_token(&self) -> bool {
+        self.as_api_error().is_some_and(ApiError::is_bad_device_token)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
         match self {
-            SendError::Api(ref e) => e.is_bad_device_token(),
+            SendError::Cancelled => true,
+            SendError::Retried(ref history) => history.last_error().is_cancelled(),
             _ => false,
         }
     }
+
+    /// Whether sending the same notification again later may succeed: it
+    /// couldn't reach APNS, was rate limited, or failed with an API error
+    /// for which `ApiError::is_retriable` holds.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            SendError::Api(e) => e.is_retriable(),
+            SendError::Transport(_) | SendError::RateLimited { .. } => true,
+            SendError::Retried(history) => history.last_error().is_retriable(),
+            _ => false,
+        }
+    }
+
+    /// Whether the device token should be removed; see
+    /// `ApiError::should_remove_token`.
+    pub fn should_remove_token(&self) -> bool {
+        self.as_api_error().is_some_and(ApiError::should_remove_token)
+    }
+
+    /// Whether the failure is due to the provider's credentials; see
+    /// `ApiErrorReason::is_auth_error`.
+    pub fn is_auth_error(&self) -> bool {
+        self.as_api_error().is_some_and(ApiError::is_auth_error)
+    }
+
+    /// The attempts made to send the notification, if retries were enabled.
+    pub fn attempts(&self) -> &[AttemptRecord] {
+        match self {
+            SendError::Retried(ref history) => &history.attempts,
+            _ => &[],
+        }
+    }
 }
 
+#[cfg(feature = "client")]
 impl From<hyper::Error> for SendError {
     fn from(e: hyper::Error) -> Self {
-        SendError::Other(e.into())
+        SendError::Transport(e.into())
+    }
+}
+
+#[cfg(feature = "h2")]
+impl From<h2::Error> for SendError {
+    fn from(e: h2::Error) -> Self {
+        SendError::Transport(e.into())
     }
 }
 
@@ -202,12 +515,18 @@ impl From<serde_json::Error> for SendError {
     }
 }
 
-impl From<failure::Error> for SendError {
-    fn from(e: failure::Error) -> Self {
+impl From<Error> for SendError {
+    fn from(e: Error) -> Self {
         SendError::Other(e)
     }
 }
 
+impl From<ValidationError> for SendError {
+    fn from(e: ValidationError) -> Self {
+        SendError::Validation(e)
+    }
+}
+
 impl From<ApiError> for SendError {
     fn from(e: ApiError) -> Self {
         SendError::Api(e)
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::broadcast;
+
+use crate::compat::IosVersion;
+use crate::types::{InterruptionLevel, PushType};
+
+
+/// How many events a subscriber can fall behind before missing some.
+const EVENT_BUFFER: usize = 64;
+
+/// Something that happened to the client which operators may want to be
+/// alerted about. See `ApplePushClient::events`.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// APNS refused tokens signed with the primary key, and the client
+    /// switched to the secondary key.
+    KeyFailover { from_kid: String, to_kid: String },
+    /// APNS refused `consecutive` provider tokens signed with the key `kid`
+    /// in a row, which most likely means the key was revoked rather than
+    /// that APNS is misbehaving. See
+    /// `ApplePushClient::set_revocation_threshold`.
+    CredentialRevoked { kid: String, consecutive: u32 },
+    /// A time-sensitive or critical notification to `topic` was explicitly
+    /// given priority 5, so the device may not present it as urgently as
+    /// its interruption level asks. It was sent as it was.
+    ConflictingPriority { topic: String, interruption_level: InterruptionLevel },
+    /// The client certificate expires within the window given to
+    /// `ApplePushClient::set_certificate_expiry_warning`. Repeated hourly
+    /// while sends continue.
+    CertificateExpiring { expires_at: SystemTime, expires_in: Duration },
+    /// A notification to `topic` used a feature that the oldest version of
+    /// iOS given to `ApplePushClient::set_compatibility` doesn't support,
+    /// so some devices won't present it as intended. It was sent as it was.
+    UnsupportedFeature { topic: String, feature: &'static str, since: IosVersion },
+    /// A notification to `topic` had an expiration that makes no sense for
+    /// its push type. It was sent as it was. See
+    /// `ApplePushClient::set_expiration_policy`.
+    UnsuitableExpiration { topic: String, push_type: PushType, problem: &'static str },
+    /// The client signed `last_hour` provider tokens in the last hour,
+    /// more than the one every 20 minutes APNS wants, so it may start
+    /// refusing them with `TooManyProviderTokenUpdates`. Emitted on each
+    /// signing over the limit.
+    FrequentTokenSigning { kid: String, last_hour: usize },
+    /// APNS refused the provider token signed with the key `kid` with
+    /// `TooManyProviderTokenUpdates`, so the client keeps sending the token
+    /// it has for `duration` instead of signing new ones, even if they are
+    /// due or invalidated.
+    TokenCoolDown { kid: String, duration: Duration },
+}
+
+/// Delivers events to every subscriber.
+pub(crate) struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus { sender: broadcast::channel(EVENT_BUFFER).0 }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Send `event` to the current subscribers, if there are any.
+    pub fn emit(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::error::ValidationError;
+use crate::types::Notification;
+
+
+/// What to do with notifications whose expiration makes no sense for their
+/// push type, such as a VoIP notification kept for an hour. See
+/// `Notification::expiration_problem`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ExpirationPolicy {
+    /// Send them without checking.
+    Allow,
+    /// Send them, emitting `Event::UnsuitableExpiration`.
+    #[default]
+    Warn,
+    /// Refuse to send them, with `ValidationError::Expiration`.
+    Reject,
+}
+
+impl ExpirationPolicy {
+    /// Check the expiration of `n`, sent at `now`. Returns the problem to
+    /// warn about, if any.
+    pub(crate) fn check(self, n: &Notification, now: u64) -> Result<Option<&'static str>, ValidationError> {
+        if self == ExpirationPolicy::Allow {
+            return Ok(None);
+        }
+        match n.expiration_problem(now) {
+            Some(problem) if self == ExpirationPolicy::Reject => {
+                Err(ValidationError::Expiration { push_type: n.effective_push_type(), problem })
+            }
+            problem => Ok(problem),
+        }
+    }
+}
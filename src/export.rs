@@ -0,0 +1,87 @@
+//! Delivery outcomes of batches as CSV, for analysing deliverability in a
+//! spreadsheet or data warehouse.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use ring::digest;
+
+use crate::batch::BatchReport;
+use crate::error::SendError;
+use crate::id::ApnsId;
+
+
+/// Writes one CSV row per device token of a batch: the SHA-256 of the
+/// token in hex, so that rows can be joined with the registrations of a
+/// token without exporting it; the HTTP status, empty if the send never got
+/// a response; the reason, an APNS error reason or else a description of
+/// the failure; and the latency in milliseconds.
+///
+/// ```
+/// use apple_push::{ApiError, ApiErrorReason, OutcomeWriter, SendError};
+///
+/// let mut csv = Vec::new();
+/// let mut writer = OutcomeWriter::new(&mut csv).unwrap();
+/// let unregistered = Err(SendError::Api(ApiError::new(410, ApiErrorReason::Unregistered)));
+/// writer.write("00fc13adff785122", &unregistered, None).unwrap();
+/// let csv = String::from_utf8(csv).unwrap();
+/// assert_eq!(csv.lines().next(), Some("token_sha256,status,reason,latency_ms"));
+/// assert!(csv.lines().nth(1).unwrap().ends_with(",410,Unregistered,"));
+/// ```
+pub struct OutcomeWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> OutcomeWriter<W> {
+    /// Write the header row to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "token_sha256,status,reason,latency_ms")?;
+        Ok(OutcomeWriter { writer })
+    }
+
+    /// Write the row of the result for `token`.
+    pub fn write(&mut self, token: &str, result: &Result<ApnsId, SendError>, latency: Option<Duration>) -> io::Result<()> {
+        let hash: String = digest::digest(&digest::SHA256, token.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        let (status, reason) = match result {
+            Ok(_) => (Some(200), String::new()),
+            Err(e) => match e.as_api_error() {
+                Some(api) => (Some(api.status), api.reason.to_string()),
+                None => (None, e.to_string()),
+            },
+        };
+        writeln!(
+            self.writer,
+            "{},{},{},{}",
+            hash,
+            status.map(|status| status.to_string()).unwrap_or_default(),
+            escape(&reason),
+            latency.map(|latency| format!("{:.3}", latency.as_secs_f64() * 1000.0)).unwrap_or_default(),
+        )
+    }
+
+    /// The writer, once every row is written.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl BatchReport {
+    /// Write the outcome of every device token of the batch to `writer` as
+    /// CSV. See `OutcomeWriter`.
+    pub fn write_csv<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = OutcomeWriter::new(writer)?;
+        for (i, (token, result)) in self.results.iter().enumerate() {
+            writer.write(token, result, self.latencies.get(i).copied())?;
+        }
+        writer.into_inner().flush()
+    }
+}
+
+/// Quote `field` if it holds a separator, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
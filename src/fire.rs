@@ -0,0 +1,84 @@
+use std::sync::{Arc, Weak};
+
+use futures::channel::mpsc;
+use futures::stream::StreamExt;
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::memory::{payload_size, Reservation};
+use crate::queues::Entry;
+use crate::metrics::names;
+use crate::{ApplePushClient, Notification};
+
+
+/// Default number of notifications `fire` buffers before dropping.
+pub(crate) const DEFAULT_FIRE_BUFFER: usize = 1024;
+
+/// How many buffered notifications are sent at once.
+const FIRE_CONCURRENCY: usize = 16;
+
+/// A buffered notification, the memory reserved for it, and its place in
+/// the queue stats.
+pub(crate) type Queued = (Notification, Option<Reservation>, Entry);
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Set how many notifications `fire` buffers before it starts dropping
+    /// them. Defaults to 1024. Has no effect after the first call to `fire`.
+    pub fn set_fire_buffer(&mut self, capacity: usize) {
+        self.fire_buffer = capacity;
+    }
+
+    /// Queue a notification to be sent in the background, and return
+    /// immediately. For best-effort notifications whose result nobody waits
+    /// for: failures are only reported through metrics.
+    ///
+    /// If the buffer is full, or the notification doesn't fit in the memory
+    /// limit, the notification is dropped and counted in the
+    /// `fire.dropped` metric. Returns whether it was queued.
+    ///
+    /// The first call starts a task on the current tokio runtime, which
+    /// stops once the client is dropped or `shutdown`.
+    pub fn fire(self: &Arc<Self>, n: Notification) -> bool {
+        let mut fire = self.fire.lock().unwrap();
+        let sender = fire.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel(self.fire_buffer);
+            self.tasks.spawn(Self::fire_worker(Arc::downgrade(self), receiver));
+            sender
+        });
+        let tags = self.tags(&n);
+        let reservation = match self.memory {
+            Some(ref memory) => match memory.try_reserve(payload_size(&n)) {
+                Some(reservation) => Some(reservation),
+                None => {
+                    self.count(names::FIRE_DROPPED, tags.as_ref());
+                    return false;
+                }
+            },
+            None => None,
+        };
+        let queued = sender.try_send((n, reservation, self.fire_queue.enter())).is_ok();
+        self.count(if queued { names::FIRE_ENQUEUED } else { names::FIRE_DROPPED }, tags.as_ref());
+        queued
+    }
+
+    async fn fire_worker(client: Weak<Self>, receiver: mpsc::Receiver<Queued>) {
+        receiver.for_each_concurrent(FIRE_CONCURRENCY, |(n, reservation, entry)| {
+            let client = client.upgrade();
+            drop(entry);
+            async move {
+                if let Some(client) = client {
+                    let tags = client.tags(&n);
+                    if client.send_limited(n, reservation).await.is_err() {
+                        client.count(names::FIRE_FAILED, tags.as_ref());
+                    }
+                }
+            }
+        }).await
+    }
+}
@@ -0,0 +1,141 @@
+//! An HTTP front end to a client, for sending notifications from services
+//! that aren't written in Rust.
+//!
+//! The gateway has a single endpoint, `POST /v1/send`, taking the JSON
+//! serialization of a `Notification`. It answers `200` with
+//! `{"id":"<apns-id>"}` once APNS accepted the notification. Errors are
+//! answered with `{"error":"<message>"}`, plus `"reason"` for errors returned
+//! by APNS, and with:
+//!
+//! * the status APNS answered with, for errors returned by APNS
+//! * `400` for notifications that aren't valid JSON or were refused before
+//!   being sent
+//! * `403` for notifications vetoed by a policy
+//! * `413` for request bodies over `MAX_BODY_SIZE`
+//! * `429` for sends refused by the client's rate limiter
+//! * `502` if APNS couldn't be reached, or answered with more than the
+//!   response body limit
+//! * `504` for notifications that expired before they could be sent
+//! * `500` for anything else
+//!
+//! ```no_run
+//! # async fn run(client: apple_push::ApplePushClient<hyper::client::HttpConnector>) -> Result<(), hyper::Error> {
+//! use std::sync::Arc;
+//!
+//! apple_push::gateway::serve(Arc::new(client), ([127, 0, 0, 1], 8080).into()).await
+//! # }
+//! ```
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::{body::HttpBody, Body, client::connect::Connection, Method, Request, Response, Server, service::{make_service_fn, service_fn, Service}, StatusCode, Uri};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::consts::MAX_VOIP_PAYLOAD_SIZE;
+use crate::{ApnsId, ApplePushClient, Notification, SendError};
+
+
+/// Path of the send endpoint.
+pub const SEND_PATH: &str = "/v1/send";
+
+/// The longest request body the gateway reads, leaving room around the
+/// largest payload APNS accepts for the rest of the notification.
+pub const MAX_BODY_SIZE: usize = 4 * MAX_VOIP_PAYLOAD_SIZE;
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Reply {
+    Sent { id: ApnsId },
+    Failed {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+}
+
+/// Serve the gateway on `addr` until the server fails.
+pub async fn serve<S>(client: Arc<ApplePushClient<S>>, addr: SocketAddr) -> Result<(), hyper::Error>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let make_service = make_service_fn(move |_| {
+        let client = client.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let client = client.clone();
+                async move { Ok::<_, Infallible>(handle(&client, req).await) }
+            }))
+        }
+    });
+    Server::try_bind(&addr)?.serve(make_service).await
+}
+
+/// Answer a single request to the gateway, for mounting it in an existing
+/// hyper server.
+pub async fn handle<S>(client: &ApplePushClient<S>, req: Request<Body>) -> Response<Body>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    if req.uri().path() != SEND_PATH {
+        return reply(StatusCode::NOT_FOUND, failed("not found", None));
+    }
+    if req.method() != Method::POST {
+        return reply(StatusCode::METHOD_NOT_ALLOWED, failed("method not allowed", None));
+    }
+    let mut body = Vec::new();
+    let mut chunks = req.into_body();
+    while let Some(chunk) = chunks.data().await {
+        match chunk {
+            Ok(chunk) if body.len() + chunk.len() > MAX_BODY_SIZE => {
+                let error = format!("request body is over {} bytes", MAX_BODY_SIZE);
+                return reply(StatusCode::PAYLOAD_TOO_LARGE, failed(error, None));
+            }
+            Ok(chunk) => body.extend_from_slice(&chunk),
+            Err(e) => return reply(StatusCode::BAD_REQUEST, failed(e, None)),
+        }
+    }
+    let n = match serde_json::from_slice::<Notification>(&body) {
+        Ok(n) => n,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, failed(format!("invalid notification: {}", e), None)),
+    };
+    match client.send(n).await {
+        Ok(id) => reply(StatusCode::OK, Reply::Sent { id }),
+        Err(e) => {
+            let reason = e.as_api_error().map(|e| e.reason.to_string());
+            reply(status_of(&e), failed(e, reason))
+        }
+    }
+}
+
+fn failed(error: impl ToString, reason: Option<String>) -> Reply {
+    Reply::Failed { error: error.to_string(), reason }
+}
+
+fn status_of(error: &SendError) -> StatusCode {
+    match error {
+        SendError::Api(e) => StatusCode::from_u16(e.status as u16).unwrap_or(StatusCode::BAD_GATEWAY),
+        SendError::Validation(_) => StatusCode::BAD_REQUEST,
+        SendError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        SendError::Expired { .. } => StatusCode::GATEWAY_TIMEOUT,
+        SendError::Vetoed(_) => StatusCode::FORBIDDEN,
+        SendError::Transport(_) | SendError::ResponseTooLarge { .. } => StatusCode::BAD_GATEWAY,
+        SendError::Retried(history) => status_of(history.last_error()),
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn reply(status: StatusCode, reply: Reply) -> Response<Body> {
+    let mut response = Response::new(Body::from(serde_json::to_vec(&reply).unwrap_or_default()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("application/json"));
+    response
+}
@@ -0,0 +1,74 @@
+//! A process-wide client, for applications that would rather reach for a
+//! static than pass a client around.
+//!
+//! Configure a client at startup and hand it to `init`; everything else
+//! reaches it with `client`. Only the first `init` takes effect, even when
+//! several threads race to it, so a library calling `init` can't replace
+//! the client the application set up:
+//!
+//! ```
+//! use apple_push::{global, ApplePushClient};
+//!
+//! let client = ApplePushClient::new_certificate_auth(hyper::Client::new());
+//! global::init(client).unwrap();
+//! assert!(global::init(ApplePushClient::new_certificate_auth(hyper::Client::new())).is_err());
+//! assert_eq!(global::client().health(), global::try_client().unwrap().health());
+//! ```
+//!
+//! Call `shutdown` before the process exits, so that notifications queued
+//! with `fire` go out.
+
+use std::sync::OnceLock;
+
+use crate::handle::ApnsHandle;
+
+
+static CLIENT: OnceLock<ApnsHandle> = OnceLock::new();
+
+/// `init` was called after the global client was set.
+#[derive(Debug)]
+pub struct AlreadyInitialized;
+
+impl ::std::fmt::Display for AlreadyInitialized {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str("the global APNS client is already initialized")
+    }
+}
+
+impl ::std::error::Error for AlreadyInitialized {}
+
+/// Set the global client, unless it is already set, in which case `client`
+/// is dropped and the one set first is kept.
+pub fn init<C: Into<ApnsHandle>>(client: C) -> Result<(), AlreadyInitialized> {
+    CLIENT.set(client.into()).map_err(|_| AlreadyInitialized)
+}
+
+/// The global client, setting it to the client `init` returns if it isn't
+/// set yet. `init` is only called once, even if several threads get here
+/// at the same time; the others wait for it.
+pub fn client_or_init<C: Into<ApnsHandle>, F: FnOnce() -> C>(init: F) -> &'static ApnsHandle {
+    CLIENT.get_or_init(|| init().into())
+}
+
+/// The global client.
+///
+/// # Panics
+///
+/// If `init` wasn't called first; see `try_client`.
+pub fn client() -> &'static ApnsHandle {
+    try_client().expect("apple_push::global::init wasn't called")
+}
+
+/// The global client, if it is set.
+pub fn try_client() -> Option<&'static ApnsHandle> {
+    CLIENT.get()
+}
+
+/// Shut the global client down, if it is set. See
+/// `ApplePushClient::shutdown`. It stays set, and sends through it fail with
+/// `SendError::ClientClosed` from then on.
+pub async fn shutdown() {
+    if let Some(client) = try_client() {
+        client.shutdown().await;
+    }
+}
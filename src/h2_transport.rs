@@ -0,0 +1,338 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use futures::future::{self, poll_fn, Either};
+use h2::client::SendRequest;
+use h2::{Ping, PingPong, Reason};
+use http::request::Parts;
+use hyper::{client::connect::Connection, service::Service, Request, Response, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Mutex, Notify, Semaphore};
+
+use crate::error::SendError;
+use crate::tasks::TaskSet;
+use crate::timings::{Stopwatch, Timings};
+use crate::transport;
+
+
+/// Options for the direct HTTP/2 transport used by `ApplePushClient::new_h2`.
+#[derive(Clone, Debug)]
+pub struct H2Options {
+    /// Maximum number of requests in flight on the connection at once, in
+    /// addition to the limit APNS advertises. `None` uses APNS' limit alone.
+    pub max_streams: Option<usize>,
+    /// Initial flow-control window of each stream, in bytes.
+    pub initial_window_size: Option<u32>,
+    /// How often to PING each connection, keeping it alive and detecting
+    /// dead connections. `None` disables keep-alive PINGs.
+    pub ping_interval: Option<Duration>,
+    /// How many connections to keep open. Connections are opened as
+    /// requests are sent until there are this many, and requests are then
+    /// spread over them in turn. `max_streams` applies to the whole pool.
+    pub pool_size: usize,
+    /// How long a connection may go without requests before it is closed
+    /// and replaced by a new one on the next request, since APNS drops
+    /// idle connections without always telling. `None` keeps connections
+    /// until they close.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for H2Options {
+    fn default() -> Self {
+        H2Options {
+            max_streams: None,
+            initial_window_size: None,
+            ping_interval: Some(Duration::from_secs(60)),
+            pool_size: 1,
+            idle_timeout: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+struct H2Connection {
+    authority: String,
+    sender: SendRequest<Bytes>,
+    ping_pong: Option<Arc<Mutex<PingPong>>>,
+    closed: Arc<AtomicBool>,
+    /// Closes the connection once its requests are done.
+    stop: Arc<Notify>,
+    last_used: Instant,
+}
+
+impl H2Connection {
+    /// Whether the connection can't be used for `authority` any more.
+    fn is_stale(&self, authority: &str, idle_timeout: Option<Duration>, now: Instant) -> bool {
+        self.authority != authority
+            || self.closed.load(Ordering::SeqCst)
+            || idle_timeout.is_some_and(|timeout| now.duration_since(self.last_used) >= timeout)
+    }
+
+    /// Stop using the connection. It closes once the requests in flight on
+    /// it are done.
+    fn close(self) {
+        let stop = self.stop.clone();
+        // h2 only closes a connection once every handle to it is gone.
+        drop(self);
+        stop.notify();
+    }
+}
+
+/// Why a request failed.
+enum Failure {
+    /// The request never reached APNS, because the connection was closing
+    /// (after a GOAWAY, say), so it can be sent again on another one.
+    Unsent(SendError),
+    Failed(SendError),
+}
+
+impl<E: Into<SendError>> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Failed(e.into())
+    }
+}
+
+/// A transport speaking HTTP/2 directly over the streams of a connector,
+/// keeping a pool of connections open and replacing them when they close.
+pub(crate) struct H2Transport<S> {
+    connector: S,
+    pub options: H2Options,
+    streams: Option<Semaphore>,
+    connections: Mutex<Vec<H2Connection>>,
+    /// Held while connecting, so that one connection is opened at a time
+    /// without holding `connections`.
+    connecting: Mutex<()>,
+    next: AtomicUsize,
+    tasks: Arc<TaskSet>,
+}
+
+impl<S> H2Transport<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub fn new(connector: S, options: H2Options, tasks: Arc<TaskSet>) -> Self {
+        H2Transport {
+            connector,
+            streams: options.max_streams.map(Semaphore::new),
+            options,
+            connections: Mutex::new(Vec::new()),
+            connecting: Mutex::new(()),
+            next: AtomicUsize::new(0),
+            tasks,
+        }
+    }
+
+    /// A handle to a connection to the authority of `uri`, connecting first
+    /// if the pool isn't full, and whether it was already open.
+    async fn sender(&self, uri: &Uri) -> Result<(SendRequest<Bytes>, Arc<AtomicBool>, bool), SendError> {
+        let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+        // While another send connects, use the connections already open
+        // rather than waiting for it.
+        let busy = self.connecting.try_lock().is_err();
+        if let Some(handle) = self.pooled(authority, busy).await {
+            return Ok(handle);
+        }
+        let _connecting = self.connecting.lock().await;
+        // The pool may have filled up while this send waited.
+        if let Some(handle) = self.pooled(authority, false).await {
+            return Ok(handle);
+        }
+
+        let connection = self.connect(uri, authority).await?;
+        let handle = (connection.sender.clone(), connection.closed.clone(), false);
+        self.connections.lock().await.push(connection);
+        Ok(handle)
+    }
+
+    /// A handle to an open connection to `authority`, closing stale ones,
+    /// unless the pool has room for another and `any` isn't set.
+    async fn pooled(&self, authority: &str, any: bool) -> Option<(SendRequest<Bytes>, Arc<AtomicBool>, bool)> {
+        let now = Instant::now();
+        let mut connections = self.connections.lock().await;
+        let (stale, open): (Vec<_>, Vec<_>) = connections.drain(..)
+            .partition(|c| c.is_stale(authority, self.options.idle_timeout, now));
+        *connections = open;
+        stale.into_iter().for_each(H2Connection::close);
+        if connections.is_empty() || (connections.len() < self.options.pool_size && !any) {
+            return None;
+        }
+        let next = self.next.fetch_add(1, Ordering::Relaxed) % connections.len();
+        let c = &mut connections[next];
+        c.last_used = now;
+        Some((c.sender.clone(), c.closed.clone(), true))
+    }
+
+    async fn connect(&self, uri: &Uri, authority: &str) -> Result<H2Connection, SendError> {
+        let root = Uri::builder()
+            .scheme(uri.scheme_str().unwrap_or("https"))
+            .authority(authority)
+            .path_and_query("/")
+            .build()?;
+        let mut connector = self.connector.clone();
+        poll_fn(|cx| connector.poll_ready(cx)).await.map_err(connect_error)?;
+        let io = connector.call(root).await.map_err(connect_error)?;
+
+        let mut builder = h2::client::Builder::new();
+        if let Some(size) = self.options.initial_window_size {
+            builder.initial_window_size(size);
+        }
+        let (sender, mut conn) = builder.handshake::<_, Bytes>(io).await?;
+        let ping_pong = conn.ping_pong().map(|p| Arc::new(Mutex::new(p)));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let stop = Arc::new(Notify::new());
+        let stop_pings = Arc::new(Notify::new());
+
+        let conn_closed = closed.clone();
+        let conn_stop = stop.clone();
+        let conn_stop_pings = stop_pings.clone();
+        self.tasks.spawn(async move {
+            let stopped = conn_stop.notified();
+            futures::pin_mut!(stopped);
+            if let Either::Right((_, conn)) = future::select(conn, stopped).await {
+                // Polling the connection again lets it notice that it has
+                // no handles left, and close once its streams are done.
+                conn_stop_pings.notify();
+                let _ = conn.await;
+            }
+            conn_closed.store(true, Ordering::SeqCst);
+            conn_stop_pings.notify();
+        });
+        if let (Some(interval), Some(ping_pong)) = (self.options.ping_interval, ping_pong.clone()) {
+            let conn_closed = closed.clone();
+            let stop = stop_pings;
+            self.tasks.spawn(async move {
+                while !conn_closed.load(Ordering::SeqCst) {
+                    let delay = tokio::time::delay_for(interval);
+                    let stopped = stop.notified();
+                    futures::pin_mut!(stopped);
+                    if let Either::Right(_) = future::select(delay, stopped).await {
+                        break;
+                    }
+                    if ping_pong.lock().await.ping(Ping::opaque()).await.is_err() {
+                        conn_closed.store(true, Ordering::SeqCst);
+                    }
+                }
+            });
+        }
+
+        Ok(H2Connection {
+            authority: authority.to_owned(),
+            sender,
+            ping_pong,
+            closed,
+            stop,
+            last_used: Instant::now(),
+        })
+    }
+
+    pub async fn request(&self, req: Request<Vec<u8>>, body_limit: usize, timings: &mut Timings) -> Result<Response<Bytes>, SendError> {
+        let mut stopwatch = Stopwatch::start();
+        let _permit = match self.streams {
+            Some(ref streams) => Some(streams.acquire().await),
+            None => None,
+        };
+        timings.queue_wait += stopwatch.lap();
+
+        let (parts, body) = req.into_parts();
+        let body = Bytes::from(body);
+        let mut resent = false;
+        loop {
+            let (sender, closed, reused) = self.sender(&parts.uri).await?;
+            timings.connect = Some(stopwatch.lap());
+            timings.reused_connection = Some(reused);
+            match self.send_on(sender, &parts, body.clone(), body_limit, timings, &mut stopwatch).await {
+                Ok(response) => return Ok(response),
+                // A request refused by a connection that was already open
+                // is sent once more, on a new connection.
+                Err(Failure::Unsent(_)) if reused && !resent => {
+                    closed.store(true, Ordering::SeqCst);
+                    resent = true;
+                }
+                Err(Failure::Unsent(e)) | Err(Failure::Failed(e)) => return Err(e),
+            }
+        }
+    }
+
+    async fn send_on(
+        &self,
+        sender: SendRequest<Bytes>,
+        parts: &Parts,
+        body: Bytes,
+        body_limit: usize,
+        timings: &mut Timings,
+        stopwatch: &mut Stopwatch,
+    ) -> Result<Response<Bytes>, Failure> {
+        // Waiting for the connection to allow another stream.
+        let mut sender = sender.ready().await.map_err(unsent)?;
+        timings.queue_wait += stopwatch.lap();
+
+        let (response, mut stream) = sender.send_request(head(parts), body.is_empty()).map_err(unsent)?;
+        if !body.is_empty() {
+            stream.send_data(body, true)?;
+        }
+        timings.request_write = Some(stopwatch.lap());
+
+        let response = response.await.map_err(|e| match e.reason() {
+            Some(Reason::REFUSED_STREAM) => unsent(e),
+            _ => Failure::Failed(e.into()),
+        })?;
+        let (parts, mut body) = response.into_parts();
+        timings.ttfb = stopwatch.lap();
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            body.flow_control().release_capacity(chunk.len())?;
+            transport::extend_limited(&mut bytes, &chunk, body_limit)?;
+        }
+        timings.body_read = stopwatch.lap();
+        Ok(Response::from_parts(parts, bytes.freeze()))
+    }
+
+    /// Drop the open connections, so the next request reconnects. They
+    /// close once the requests in flight on them are done.
+    pub async fn reset(&self) {
+        self.connections.lock().await.drain(..).for_each(H2Connection::close);
+    }
+
+    /// The number of connections open.
+    pub async fn open_connections(&self) -> usize {
+        self.connections.lock().await.iter().filter(|c| !c.closed.load(Ordering::SeqCst)).count()
+    }
+
+    /// PING an open connection and return the round trip time.
+    pub async fn ping(&self) -> Result<Duration, SendError> {
+        let ping_pong = self.connections.lock().await.iter()
+            .filter(|c| !c.closed.load(Ordering::SeqCst))
+            .find_map(|c| c.ping_pong.clone());
+        let ping_pong = match ping_pong {
+            Some(ping_pong) => ping_pong,
+            None => return Err(SendError::Transport("not connected".into())),
+        };
+        let start = Instant::now();
+        ping_pong.lock().await.ping(Ping::opaque()).await?;
+        Ok(start.elapsed())
+    }
+}
+
+/// The head of a request, for sending it again.
+fn head(parts: &Parts) -> Request<()> {
+    let mut request = Request::new(());
+    *request.method_mut() = parts.method.clone();
+    *request.uri_mut() = parts.uri.clone();
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers.clone();
+    request
+}
+
+fn unsent(e: h2::Error) -> Failure {
+    Failure::Unsent(e.into())
+}
+
+fn connect_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> SendError {
+    SendError::Transport(e.into())
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast;
+
+use crate::{ApnsId, ApplePushClient, Event, Health, Notification, SendError, SendResponse, TokenInfo};
+
+
+/// What a handle needs of a client, whatever its connector.
+pub(crate) trait ErasedClient: Send + Sync {
+    fn send_detailed(&self, n: Notification) -> BoxFuture<'_, Result<SendResponse, SendError>>;
+    fn fire(self: Arc<Self>, n: Notification) -> bool;
+    fn events(&self) -> broadcast::Receiver<Event>;
+    fn token_info(&self) -> Option<TokenInfo>;
+    fn invalidate_token(&self);
+    fn health(&self) -> Health;
+    fn shutdown(&self) -> BoxFuture<'_, ()>;
+}
+
+impl<S> ErasedClient for ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn send_detailed(&self, n: Notification) -> BoxFuture<'_, Result<SendResponse, SendError>> {
+        Box::pin(ApplePushClient::send_detailed(self, n))
+    }
+
+    fn fire(self: Arc<Self>, n: Notification) -> bool {
+        ApplePushClient::fire(&self, n)
+    }
+
+    fn events(&self) -> broadcast::Receiver<Event> {
+        ApplePushClient::events(self)
+    }
+
+    fn token_info(&self) -> Option<TokenInfo> {
+        ApplePushClient::token_info(self)
+    }
+
+    fn invalidate_token(&self) {
+        ApplePushClient::invalidate_token(self)
+    }
+
+    fn health(&self) -> Health {
+        ApplePushClient::health(self)
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(ApplePushClient::shutdown(self))
+    }
+}
+
+/// A shared, configured client, for storing in the state of a web framework
+/// (an axum `State`, an actix-web `Data`) or anywhere else naming the
+/// connector type would be awkward. Clones are cheap and share the client,
+/// with its connections and provider token.
+///
+/// Configure the client first, then turn it into a handle:
+///
+/// ```no_run
+/// # fn run(mut client: apple_push::ApplePushClient<hyper::client::HttpConnector>) {
+/// use std::convert::Infallible;
+///
+/// use apple_push::{ApnsHandle, NotificationBuilder};
+/// use hyper::{Body, Request, Response};
+///
+/// async fn notify(apns: ApnsHandle, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     let token = req.uri().query().unwrap_or_default().to_owned();
+///     let n = NotificationBuilder::new("com.example.app", &token).alert("Ping").build();
+///     Ok(match apns.send(n).await {
+///         Ok(id) => Response::new(Body::from(id.to_string())),
+///         Err(e) => Response::builder().status(502).body(Body::from(e.to_string())).unwrap(),
+///     })
+/// }
+///
+/// client.set_production(false);
+/// let apns = ApnsHandle::new(client);
+/// let service = hyper::service::make_service_fn(move |_: &hyper::server::conn::AddrStream| {
+///     let apns = apns.clone();
+///     async move {
+///         Ok::<_, Infallible>(hyper::service::service_fn(move |req| notify(apns.clone(), req)))
+///     }
+/// });
+/// hyper::Server::bind(&([127, 0, 0, 1], 8080).into()).serve(service);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ApnsHandle {
+    client: Arc<dyn ErasedClient>,
+}
+
+impl ApnsHandle {
+    pub fn new<S>(client: ApplePushClient<S>) -> Self
+    where
+        S: Service<Uri> + Clone + Send + Sync + 'static,
+        S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        S::Future: Send + Unpin + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        ApnsHandle { client: Arc::new(client) }
+    }
+
+    /// See `ApplePushClient::send`.
+    pub async fn send(&self, n: Notification) -> Result<ApnsId, SendError> {
+        self.client.send_detailed(n).await.map(|response| response.id)
+    }
+
+    /// See `ApplePushClient::send_detailed`.
+    pub async fn send_detailed(&self, n: Notification) -> Result<SendResponse, SendError> {
+        self.client.send_detailed(n).await
+    }
+
+    /// See `ApplePushClient::fire`.
+    pub fn fire(&self, n: Notification) -> bool {
+        self.client.clone().fire(n)
+    }
+
+    /// See `ApplePushClient::events`.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.client.events()
+    }
+
+    /// See `ApplePushClient::token_info`.
+    pub fn token_info(&self) -> Option<TokenInfo> {
+        self.client.token_info()
+    }
+
+    /// See `ApplePushClient::invalidate_token`.
+    pub fn invalidate_token(&self) {
+        self.client.invalidate_token()
+    }
+
+    /// See `ApplePushClient::health`.
+    pub fn health(&self) -> Health {
+        self.client.health()
+    }
+
+    /// See `ApplePushClient::shutdown`.
+    pub async fn shutdown(&self) {
+        self.client.shutdown().await
+    }
+}
+
+/// A client with its connector type erased, for storing in structs and
+/// trait objects without a type parameter. The same type as `ApnsHandle`;
+/// see `ApplePushClient::boxed`.
+pub type BoxedApnsClient = ApnsHandle;
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Erase the connector type of the client, once it is configured.
+    pub fn boxed(self) -> BoxedApnsClient {
+        ApnsHandle::new(self)
+    }
+}
+
+impl<S> From<ApplePushClient<S>> for ApnsHandle
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn from(client: ApplePushClient<S>) -> Self {
+        ApnsHandle::new(client)
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::SendError;
+
+
+/// How far back `ApplePushClient::health` looks at requests.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The most requests kept for computing the health.
+const MAX_SAMPLES: usize = 1000;
+
+/// Fewer requests than this in the window are too few for error rates to
+/// mean anything.
+const MIN_SAMPLES: usize = 10;
+
+/// Consecutive connection failures after which APNS is unreachable.
+const UNREACHABLE_AFTER: usize = 3;
+
+/// Whether a client can be expected to deliver notifications, for readiness
+/// probes. See `ApplePushClient::health`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Health {
+    Ok,
+    /// Notifications are getting through, but something needs attention.
+    Degraded(String),
+    /// Notifications aren't getting through.
+    Unhealthy(String),
+}
+
+impl Health {
+    /// Whether the client should keep receiving traffic: it is `Ok` or
+    /// `Degraded`.
+    pub fn is_ready(&self) -> bool {
+        !matches!(self, Health::Unhealthy(_))
+    }
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Health::Ok => write!(f, "ok"),
+            Health::Degraded(reason) => write!(f, "degraded: {}", reason),
+            Health::Unhealthy(reason) => write!(f, "unhealthy: {}", reason),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// APNS answered, whatever it answered apart from the below.
+    Answered,
+    /// APNS couldn't be reached.
+    Unreachable,
+    /// APNS answered with a server error.
+    ServerError,
+    /// APNS answered 429.
+    Throttled,
+}
+
+/// The outcomes of recent requests.
+#[derive(Default)]
+pub(crate) struct HealthTracker {
+    outcomes: Mutex<VecDeque<(Instant, Outcome)>>,
+}
+
+impl HealthTracker {
+    pub fn record<T>(&self, result: &Result<T, SendError>) {
+        let outcome = match result {
+            Err(SendError::Transport(_)) => Outcome::Unreachable,
+            Err(SendError::Api(e)) if e.status >= 500 => Outcome::ServerError,
+            Err(SendError::Api(e)) if e.status == 429 => Outcome::Throttled,
+            Ok(_) | Err(SendError::Api(_)) => Outcome::Answered,
+            // Refused before being sent; says nothing about APNS.
+            Err(_) => return,
+        };
+        let mut outcomes = self.outcomes.lock().unwrap();
+        if outcomes.len() == MAX_SAMPLES {
+            outcomes.pop_front();
+        }
+        outcomes.push_back((Instant::now(), outcome));
+    }
+
+    /// The health according to recent requests alone.
+    pub fn health(&self) -> Health {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        while outcomes.front().is_some_and(|(at, _)| at.elapsed() > WINDOW) {
+            outcomes.pop_front();
+        }
+        let unreachable = outcomes.iter().rev().take_while(|(_, o)| *o == Outcome::Unreachable).count();
+        if unreachable >= UNREACHABLE_AFTER {
+            return Health::Unhealthy(format!("the last {} connections to APNS failed", unreachable));
+        }
+        if outcomes.len() < MIN_SAMPLES {
+            return Health::Ok;
+        }
+        let percent = |f: fn(Outcome) -> bool| outcomes.iter().filter(|(_, o)| f(*o)).count() * 100 / outcomes.len();
+        let failed = percent(|o| matches!(o, Outcome::Unreachable | Outcome::ServerError));
+        let throttled = percent(|o| o == Outcome::Throttled);
+        if failed >= 50 {
+            Health::Unhealthy(format!("{}% of recent requests failed", failed))
+        } else if failed >= 10 {
+            Health::Degraded(format!("{}% of recent requests failed", failed))
+        } else if throttled >= 10 {
+            Health::Degraded(format!("{}% of recent requests were throttled", throttled))
+        } else {
+            Health::Ok
+        }
+    }
+}
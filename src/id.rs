@@ -0,0 +1,105 @@
+//! The type of apns-id values.
+//!
+//! With the default `uuid` feature this is `uuid::Uuid`. Without it, a small
+//! 128-bit type with the same string format is used instead, so that the
+//! `uuid` crate isn't needed.
+
+#[cfg(feature = "uuid")]
+pub use uuid::Uuid as ApnsId;
+
+#[cfg(not(feature = "uuid"))]
+pub use self::opaque::{ApnsId, ParseApnsIdError};
+
+/// A new random apns-id.
+#[cfg(feature = "client")]
+pub(crate) fn generate() -> ApnsId {
+    ApnsId::new_v4()
+}
+
+#[cfg(not(feature = "uuid"))]
+mod opaque {
+    use std::fmt;
+    use std::str::FromStr;
+
+    use ring::rand::{SecureRandom, SystemRandom};
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+
+    #[derive(Debug)]
+    pub struct ParseApnsIdError;
+
+    impl fmt::Display for ParseApnsIdError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("invalid apns-id (must be formatted like a UUID)")
+        }
+    }
+
+    impl ::std::error::Error for ParseApnsIdError {}
+
+    /// An apns-id: a 128-bit value, formatted like a UUID on the wire.
+    #[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
+    pub struct ApnsId(u128);
+
+    impl ApnsId {
+        pub fn from_u128(value: u128) -> Self {
+            ApnsId(value)
+        }
+
+        pub fn as_u128(self) -> u128 {
+            self.0
+        }
+
+        /// A random id with the version and variant bits of a version 4 UUID.
+        pub fn new_v4() -> Self {
+            let mut bytes = [0; 16];
+            SystemRandom::new().fill(&mut bytes).expect("system random number generator failed");
+            let value = u128::from_be_bytes(bytes);
+            ApnsId((value & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62))
+        }
+    }
+
+    impl fmt::Display for ApnsId {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let v = self.0;
+            write!(
+                f,
+                "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                v >> 96,
+                (v >> 80) & 0xffff,
+                (v >> 64) & 0xffff,
+                (v >> 48) & 0xffff,
+                v & 0xffff_ffff_ffff,
+            )
+        }
+    }
+
+    impl FromStr for ApnsId {
+        type Err = ParseApnsIdError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let groups: Vec<&str> = s.split('-').collect();
+            let lengths: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+            if lengths != [8, 4, 4, 4, 12] {
+                return Err(ParseApnsIdError);
+            }
+            let hex: String = groups.concat();
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(ParseApnsIdError);
+            }
+            u128::from_str_radix(&hex, 16).map(ApnsId).map_err(|_| ParseApnsIdError)
+        }
+    }
+
+    impl Serialize for ApnsId {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ApnsId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(de::Error::custom)
+        }
+    }
+}
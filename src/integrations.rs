@@ -0,0 +1,4 @@
+//! Glue between the client and other systems notifications come from.
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
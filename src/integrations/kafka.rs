@@ -0,0 +1,111 @@
+//! Sending notifications consumed from a Kafka topic.
+//!
+//! Each message on the topic holds the JSON serialization of a
+//! `Notification`. A message's offset is only committed once its notification
+//! was sent or failed for good, so after a crash or a stop any notification
+//! not known to have been handled is consumed again: delivery is at least
+//! once, never at most once.
+//!
+//! ```no_run
+//! # async fn run(client: apple_push::ApplePushClient<hyper::client::HttpConnector>) -> Result<(), apple_push::Error> {
+//! use apple_push::integrations::kafka::{self, Disposition};
+//! use rdkafka::config::ClientConfig;
+//! use rdkafka::consumer::{Consumer, StreamConsumer};
+//!
+//! let consumer: StreamConsumer = ClientConfig::new()
+//!     .set("bootstrap.servers", "localhost:9092")
+//!     .set("group.id", "apns")
+//!     .set("enable.auto.commit", "false")
+//!     .create()?;
+//! consumer.subscribe(&["notifications"])?;
+//! kafka::consume(&client, &consumer, kafka::default_disposition).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::StreamExt;
+use hyper::{client::connect::Connection, service::Service, Uri};
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::Message;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{ApplePushClient, Notification, SendError};
+
+
+/// What to do with a message whose notification failed to send.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    /// Commit the message and carry on with the next one.
+    Commit,
+    /// Stop consuming without committing the message, so that it is consumed
+    /// again by the next consumer of its partition.
+    Stop,
+}
+
+/// Why `consume` stopped.
+#[derive(Debug)]
+pub enum ConsumeError {
+    Kafka(KafkaError),
+    /// A notification failed and the disposition for it was `Stop`.
+    Send(SendError),
+}
+
+impl ::std::fmt::Display for ConsumeError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            ConsumeError::Kafka(e) => write!(f, "{}", e),
+            ConsumeError::Send(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for ConsumeError {}
+
+impl From<KafkaError> for ConsumeError {
+    fn from(e: KafkaError) -> Self {
+        ConsumeError::Kafka(e)
+    }
+}
+
+/// Commit unless the failure is one that might not happen again, like a
+/// connection failure or APNS being overloaded.
+pub fn default_disposition(_n: &Notification, error: &SendError) -> Disposition {
+    if error.is_retriable() {
+        Disposition::Stop
+    } else {
+        Disposition::Commit
+    }
+}
+
+/// Send the notifications consumed by `consumer`, one at a time, committing
+/// each message once it has been handled. `on_failure` decides what happens
+/// to messages whose notification failed.
+///
+/// Messages that aren't a notification are committed and skipped, since
+/// consuming them again wouldn't help. Returns only if consuming fails or
+/// `on_failure` says to stop, or when the consumer's stream ends.
+pub async fn consume<S, C, F>(client: &ApplePushClient<S>, consumer: &StreamConsumer<C>, mut on_failure: F) -> Result<(), ConsumeError>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    C: ConsumerContext + 'static,
+    F: FnMut(&Notification, &SendError) -> Disposition,
+{
+    let mut messages = consumer.start();
+    while let Some(message) = messages.next().await {
+        let message = message?;
+        let n = message.payload().and_then(|payload| serde_json::from_slice::<Notification>(payload).ok());
+        if let Some(n) = n {
+            if let Err(e) = client.send(n.clone()).await {
+                if on_failure(&n, &e) == Disposition::Stop {
+                    return Err(ConsumeError::Send(e));
+                }
+            }
+        }
+        consumer.commit_message(&message, CommitMode::Async)?;
+    }
+    Ok(())
+}
@@ -1,148 +1,181 @@
+pub mod consts;
 mod types;
 mod error;
-
-use std::sync::{Arc, RwLock};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use biscuit::{jwa, jws, JWT};
-use bytes::buf::BufExt;
-use failure::{Error, format_err};
-use hyper::{Body, client::connect::Connection, Client, Request, service::Service, Uri};
-use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
-use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncRead, AsyncWrite};
-use uuid::Uuid;
-
-pub use self::error::{ApiError, SendError};
-use self::error::ErrorResponse;
+mod id;
+mod retry;
+mod limits;
+mod compat;
+mod rng;
+mod signing;
+mod pem;
+mod certificate;
+pub mod broadcast;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "registry")]
+pub mod registry;
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+mod trim;
+#[cfg(feature = "client")]
+mod sanitize;
+#[cfg(feature = "client")]
+mod expiration;
+#[cfg(feature = "client")]
+mod localize;
+#[cfg(feature = "client")]
+mod defaults;
+#[cfg(feature = "unstable-correlation")]
+mod correlation;
+#[cfg(feature = "client")]
+mod batch;
+#[cfg(feature = "client")]
+mod export;
+#[cfg(feature = "client")]
+mod adaptive;
+#[cfg(feature = "client")]
+mod throttle;
+#[cfg(feature = "client")]
+mod send_rate;
+#[cfg(feature = "client")]
+mod retry_budget;
+#[cfg(feature = "client")]
+mod rate_limit;
+#[cfg(feature = "redis")]
+mod redis_limiter;
+#[cfg(feature = "client")]
+mod transport;
+#[cfg(feature = "client")]
+mod tasks;
+#[cfg(feature = "client")]
+mod token_cache;
+#[cfg(feature = "client")]
+mod egress;
+#[cfg(feature = "wire-log")]
+mod wire_log;
+#[cfg(feature = "client")]
+mod fire;
+#[cfg(feature = "client")]
+mod sink;
+#[cfg(feature = "client")]
+mod handle;
+#[cfg(feature = "client")]
+mod permits;
+#[cfg(feature = "client")]
+mod diagnostics;
+#[cfg(feature = "chaos")]
+mod chaos;
+#[cfg(feature = "default-tls")]
+mod tls;
+#[cfg(feature = "client")]
+mod config;
+#[cfg(feature = "client")]
+mod closing;
+#[cfg(feature = "client")]
+mod queues;
+#[cfg(feature = "client")]
+mod middleware;
+#[cfg(feature = "client")]
+mod policy;
+#[cfg(feature = "unstable-payload-signing")]
+mod payload_signing;
+#[cfg(feature = "client")]
+mod memory;
+#[cfg(feature = "client")]
+mod events;
+#[cfg(feature = "client")]
+mod credentials;
+#[cfg(feature = "client")]
+mod health;
+#[cfg(feature = "client")]
+mod timings;
+#[cfg(feature = "client")]
+pub mod metrics;
+#[cfg(feature = "h2")]
+mod h2_transport;
+pub mod live_activity;
+pub mod migrate;
+pub mod prelude;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "gateway")]
+pub mod gateway;
+#[cfg(feature = "global")]
+pub mod global;
+#[cfg(feature = "kafka")]
+pub mod integrations;
+#[cfg(feature = "client")]
+pub mod scheduler;
+
+pub use self::error::{ApiError, ApiErrorReason, BuildError, CloseReason, Error, ReasonClass, SendError, ValidationError};
 pub use self::types::*;
-
-
-struct CachedToken {
-    token: String,
-    cached_at: i64
-}
-
-pub struct ApplePushClient<S>
-where
-    S: Service<Uri> + Clone + Send + Sync + 'static,
-    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
-    S::Future: Send + Unpin + 'static,
-    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-{
-    production: bool,
-    client: Client<S, Body>,
-    team_id: String,
-    jwt_kid: String,
-    jwt_key: jws::Secret,
-    jwt: RwLock<Option<CachedToken>>
-}
-
-impl<S> ApplePushClient<S>
-where
-    S: Service<Uri> + Clone + Send + Sync + 'static,
-    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
-    S::Future: Send + Unpin + 'static,
-    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-{
-    pub fn new(client: Client<S, Body>, team_id: &str, jwt_kid: &str, jwt_key: &[u8]) -> Result<Self, Error> {
-        let keypair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, jwt_key).map_err(|e| format_err!("bad key: {:?}", e))?;
-        Ok(Self {
-            production: true,
-            client,
-            team_id: team_id.to_owned(),
-            jwt_kid: jwt_kid.to_owned(),
-            jwt_key: jws::Secret::EcdsaKeyPair(Arc::new(keypair)),
-            jwt: RwLock::new(None)
-        })
-    }
-
-    /// Set API endpoint to use (production or development sandbox).
-    pub fn set_production(&mut self, production: bool) {
-        self.production = production;
-    }
-
-    /// Build the url for a device token.
-    fn build_url(&self, device_token: &str) -> String {
-        let root = if self.production {
-            APN_URL_PRODUCTION
-        } else {
-            APN_URL_DEV
-        };
-        format!("{}/3/device/{}", root, device_token)
-    }
-
-    fn generate_jwt(&self) -> Result<String, Error> {
-        let since_the_epoch = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
-
-        if let Some(ref token) = *self.jwt.read().unwrap() {
-            if since_the_epoch - token.cached_at < (3600 - 60) {
-                return Ok(token.token.clone());
-            }
-        }
-
-        #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
-        struct PrivateClaims {}
-        let claims = biscuit::ClaimsSet::<PrivateClaims> {
-            registered: biscuit::RegisteredClaims {
-                issuer: Some(self.team_id.parse()?),
-                issued_at: Some(since_the_epoch.into()),
-                ..Default::default()
-            },
-            private: PrivateClaims {},
-        };
-        let header = jws::RegisteredHeader {
-            algorithm: jwa::SignatureAlgorithm::ES256,
-            key_id: Some(self.jwt_kid.clone()),
-            ..Default::default()
-        };
-        let jwt = JWT::new_decoded(header.into(), claims);
-        let encoded = jwt.into_encoded(&self.jwt_key).unwrap().unwrap_encoded().to_string();
-        
-        *self.jwt.write().unwrap() = Some(CachedToken {
-            cached_at: since_the_epoch,
-            token: encoded.clone()
-        });
-        Ok(encoded)
-    }
-
-    /// Send a notification.
-    /// Returns the UUID of the notification.
-    pub async fn send(&self, n: Notification) -> Result<Uuid, SendError> {
-        let id = n.id.unwrap_or_else(Uuid::new_v4);
-        let body = ApnsRequest { aps: n.payload };
-        let jwt = self.generate_jwt().map_err(|e| SendError::from(e))?;
-        let body = serde_json::to_vec(&body)?;
-
-        let mut req = Request::post(&self.build_url(&n.device_token));
-        let headers = req.headers_mut().unwrap();
-        headers.insert("authorization", format!("bearer {}", jwt).parse()?);
-        headers.insert("apns-id", id.to_string().parse()?);
-        headers.insert("apns-topic", n.topic.parse()?);
-        
-        if let Some(expiration) = n.expiration {
-            headers.insert("apns-expiration", expiration.to_string().parse()?);
-        }
-        if let Some(priority) = n.priority {
-            headers.insert("apns-priority", priority.to_int().to_string().parse()?);
-        }
-        if let Some(collapse_id) = n.collapse_id {
-            headers.insert("apns-collapse-id", collapse_id.as_str().parse()?);
-        }
-
-        let res = self.client.request(req.body(body.into())?).await?;
-        let status = res.status();
-        if status.is_success() {
-            Ok(id)
-        }
-        else {
-            let body = hyper::body::aggregate(res.into_body()).await?;
-            let reason = ErrorResponse::parse_payload(body.reader());
-            Err(ApiError {
-                status: status.as_u16() as u32,
-                reason,
-            }.into())
-        }
-    }
-}
-
+pub use self::consts::{APN_URL_DEV, APN_URL_PRODUCTION, MAX_COLLAPSE_ID_SIZE, MAX_PAYLOAD_SIZE, MAX_VOIP_PAYLOAD_SIZE};
+pub use self::id::*;
+pub use self::retry::{AttemptHistory, AttemptRecord, RetryPolicy};
+pub use self::limits::PayloadLimits;
+pub use self::compat::{Compatibility, IosVersion, Unsupported};
+pub use self::rng::{DeterministicRng, Rng, SystemRng};
+pub use self::signing::{TokenInfo, TokenSigner};
+pub use self::pem::{pkcs8_from_pem, read_key_file, KeyError};
+pub use self::certificate::Certificate;
+
+#[cfg(feature = "client")]
+pub use self::client::ApplePushClient;
+#[cfg(feature = "client")]
+pub use self::trim::TrimPolicy;
+#[cfg(feature = "client")]
+pub use self::sanitize::SanitizePolicy;
+#[cfg(feature = "client")]
+pub use self::expiration::ExpirationPolicy;
+#[cfg(feature = "client")]
+pub use self::localize::Localizer;
+#[cfg(feature = "client")]
+pub use self::defaults::TopicDefaults;
+#[cfg(feature = "client")]
+pub use self::throttle::ThrottlePolicy;
+#[cfg(feature = "client")]
+pub use self::send_rate::SendRate;
+#[cfg(feature = "client")]
+pub use self::retry_budget::RetryBudget;
+#[cfg(feature = "client")]
+pub use self::rate_limit::{MemoryRateLimiter, RateLimiter};
+#[cfg(feature = "unstable-correlation")]
+pub use self::correlation::{CorrelationStore, MemoryCorrelationStore};
+#[cfg(feature = "redis")]
+pub use self::redis_limiter::RedisRateLimiter;
+#[cfg(feature = "client")]
+pub use self::metrics::Metrics;
+#[cfg(feature = "client")]
+pub use self::events::Event;
+#[cfg(feature = "client")]
+pub use self::health::Health;
+#[cfg(feature = "client")]
+pub use self::middleware::{PayloadMiddleware, RequestMiddleware, ResponseMiddleware};
+#[cfg(feature = "client")]
+pub use self::policy::{Policy, Verdict};
+#[cfg(feature = "unstable-payload-signing")]
+pub use self::payload_signing::{HmacSigner, PayloadSigner, DEFAULT_SIGNATURE_KEY};
+#[cfg(feature = "client")]
+pub use self::timings::{SendResponse, Timings};
+#[cfg(feature = "client")]
+pub use self::config::ClientConfig;
+#[cfg(feature = "client")]
+pub use self::queues::QueueStats;
+#[cfg(feature = "client")]
+pub use self::egress::EgressConnector;
+#[cfg(feature = "client")]
+pub use self::handle::{ApnsHandle, BoxedApnsClient};
+#[cfg(feature = "client")]
+pub use self::sink::{SendSink, SinkResult};
+#[cfg(feature = "client")]
+pub use self::batch::{BatchReport, BatchStream, CancellationToken, FollowUp, FollowUpReport, ResumeToken, SendOptions};
+#[cfg(feature = "client")]
+pub use self::adaptive::AdaptiveConcurrency;
+#[cfg(feature = "client")]
+pub use self::export::OutcomeWriter;
+#[cfg(feature = "h2")]
+pub use self::h2_transport::H2Options;
+#[cfg(feature = "chaos")]
+pub use self::chaos::ChaosPolicy;
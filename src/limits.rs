@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::consts::{MAX_PAYLOAD_SIZE, MAX_VOIP_PAYLOAD_SIZE};
+use crate::types::PushType;
+
+
+/// Maximum payload sizes in bytes. Notifications with larger payloads are
+/// refused before being sent.
+///
+/// The defaults are the limits APNS documents; override them for contexts
+/// with different limits, such as MDM or enterprise deployments.
+///
+/// ```
+/// use apple_push::{PayloadLimits, PushType};
+///
+/// let limits = PayloadLimits::new()
+///     .push_type(PushType::Mdm, 8192)
+///     .topic("com.example.internal", 16384);
+/// assert_eq!(limits.limit_for(PushType::Mdm, "com.apple.mgmt.External.1234"), 8192);
+/// assert_eq!(limits.limit_for(PushType::Voip, "com.example.app.voip"), 5120);
+/// assert_eq!(limits.limit_for(PushType::Alert, "com.example.internal"), 16384);
+/// assert_eq!(limits.limit_for(PushType::Alert, "com.example.app"), 4096);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PayloadLimits {
+    /// Limit for notifications without a more specific one.
+    pub default: usize,
+    push_types: HashMap<PushType, usize>,
+    topics: HashMap<String, usize>,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        PayloadLimits {
+            default: MAX_PAYLOAD_SIZE,
+            push_types: vec![(PushType::Voip, MAX_VOIP_PAYLOAD_SIZE)].into_iter().collect(),
+            topics: HashMap::new(),
+        }
+    }
+}
+
+impl PayloadLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `limit` for notifications of `push_type`, unless their topic has
+    /// a limit of its own.
+    pub fn push_type(mut self, push_type: PushType, limit: usize) -> Self {
+        self.push_types.insert(push_type, limit);
+        self
+    }
+
+    /// Use `limit` for notifications to `topic`, whatever their push type.
+    pub fn topic(mut self, topic: &str, limit: usize) -> Self {
+        self.topics.insert(topic.to_owned(), limit);
+        self
+    }
+
+    /// The limit for notifications of `push_type` to `topic`.
+    pub fn limit_for(&self, push_type: PushType, topic: &str) -> usize {
+        self.topics.get(topic)
+            .or_else(|| self.push_types.get(&push_type))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
@@ -0,0 +1,167 @@
+//! Sending Live Activity updates, and skipping redundant ones.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+
+#[cfg(feature = "client")]
+use hyper::{client::connect::Connection, service::Service, Uri};
+#[cfg(feature = "client")]
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "client")]
+use crate::{ApnsId, ApplePushClient, Notification, SendError};
+
+use crate::types::epoch_seconds;
+use crate::{ActivityEvent, NotificationBuilder, Payload, PushType};
+
+
+/// The topic of the Live Activities of the app `bundle_id`.
+pub fn topic(bundle_id: &str) -> String {
+    format!("{}{}", bundle_id, PushType::LiveActivity.topic_suffix().unwrap_or_default())
+}
+
+/// A builder for an update of the Live Activity with push token
+/// `push_token`, to the state `content_state`, timestamped now.
+///
+/// ```
+/// # #[derive(serde::Serialize)]
+/// # struct Score { home: u32, away: u32 }
+/// let n = apple_push::live_activity::update("com.example.app", "activity-push-token", &Score { home: 1, away: 0 })
+///     .unwrap()
+///     .build();
+/// assert_eq!(n.topic, "com.example.app.push-type.liveactivity");
+/// assert_eq!(n.payload.content_state.as_ref().unwrap()["home"], 1);
+/// ```
+pub fn update<T: Serialize>(bundle_id: &str, push_token: &str, content_state: &T) -> Result<NotificationBuilder, serde_json::Error> {
+    builder(bundle_id, push_token, ActivityEvent::Update, content_state)
+}
+
+/// A builder for ending the Live Activity with push token `push_token`,
+/// showing `content_state` until it is dismissed. Set when with
+/// `dismissal_date`; by default the system dismisses it after a while.
+pub fn end<T: Serialize>(bundle_id: &str, push_token: &str, content_state: &T) -> Result<NotificationBuilder, serde_json::Error> {
+    builder(bundle_id, push_token, ActivityEvent::End, content_state)
+}
+
+/// A builder for starting a Live Activity remotely with the push-to-start
+/// token `push_to_start_token`, of the app's `ActivityAttributes` type
+/// `attributes_type`. Starting an activity needs an alert, so set a title
+/// and body on the builder.
+///
+/// ```
+/// # #[derive(serde::Serialize)]
+/// # struct Match { home: &'static str, away: &'static str }
+/// # #[derive(serde::Serialize)]
+/// # struct Score { home: u32, away: u32 }
+/// let n = apple_push::live_activity::start(
+///     "com.example.app", "push-to-start-token", "MatchAttributes",
+///     &Match { home: "Lions", away: "Tigers" }, &Score { home: 0, away: 0 },
+/// )
+///     .unwrap()
+///     .title("Kick-off")
+///     .body("Lions v Tigers has started")
+///     .build();
+/// assert!(n.validate_push_type().is_ok());
+/// assert_eq!(n.payload.attributes.as_ref().unwrap()["home"], "Lions");
+/// ```
+pub fn start<A: Serialize, T: Serialize>(
+    bundle_id: &str,
+    push_to_start_token: &str,
+    attributes_type: &str,
+    attributes: &A,
+    content_state: &T,
+) -> Result<NotificationBuilder, serde_json::Error> {
+    let mut builder = builder(bundle_id, push_to_start_token, ActivityEvent::Start, content_state)?;
+    let payload = builder.payload_mut();
+    payload.attributes_type = Some(attributes_type.to_owned());
+    payload.attributes = Some(serde_json::to_value(attributes)?);
+    Ok(builder)
+}
+
+fn builder<T: Serialize>(bundle_id: &str, push_token: &str, event: ActivityEvent, content_state: &T) -> Result<NotificationBuilder, serde_json::Error> {
+    let payload = Payload {
+        timestamp: Some(epoch_seconds(SystemTime::now())),
+        event: Some(event),
+        content_state: Some(serde_json::to_value(content_state)?),
+        ..Payload::default()
+    };
+    Ok(NotificationBuilder::new(&topic(bundle_id), push_token)
+        .payload(payload)
+        .push_type(PushType::LiveActivity))
+}
+
+/// Remembers the last content state sent to each Live Activity push token, so
+/// that updates which wouldn't change anything on the device can be skipped.
+pub struct ContentStateTracker<T> {
+    max_interval: Option<Duration>,
+    sent: Mutex<HashMap<String, (T, Instant)>>,
+}
+
+impl<T: PartialEq> ContentStateTracker<T> {
+    /// Create a tracker that only sends states that differ from the last one.
+    pub fn new() -> Self {
+        ContentStateTracker {
+            max_interval: None,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a tracker that also resends an unchanged state once
+    /// `max_interval` has passed since it was last sent.
+    pub fn with_max_interval(max_interval: Duration) -> Self {
+        ContentStateTracker {
+            max_interval: Some(max_interval),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `state` needs to be sent to the activity with push token `token`.
+    pub fn should_send(&self, token: &str, state: &T) -> bool {
+        match self.sent.lock().unwrap().get(token) {
+            None => true,
+            Some((last, sent_at)) => {
+                last != state || self.max_interval.is_some_and(|interval| sent_at.elapsed() >= interval)
+            }
+        }
+    }
+
+    /// Record that `state` was sent to the activity with push token `token`.
+    pub fn mark_sent(&self, token: &str, state: T) {
+        self.sent.lock().unwrap().insert(token.to_owned(), (state, Instant::now()));
+    }
+
+    /// Forget the activity with push token `token`, e.g. once it has ended.
+    /// Returns the last state sent to it.
+    pub fn remove(&self, token: &str) -> Option<T> {
+        self.sent.lock().unwrap().remove(token).map(|(state, _)| state)
+    }
+
+    /// Send the notification, which should carry `state` as its content
+    /// state, unless the tracker says it would be redundant.
+    /// Returns the apns-id of the notification, or `None` if it was skipped.
+    #[cfg(feature = "client")]
+    pub async fn send_if_changed<S>(&self, client: &ApplePushClient<S>, n: Notification, state: T) -> Result<Option<ApnsId>, SendError>
+    where
+        S: Service<Uri> + Clone + Send + Sync + 'static,
+        S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        S::Future: Send + Unpin + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        if !self.should_send(&n.device_token, &state) {
+            return Ok(None);
+        }
+        let token = n.device_token.clone();
+        let id = client.send(n).await?;
+        self.mark_sent(&token, state);
+        Ok(Some(id))
+    }
+}
+
+impl<T: PartialEq> Default for ContentStateTracker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
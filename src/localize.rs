@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use crate::types::{Alert, Payload};
+
+
+/// Resolves localization keys into alert strings before a notification is
+/// sent, for apps that don't ship localized strings in their bundle.
+///
+/// See `ApplePushClient::set_localizer`.
+pub trait Localizer: Send + Sync {
+    /// The string for `key` with `args` substituted, or `None` to leave the
+    /// key for the device to resolve.
+    fn localize(&self, key: &str, args: &[String]) -> Option<String>;
+}
+
+/// Replace `title-loc-key`, `subtitle-loc-key` and `loc-key`, with their
+/// arguments, by the title, subtitle and body `localizer` resolves them to.
+/// A shared payload is only copied if there is something to resolve.
+pub(crate) fn apply(localizer: &dyn Localizer, payload: &mut Arc<Payload>) {
+    let (title, subtitle, body) = match payload.alert {
+        Some(Alert::Payload(ref alert)) => (
+            resolve(localizer, &alert.title_loc_key, &alert.title_loc_args),
+            resolve(localizer, &alert.subtitle_loc_key, &alert.subtitle_loc_args),
+            resolve(localizer, &alert.loc_key, &alert.loc_args),
+        ),
+        _ => return,
+    };
+    if title.is_none() && subtitle.is_none() && body.is_none() {
+        return;
+    }
+    if let Some(Alert::Payload(ref mut alert)) = Arc::make_mut(payload).alert {
+        if let Some(title) = title {
+            replace(&mut alert.title, &mut alert.title_loc_key, &mut alert.title_loc_args, title);
+        }
+        if let Some(subtitle) = subtitle {
+            replace(&mut alert.subtitle, &mut alert.subtitle_loc_key, &mut alert.subtitle_loc_args, subtitle);
+        }
+        if let Some(body) = body {
+            replace(&mut alert.body, &mut alert.loc_key, &mut alert.loc_args, body);
+        }
+    }
+}
+
+fn resolve(localizer: &dyn Localizer, key: &Option<String>, args: &Option<Vec<String>>) -> Option<String> {
+    let key = key.as_ref()?;
+    localizer.localize(key, args.as_deref().unwrap_or_default())
+}
+
+fn replace(text: &mut Option<String>, key: &mut Option<String>, args: &mut Option<Vec<String>>, resolved: String) {
+    *text = Some(resolved);
+    *key = None;
+    *args = None;
+}
@@ -0,0 +1,111 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Notify;
+
+use crate::types::Notification;
+use crate::ApplePushClient;
+
+
+/// A cap on the bytes of payload buffered by the client at once.
+pub(crate) struct MemoryBudget {
+    pub limit: usize,
+    used: Mutex<usize>,
+    released: Notify,
+}
+
+/// Bytes reserved from a `MemoryBudget`, released when dropped.
+pub(crate) struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        MemoryBudget { limit, used: Mutex::new(0), released: Notify::new() }
+    }
+
+    pub fn used(&self) -> usize {
+        *self.used.lock().unwrap()
+    }
+
+    /// Reserve `bytes` if they fit. A notification larger than the whole
+    /// budget fits when nothing else is reserved, so it isn't stuck forever.
+    pub fn try_reserve(self: &Arc<Self>, bytes: usize) -> Option<Reservation> {
+        let mut used = self.used.lock().unwrap();
+        if *used > 0 && *used + bytes > self.limit {
+            return None;
+        }
+        *used += bytes;
+        Some(Reservation { budget: self.clone(), bytes })
+    }
+
+    /// Reserve `bytes`, waiting for other reservations to be released until
+    /// they fit.
+    pub async fn reserve(self: &Arc<Self>, bytes: usize) -> Reservation {
+        loop {
+            if let Some(reservation) = self.try_reserve(bytes) {
+                // Let the next waiter check whether it fits in what's left.
+                self.released.notify();
+                return reservation;
+            }
+            self.released.notified().await;
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify();
+    }
+}
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Limit the total bytes of payload the client buffers at once, counting
+    /// notifications queued by `fire` and in flight. Beyond the limit,
+    /// `send` waits for earlier notifications to finish and `fire` drops.
+    /// `None` removes the limit.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.memory = bytes.map(|bytes| Arc::new(MemoryBudget::new(bytes)));
+    }
+
+    /// The bytes of payload currently buffered, if there is a memory limit.
+    pub fn buffered_bytes(&self) -> Option<usize> {
+        self.memory.as_ref().map(|memory| memory.used())
+    }
+
+    /// Reserve memory for `n`, waiting for it if needed.
+    pub(crate) async fn reserve(&self, n: &Notification) -> Option<Reservation> {
+        match self.memory {
+            Some(ref memory) => Some(memory.reserve(payload_size(n)).await),
+            None => None,
+        }
+    }
+}
+
+/// The size of the JSON encoding of the payload of `n`, without encoding it
+/// into memory.
+pub(crate) fn payload_size(n: &Notification) -> usize {
+    struct Counter(usize);
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, &n.payload);
+    counter.0
+}
@@ -0,0 +1,129 @@
+//! Reporting client measurements to a metrics backend.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::types::Notification;
+
+
+/// Receives measurements from the client, for forwarding to a metrics
+/// backend. See `ApplePushClient::set_metrics`.
+pub trait Metrics: Send + Sync {
+    /// Add `value` to the counter `name`.
+    fn counter(&self, name: &'static str, value: u64);
+
+    /// Add `value` to the counter `name` for the given tags, which are
+    /// `(tag, value)` pairs named in `tags`. By default the tags are ignored.
+    fn tagged_counter(&self, name: &'static str, value: u64, tags: &[(&'static str, &str)]) {
+        let _ = tags;
+        self.counter(name, value);
+    }
+
+    /// Record `value` in the histogram `name` for the given tags. By default
+    /// histograms are ignored.
+    fn histogram(&self, name: &'static str, value: f64, tags: &[(&'static str, &str)]) {
+        let _ = (name, value, tags);
+    }
+}
+
+/// Counter names reported by the client.
+pub mod names {
+    /// Notifications accepted by APNS.
+    pub const SENT: &str = "send.sent";
+    /// Notifications that failed to send, after any retries.
+    pub const FAILED: &str = "send.failed";
+    /// Retries made under the retry policy.
+    pub const RETRIED: &str = "retry.attempted";
+    /// Retries not made because the retry budget was used up.
+    pub const RETRY_BUDGET_EXHAUSTED: &str = "retry.budget_exhausted";
+    /// Notifications queued by `fire`.
+    pub const FIRE_ENQUEUED: &str = "fire.enqueued";
+    /// Notifications dropped by `fire` because its buffer was full.
+    pub const FIRE_DROPPED: &str = "fire.dropped";
+    /// Notifications queued by `fire` that failed to send.
+    pub const FIRE_FAILED: &str = "fire.failed";
+    /// Notifications dropped because they expired before they could be
+    /// sent.
+    pub const EXPIRED: &str = "send.expired";
+    /// Notifications a policy vetoed.
+    pub const VETOED: &str = "send.vetoed";
+    /// Attempts APNS refused, tagged with the `reason` it gave.
+    pub const API_ERROR: &str = "send.api_error";
+    /// Provider tokens signed, untagged.
+    pub const JWT_SIGNED: &str = "jwt.signed";
+
+    /// Histogram of the milliseconds each attempt took, from taking the
+    /// notification to reading the response.
+    pub const LATENCY_MS: &str = "send.latency_ms";
+    /// Histogram of the sizes in bytes of the payloads sent.
+    pub const PAYLOAD_BYTES: &str = "send.payload_bytes";
+    /// Histogram of the number of provider tokens signed in the hour up to
+    /// each signing. APNS wants no more than 3.
+    pub const JWT_SIGNED_LAST_HOUR: &str = "jwt.signed_last_hour";
+    /// Histogram of the depth of each internal queue, tagged with the
+    /// `queue`, recorded by `ApplePushClient::report_queue_stats`.
+    pub const QUEUE_DEPTH: &str = "queue.depth";
+    /// Histogram of how many milliseconds the oldest item of each internal
+    /// queue has waited, tagged with the `queue`. Nothing is recorded for
+    /// empty queues.
+    pub const QUEUE_OLDEST_AGE_MS: &str = "queue.oldest_age_ms";
+}
+
+/// Tags attached to counters about a notification.
+pub mod tags {
+    /// The topic of the notification, or `OTHER_TOPIC` beyond the topic
+    /// limit.
+    pub const TOPIC: &str = "topic";
+    /// The push type of the notification, as in its `apns-push-type`
+    /// header.
+    pub const PUSH_TYPE: &str = "push_type";
+    /// The reason APNS gave for refusing a notification, as in the error
+    /// response.
+    pub const REASON: &str = "reason";
+    /// The internal queue a measurement is about: `fire`, `permits` or
+    /// `retries`.
+    pub const QUEUE: &str = "queue";
+    /// The topic tag of notifications to topics beyond the topic limit.
+    pub const OTHER_TOPIC: &str = "other";
+}
+
+/// Default number of distinct topics tagged by name.
+pub(crate) const DEFAULT_TOPIC_LIMIT: usize = 100;
+
+/// The tags of a notification.
+pub(crate) struct Tags {
+    topic: String,
+    push_type: &'static str,
+}
+
+impl Tags {
+    pub fn as_pairs(&self) -> [(&'static str, &str); 2] {
+        [(tags::TOPIC, &self.topic), (tags::PUSH_TYPE, self.push_type)]
+    }
+}
+
+/// Tags notifications by topic, putting topics beyond the first `limit`
+/// seen in a shared bucket to bound the number of series.
+pub(crate) struct TopicTagger {
+    pub limit: usize,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl TopicTagger {
+    pub fn new(limit: usize) -> Self {
+        TopicTagger { limit, seen: Mutex::new(HashSet::new()) }
+    }
+
+    pub fn tags(&self, n: &Notification) -> Tags {
+        let mut seen = self.seen.lock().unwrap();
+        let topic = if seen.contains(&n.topic) {
+            n.topic.clone()
+        } else if seen.len() < self.limit {
+            seen.insert(n.topic.clone());
+            n.topic.clone()
+        } else {
+            tags::OTHER_TOPIC.to_owned()
+        };
+        Tags { topic, push_type: n.effective_push_type().as_str() }
+    }
+}
@@ -0,0 +1,46 @@
+use bytes::Bytes;
+use crate::Error;
+use http::{Request, Response};
+
+use crate::error::SendError;
+
+
+/// Post-processes the serialized JSON payload of each notification, for
+/// example to strip whitespace, inject experiment flags or add a checksum.
+///
+/// Middleware runs in the order it was added with
+/// `ApplePushClient::add_payload_middleware`, after the payload has been
+/// trimmed and before it is checked against the size limit, so what it
+/// produces must fit. An error stops the notification from being sent.
+pub trait PayloadMiddleware: Send + Sync {
+    /// Change `payload`, the body of a notification to `topic`.
+    fn on_payload(&self, topic: &str, payload: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// Inspects or changes requests just before they are sent, for example to
+/// add headers needed by a signing gateway or to tag experiments.
+///
+/// Middleware runs in the order it was added with
+/// `ApplePushClient::add_request_middleware`, after the payload has been
+/// checked against the size limit. An error stops the notification from
+/// being sent.
+pub trait RequestMiddleware: Send + Sync {
+    fn on_request(&self, req: &mut Request<Vec<u8>>) -> Result<(), Error>;
+}
+
+/// Inspects responses before they are turned into results, for example to
+/// classify errors from a proxy in front of APNS differently from errors
+/// from APNS itself.
+///
+/// Middleware runs in the order it was added with
+/// `ApplePushClient::add_response_middleware`, and every middleware sees
+/// every response.
+pub trait ResponseMiddleware: Send + Sync {
+    /// Return an error to fail the send with instead of the result the
+    /// response would give, or `None` to leave it alone. If several
+    /// middlewares return errors, the first one is used.
+    ///
+    /// For example, returning `SendError::Transport` for a proxy's 502 makes
+    /// the send retriable.
+    fn on_response(&self, res: &Response<Bytes>) -> Option<SendError>;
+}
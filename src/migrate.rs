@@ -0,0 +1,161 @@
+//! Builders shaped like those of the `a2` crate, for moving code from it to
+//! this one a piece at a time: swap the imports, and the payload
+//! construction keeps compiling while the rest is ported.
+//!
+//! ```
+//! use apple_push::migrate::{DefaultNotificationBuilder, NotificationOptions};
+//! use apple_push::Priority;
+//!
+//! let n = DefaultNotificationBuilder::new()
+//!     .set_title("Hi there")
+//!     .set_body("How are you?")
+//!     .set_badge(420)
+//!     .set_sound("ping.flac")
+//!     .build("device-token", NotificationOptions {
+//!         apns_topic: Some("com.example.app".to_owned()),
+//!         apns_priority: Some(Priority::High),
+//!         ..Default::default()
+//!     });
+//! assert_eq!(n.topic, "com.example.app");
+//! assert_eq!(n.payload.badge, Some(420));
+//! ```
+//!
+//! What maps to what:
+//!
+//! | `a2`                                    | this crate                                     |
+//! |-----------------------------------------|------------------------------------------------|
+//! | `DefaultNotificationBuilder::set_*`     | the `NotificationBuilder` method of that name  |
+//! | `NotificationOptions::apns_*`           | the `Notification` field of that name          |
+//! | `Payload::add_custom_data`              | `NotificationBuilder::custom`                  |
+//! | `Client::send` returning `Response`     | `ApplePushClient::send` returning the apns-id  |
+//! | `ErrorReason`                           | `ApiErrorReason`                               |
+//!
+//! Unlike with `a2`, a topic is needed: the crate always sends
+//! `apns-topic`, and APNS only accepts an empty one from certificate
+//! clients.
+
+use serde::Serialize;
+
+use crate::types::{CollapseId, Notification, NotificationBuilder, Priority, PushType};
+use crate::ApnsId;
+
+
+/// Headers of a notification, in the style of `a2::NotificationOptions`.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationOptions {
+    pub apns_id: Option<ApnsId>,
+    pub apns_push_type: Option<PushType>,
+    pub apns_expiration: Option<u64>,
+    pub apns_priority: Option<Priority>,
+    pub apns_topic: Option<String>,
+    pub apns_collapse_id: Option<CollapseId>,
+}
+
+impl NotificationOptions {
+    fn apply(self, n: &mut Notification) {
+        n.id = self.apns_id;
+        n.push_type = self.apns_push_type.or(n.push_type);
+        n.expiration = self.apns_expiration;
+        n.priority = self.apns_priority.or(n.priority);
+        n.topic = self.apns_topic.unwrap_or_default();
+        n.collapse_id = self.apns_collapse_id;
+    }
+}
+
+/// An alert notification, in the style of `a2::DefaultNotificationBuilder`.
+pub struct DefaultNotificationBuilder {
+    builder: NotificationBuilder,
+}
+
+impl Default for DefaultNotificationBuilder {
+    fn default() -> Self {
+        DefaultNotificationBuilder { builder: NotificationBuilder::new("", "") }
+    }
+}
+
+impl DefaultNotificationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with(self, f: impl FnOnce(NotificationBuilder) -> NotificationBuilder) -> Self {
+        DefaultNotificationBuilder { builder: f(self.builder) }
+    }
+
+    pub fn set_title(self, title: &str) -> Self {
+        self.with(|b| b.title(title))
+    }
+
+    pub fn set_subtitle(self, subtitle: &str) -> Self {
+        self.with(|b| b.subtitle(subtitle))
+    }
+
+    pub fn set_body(self, body: &str) -> Self {
+        self.with(|b| b.body(body))
+    }
+
+    pub fn set_badge(self, badge: u32) -> Self {
+        self.with(|b| b.badge(badge))
+    }
+
+    pub fn set_sound(self, sound: &str) -> Self {
+        self.with(|b| b.sound(sound))
+    }
+
+    pub fn set_category(self, category: &str) -> Self {
+        self.with(|b| b.category(category.to_owned()))
+    }
+
+    pub fn set_thread_id(self, thread_id: &str) -> Self {
+        self.with(|b| b.thread_id(thread_id.to_owned()))
+    }
+
+    pub fn set_launch_image(self, image: &str) -> Self {
+        self.with(|b| b.launch_image(image))
+    }
+
+    pub fn set_action_loc_key(self, key: &str) -> Self {
+        self.with(|b| b.action_loc_key(key))
+    }
+
+    pub fn set_mutable_content(self) -> Self {
+        self.with(NotificationBuilder::mutable_content)
+    }
+
+    pub fn set_content_available(self) -> Self {
+        self.with(NotificationBuilder::content_available)
+    }
+
+    /// Send `data` under `key` next to `aps`, as `a2` does with
+    /// `Payload::add_custom_data`.
+    pub fn add_custom_data<T: Serialize>(self, key: &str, data: &T) -> Result<Self, serde_json::Error> {
+        let value = serde_json::to_value(data)?;
+        Ok(self.with(|b| b.custom(key, value)))
+    }
+
+    /// The notification to `device_token`, with the headers in `options`.
+    pub fn build(self, device_token: &str, options: NotificationOptions) -> Notification {
+        let mut n = self.builder.build();
+        n.device_token = device_token.to_owned();
+        options.apply(&mut n);
+        n
+    }
+}
+
+/// A background notification, in the style of
+/// `a2::SilentNotificationBuilder`.
+#[derive(Clone, Debug, Default)]
+pub struct SilentNotificationBuilder;
+
+impl SilentNotificationBuilder {
+    pub fn new() -> Self {
+        SilentNotificationBuilder
+    }
+
+    /// The notification to `device_token`, with the headers in `options`.
+    pub fn build(self, device_token: &str, options: NotificationOptions) -> Notification {
+        let mut n = NotificationBuilder::silent_refresh("", device_token).build();
+        options.apply(&mut n);
+        n
+    }
+}
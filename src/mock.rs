@@ -0,0 +1,438 @@
+//! A local stand-in for APNS, for testing code that sends notifications.
+//!
+//! `MockApnsServer` checks every request against the rules APNS enforces,
+//! answering violations with the error APNS would send and recording them so
+//! that tests can fail with a description of each one. It also records
+//! every request it receives, for asserting on the headers and payloads the
+//! client sent, and can be scripted to answer conforming requests with
+//! errors such as `Unregistered` or `TooManyRequests`.
+//!
+//! ```no_run
+//! # async fn run(key: &[u8]) -> Result<(), apple_push::Error> {
+//! use apple_push::mock::MockApnsServer;
+//!
+//! let server = MockApnsServer::start()?;
+//! let http = hyper::Client::builder().http2_only(true).build(server.connector());
+//! let client = apple_push::ApplePushClient::new(http, "TEAMID", "KEYID", key)?;
+//! // ... send notifications with `client` ...
+//! server.assert_conforming();
+//! assert_eq!(server.requests()[0].header("apns-push-type"), Some("alert"));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use biscuit::{Empty, JWT};
+use futures::channel::oneshot;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, HeaderMap, Method, Request, Response, Server, StatusCode, Uri};
+use tokio::net::TcpStream;
+
+use crate::consts;
+use crate::error::{ApiErrorReason, ErrorResponse};
+use crate::id;
+
+
+/// A request that APNS would have refused.
+#[derive(Clone, Debug)]
+pub struct Violation {
+    /// The device token the request was sent to.
+    pub device_token: String,
+    /// The apns-id header of the request, if any.
+    pub apns_id: Option<String>,
+    /// The status the server answered with.
+    pub status: u16,
+    /// The reason the server answered with.
+    pub reason: ApiErrorReason,
+    /// What exactly was wrong.
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (token {}", self.message, self.device_token)?;
+        if let Some(ref id) = self.apns_id {
+            write!(f, ", apns-id {}", id)?;
+        }
+        write!(f, "): {} {}", self.status, self.reason)
+    }
+}
+
+/// A request the server received.
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    /// The device token the request was sent to.
+    pub device_token: String,
+    pub headers: HeaderMap,
+    /// The payload, as sent.
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// The value of a header, if it was sent and is text.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// The payload as JSON, or `Value::Null` if it isn't JSON.
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+}
+
+/// How the server answers a conforming request. See
+/// `MockApnsServer::respond`.
+#[derive(Clone, Debug)]
+pub enum MockResponse {
+    /// A 200, echoing the apns-id of the request.
+    Success,
+    /// An error response, as APNS sends them.
+    Error {
+        status: u16,
+        reason: ApiErrorReason,
+        /// Milliseconds since the epoch, sent on 410 responses.
+        timestamp: Option<u64>,
+        /// Seconds sent in a `retry-after` header, as proxies in front of
+        /// APNS may.
+        retry_after: Option<u64>,
+    },
+}
+
+impl MockResponse {
+    pub fn error(status: u16, reason: ApiErrorReason) -> Self {
+        MockResponse::Error { status, reason, timestamp: None, retry_after: None }
+    }
+
+    /// A 410 `Unregistered`, with the time in milliseconds since the epoch
+    /// at which APNS found the token invalid.
+    pub fn unregistered(timestamp: u64) -> Self {
+        MockResponse::Error { status: 410, reason: ApiErrorReason::Unregistered, timestamp: Some(timestamp), retry_after: None }
+    }
+
+    /// A 429 `TooManyRequests`, with a `retry-after` header if given.
+    pub fn too_many_requests(retry_after: Option<u64>) -> Self {
+        MockResponse::Error { status: 429, reason: ApiErrorReason::TooManyRequests, timestamp: None, retry_after }
+    }
+}
+
+#[derive(Default)]
+struct Recorded {
+    violations: Mutex<Vec<Violation>>,
+    requests: Mutex<Vec<ReceivedRequest>>,
+    responses: Mutex<VecDeque<MockResponse>>,
+}
+
+/// An HTTP/2 server on a local port that behaves like APNS.
+///
+/// The server runs on the tokio runtime it was started from, and stops when
+/// dropped.
+pub struct MockApnsServer {
+    addr: SocketAddr,
+    recorded: Arc<Recorded>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockApnsServer {
+    /// Start a server on a free local port. Must be called from within a
+    /// tokio runtime.
+    pub fn start() -> Result<Self, hyper::Error> {
+        let recorded = Arc::new(Recorded::default());
+        let shared = recorded.clone();
+        let make_service = make_service_fn(move |_| {
+            let recorded = shared.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req| handle(req, recorded.clone())))
+            }
+        });
+        let server = Server::try_bind(&([127, 0, 0, 1], 0).into())?
+            .http2_only(true)
+            .serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, stop) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = stop.await;
+        }));
+        Ok(MockApnsServer { addr, recorded, shutdown: Some(shutdown) })
+    }
+
+    /// The address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A connector that connects to this server whatever the URL, for
+    /// building a client with `hyper::Client::builder().http2_only(true)` or
+    /// `ApplePushClient::new_h2`.
+    pub fn connector(&self) -> MockConnector {
+        MockConnector { addr: self.addr }
+    }
+
+    /// The requests refused so far.
+    pub fn violations(&self) -> Vec<Violation> {
+        self.recorded.violations.lock().unwrap().clone()
+    }
+
+    /// Every request received so far, in the order received, including
+    /// refused ones.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.recorded.requests.lock().unwrap().clone()
+    }
+
+    /// Answer the next conforming request with `response`. Responses queue
+    /// up, each answering one request; once they run out, conforming
+    /// requests succeed. Requests APNS would refuse are still refused.
+    pub fn respond(&self, response: MockResponse) {
+        self.recorded.responses.lock().unwrap().push_back(response);
+    }
+
+    /// Panic with a description of every refused request, if there were any.
+    pub fn assert_conforming(&self) {
+        let violations = self.violations();
+        if !violations.is_empty() {
+            let list: Vec<_> = violations.iter().map(|v| format!("  {}", v)).collect();
+            panic!("{} request(s) would be refused by APNS:\n{}", violations.len(), list.join("\n"));
+        }
+    }
+}
+
+impl Drop for MockApnsServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Connects to a `MockApnsServer`, ignoring the host of the URL.
+#[derive(Clone, Debug)]
+pub struct MockConnector {
+    addr: SocketAddr,
+}
+
+impl Service<Uri> for MockConnector {
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        Box::pin(TcpStream::connect(self.addr))
+    }
+}
+
+async fn handle(req: Request<Body>, recorded: Arc<Recorded>) -> Result<Response<Body>, hyper::Error> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok());
+    let device_token = parts.uri.path().strip_prefix(consts::DEVICE_PATH).unwrap_or_default().to_owned();
+    let apns_id = header(consts::APNS_ID).map(str::to_owned);
+    recorded.requests.lock().unwrap().push(ReceivedRequest {
+        device_token: device_token.clone(),
+        headers: parts.headers.clone(),
+        body: body.to_vec(),
+    });
+
+    let found = check(&parts, &body);
+    if let Some((status, reason, _)) = found.first().cloned() {
+        let mut violations = recorded.violations.lock().unwrap();
+        for (status, reason, message) in found {
+            violations.push(Violation {
+                device_token: device_token.clone(),
+                apns_id: apns_id.clone(),
+                status: status.as_u16(),
+                reason: ApiErrorReason::from_str(reason),
+                message,
+            });
+        }
+        let body = serde_json::to_vec(&ErrorResponse { reason: reason.to_owned(), timestamp: None }).unwrap_or_default();
+        let mut response = Response::new(body.into());
+        *response.status_mut() = status;
+        return Ok(response);
+    }
+
+    let scripted = recorded.responses.lock().unwrap().pop_front();
+    if let Some(MockResponse::Error { status, reason, timestamp, retry_after }) = scripted {
+        let body = serde_json::to_vec(&ErrorResponse { reason: reason.to_string(), timestamp }).unwrap_or_default();
+        let mut response = Response::builder()
+            .status(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+        if let Some(retry_after) = retry_after {
+            response = response.header(consts::RETRY_AFTER, retry_after);
+        }
+        return Ok(response.body(body.into()).unwrap_or_default());
+    }
+
+    let id = apns_id.unwrap_or_else(|| id::generate().to_string());
+    let mut response = Response::builder().header(consts::APNS_ID, id);
+    // Like APNS, only the sandbox sends unique ids.
+    if parts.uri.host().is_some_and(|host| consts::APN_URL_DEV.ends_with(host)) {
+        response = response.header(consts::APNS_UNIQUE_ID, id::generate().to_string());
+    }
+    Ok(response.body(Body::empty()).unwrap_or_default())
+}
+
+type Found = Vec<(StatusCode, &'static str, String)>;
+
+/// Every way in which a request breaks the rules of APNS.
+fn check(parts: &http::request::Parts, body: &[u8]) -> Found {
+    let mut found = Found::new();
+    let header = |name: &str| parts.headers.get(name).map(|v| v.to_str().unwrap_or_default());
+    let mut fail = |status, reason, message: String| found.push((status, reason, message));
+
+    if parts.method != Method::POST {
+        fail(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", format!("method is {}, not POST", parts.method));
+    }
+    match parts.uri.path().strip_prefix(consts::DEVICE_PATH) {
+        None => fail(StatusCode::NOT_FOUND, "BadPath", format!("path {} doesn't start with {}", parts.uri.path(), consts::DEVICE_PATH)),
+        Some("") => fail(StatusCode::BAD_REQUEST, "MissingDeviceToken", "path has no device token".to_owned()),
+        Some(token) if !token.chars().all(|c| c.is_ascii_hexdigit()) || token.len() % 2 != 0 => {
+            fail(StatusCode::BAD_REQUEST, "BadDeviceToken", format!("device token {:?} isn't an even number of hex digits", token))
+        }
+        Some(_) => (),
+    }
+
+    match header(consts::AUTHORIZATION) {
+        None => fail(StatusCode::FORBIDDEN, "MissingProviderToken", "no authorization header".to_owned()),
+        Some(value) => match value.strip_prefix("bearer ") {
+            None => fail(StatusCode::FORBIDDEN, "InvalidProviderToken", format!("authorization {:?} isn't a bearer token", value)),
+            Some(token) => {
+                if let Err(message) = check_provider_token(token) {
+                    fail(StatusCode::FORBIDDEN, "InvalidProviderToken", message);
+                }
+            }
+        },
+    }
+
+    let topic = header(consts::APNS_TOPIC).unwrap_or_default();
+    if topic.is_empty() {
+        fail(StatusCode::BAD_REQUEST, "MissingTopic", "no apns-topic header".to_owned());
+    }
+    if let Some(id) = header(consts::APNS_ID) {
+        if !is_uuid(id) {
+            fail(StatusCode::BAD_REQUEST, "BadMessageId", format!("apns-id {:?} isn't a UUID", id));
+        }
+    }
+    if let Some(expiration) = header(consts::APNS_EXPIRATION) {
+        if expiration.parse::<u64>().is_err() {
+            fail(StatusCode::BAD_REQUEST, "BadExpirationDate", format!("apns-expiration {:?} isn't a UNIX timestamp", expiration));
+        }
+    }
+    let priority = header(consts::APNS_PRIORITY);
+    if let Some(priority) = priority {
+        if !["1", "5", "10"].contains(&priority) {
+            fail(StatusCode::BAD_REQUEST, "BadPriority", format!("apns-priority {:?} isn't 1, 5 or 10", priority));
+        }
+    }
+    if let Some(collapse_id) = header(consts::APNS_COLLAPSE_ID) {
+        if collapse_id.len() > consts::MAX_COLLAPSE_ID_SIZE {
+            fail(StatusCode::BAD_REQUEST, "BadCollapseId", format!("apns-collapse-id is {} bytes (must be at most {})", collapse_id.len(), consts::MAX_COLLAPSE_ID_SIZE));
+        }
+    }
+
+    let limit = if topic.ends_with(".voip") { consts::MAX_VOIP_PAYLOAD_SIZE } else { consts::MAX_PAYLOAD_SIZE };
+    let aps = if body.is_empty() {
+        fail(StatusCode::BAD_REQUEST, "PayloadEmpty", "body is empty".to_owned());
+        None
+    } else if body.len() > limit {
+        fail(StatusCode::PAYLOAD_TOO_LARGE, "PayloadTooLarge", format!("body is {} bytes (must be at most {})", body.len(), limit));
+        None
+    } else {
+        // APNS has no specific reason for malformed payloads.
+        match serde_json::from_slice::<serde_json::Value>(body) {
+            Err(e) => {
+                fail(StatusCode::BAD_REQUEST, "BadPayload", format!("body isn't JSON: {}", e));
+                None
+            }
+            Ok(serde_json::Value::Object(mut payload)) => match payload.remove("aps") {
+                Some(serde_json::Value::Object(aps)) => Some(aps),
+                _ => {
+                    fail(StatusCode::BAD_REQUEST, "BadPayload", "body has no aps dictionary".to_owned());
+                    None
+                }
+            },
+            Ok(_) => {
+                fail(StatusCode::BAD_REQUEST, "BadPayload", "body isn't a JSON object".to_owned());
+                None
+            }
+        }
+    };
+
+    if let Some(push_type) = header(consts::APNS_PUSH_TYPE) {
+        match push_type_topic_suffix(push_type) {
+            None => fail(StatusCode::BAD_REQUEST, "InvalidPushType", format!("apns-push-type {:?} isn't a push type", push_type)),
+            Some(Some(suffix)) if !topic.ends_with(suffix) => {
+                fail(StatusCode::BAD_REQUEST, "InvalidPushType", format!("apns-push-type {} needs a topic ending in {}, not {:?}", push_type, suffix, topic))
+            }
+            Some(_) => (),
+        }
+        if push_type == "background" {
+            if priority != Some("5") {
+                fail(StatusCode::BAD_REQUEST, "BadPriority", format!("background notifications need apns-priority 5, not {:?}", priority.unwrap_or("10")));
+            }
+            if let Some(ref aps) = aps {
+                if aps.get("content-available").and_then(|v| v.as_u64()) != Some(1) {
+                    fail(StatusCode::BAD_REQUEST, "BadPayload", "background notifications need content-available 1".to_owned());
+                }
+                if let Some(key) = ["alert", "badge", "sound"].iter().find(|key| aps.contains_key(**key)) {
+                    fail(StatusCode::BAD_REQUEST, "BadPayload", format!("background notifications can't have {}", key));
+                }
+            }
+        }
+    }
+
+    found
+}
+
+/// Check the parts of a provider token APNS looks at, without verifying the
+/// signature.
+fn check_provider_token(token: &str) -> Result<(), String> {
+    let jwt = JWT::<Empty, Empty>::new_encoded(token);
+    let header = jwt.unverified_header().map_err(|e| format!("provider token header is unreadable: {}", e))?;
+    let claims = jwt.unverified_payload().map_err(|e| format!("provider token claims are unreadable: {}", e))?;
+    if header.registered.algorithm != biscuit::jwa::SignatureAlgorithm::ES256 {
+        return Err(format!("provider token is signed with {:?}, not ES256", header.registered.algorithm));
+    }
+    if header.registered.key_id.is_none() {
+        return Err("provider token has no kid".to_owned());
+    }
+    if claims.registered.issuer.is_none() {
+        return Err("provider token has no iss".to_owned());
+    }
+    if claims.registered.issued_at.is_none() {
+        return Err("provider token has no iat".to_owned());
+    }
+    Ok(())
+}
+
+/// The topic suffix a push type needs: `None` for unknown push types, and
+/// `Some(None)` for push types sent to the bundle id itself.
+fn push_type_topic_suffix(push_type: &str) -> Option<Option<&'static str>> {
+    match push_type {
+        "alert" | "background" | "mdm" => Some(None),
+        "voip" => Some(Some(".voip")),
+        "complication" => Some(Some(".complication")),
+        "fileprovider" => Some(Some(".pushkit.fileprovider")),
+        "liveactivity" => Some(Some(".push-type.liveactivity")),
+        "location" => Some(Some(".location-query")),
+        "pushtotalk" => Some(Some(".voip-ptt")),
+        _ => None,
+    }
+}
+
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<_> = value.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip(&[8, 4, 4, 4, 12]).all(|(group, &len)| {
+            group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
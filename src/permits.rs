@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{ApnsId, ApplePushClient, Notification, SendError};
+
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Limit how many notifications are sent at once. A send takes a permit
+    /// before anything else and holds it until it returns, including while
+    /// waiting between retries. `None` removes the limit.
+    pub fn set_concurrency_limit(&mut self, limit: Option<usize>) {
+        self.permits = limit.map(|limit| Arc::new(Semaphore::new(limit)));
+        self.concurrency_limit = limit;
+    }
+
+    /// The semaphore limiting concurrent sends, if there is a limit.
+    ///
+    /// Producers can acquire a permit with `acquire_owned` before preparing
+    /// a notification and hand it to `send_with_permit`, so that work is
+    /// only generated as fast as it can be sent.
+    pub fn permits(&self) -> Option<Arc<Semaphore>> {
+        self.permits.clone()
+    }
+
+    /// Like `send`, using a permit already acquired from `permits` instead of
+    /// waiting for one. The permit is released when the send returns.
+    pub async fn send_with_permit(&self, n: Notification, permit: OwnedSemaphorePermit) -> Result<ApnsId, SendError> {
+        let _permit = permit;
+        let _reservation = self.reserve(&n).await;
+        self.send_counted(n).await.map(|response| response.id)
+    }
+}
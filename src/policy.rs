@@ -0,0 +1,42 @@
+use crate::Error;
+use futures::future::BoxFuture;
+
+use crate::types::Notification;
+
+
+/// What a `Policy` decided about a notification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Send the notification, as the policy left it.
+    Allow,
+    /// Don't send the notification; the send fails with
+    /// `SendError::Vetoed` and the reason.
+    Veto(String),
+}
+
+/// A rule every notification is checked against before it is sent, such
+/// as not sending to users who opted out, or not making sounds at night,
+/// so that such rules live in one place rather than at every call site.
+///
+/// Policies run in the order they were added with
+/// `ApplePushClient::add_policy`, once per send rather than per attempt,
+/// each seeing the notification as the ones before left it. The first
+/// veto stops the send, and an error fails it.
+///
+/// ```
+/// use apple_push::{Notification, Policy, Verdict};
+/// use futures::future::{self, BoxFuture};
+///
+/// struct QuietHours;
+///
+/// impl Policy for QuietHours {
+///     fn check<'a>(&'a self, n: &'a mut Notification) -> BoxFuture<'a, Result<Verdict, apple_push::Error>> {
+///         std::sync::Arc::make_mut(&mut n.payload).sound = None;
+///         Box::pin(future::ok(Verdict::Allow))
+///     }
+/// }
+/// ```
+pub trait Policy: Send + Sync {
+    /// Decide whether `n` is sent, changing it first if need be.
+    fn check<'a>(&'a self, n: &'a mut Notification) -> BoxFuture<'a, Result<Verdict, Error>>;
+}
@@ -0,0 +1,28 @@
+//! The types most applications need, and that only change with the major
+//! version:
+//!
+//! ```
+//! use apple_push::prelude::*;
+//!
+//! let n: Notification = NotificationBuilder::new("com.example.app", "00fc13adff785122")
+//!     .title("Hello")
+//!     .priority(Priority::High)
+//!     .build();
+//! assert_eq!(n.effective_push_type(), PushType::Alert);
+//! ```
+//!
+//! Everything else is public and follows semver too, except what is behind
+//! the `unstable-*` features, which may change in any release until it is
+//! stabilized.
+
+pub use crate::error::{ApiError, ApiErrorReason, BuildError, SendError, ValidationError};
+pub use crate::id::ApnsId;
+pub use crate::retry::RetryPolicy;
+pub use crate::types::{
+    Alert, CollapseId, Environment, InterruptionLevel, Notification, NotificationBuilder, Payload, Priority, PushType,
+};
+
+#[cfg(feature = "client")]
+pub use crate::batch::{BatchReport, SendOptions};
+#[cfg(feature = "client")]
+pub use crate::client::ApplePushClient;
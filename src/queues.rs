@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// How far behind a queue is: what waits in it, and for how long the item
+/// that has waited longest has. See `ApplePushClient::queue_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    pub depth: usize,
+    /// `None` when the queue is empty.
+    pub oldest_age: Option<Duration>,
+}
+
+/// The sends waiting in one of the places a client queues them, keyed by
+/// order of arrival, so that the first is the oldest.
+#[derive(Default)]
+pub(crate) struct Queue {
+    waiting: Mutex<(u64, BTreeMap<u64, Instant>)>,
+}
+
+impl Queue {
+    /// Count an item as waiting until the returned entry is dropped.
+    pub fn enter(self: &Arc<Self>) -> Entry {
+        let mut waiting = self.waiting.lock().unwrap();
+        let id = waiting.0;
+        waiting.0 += 1;
+        waiting.1.insert(id, Instant::now());
+        Entry { queue: self.clone(), id }
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let waiting = self.waiting.lock().unwrap();
+        QueueStats {
+            depth: waiting.1.len(),
+            oldest_age: waiting.1.values().next().map(Instant::elapsed),
+        }
+    }
+}
+
+pub(crate) struct Entry {
+    queue: Arc<Queue>,
+    id: u64,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        self.queue.waiting.lock().unwrap().1.remove(&self.id);
+    }
+}
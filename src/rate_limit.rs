@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+use futures::future::{self, BoxFuture};
+
+
+/// Limits how often notifications are sent to each device token.
+///
+/// APNS answers 429 to a provider that pushes to the same device too often;
+/// a limiter lets the client refuse such sends itself, with
+/// `SendError::RateLimited`. See `ApplePushClient::set_rate_limiter`.
+pub trait RateLimiter: Send + Sync {
+    /// Count a send to `device_token`. Resolves to `None` if the send may go
+    /// ahead, or to how long to wait before the token is below its limit
+    /// again.
+    fn acquire<'a>(&'a self, device_token: &'a str) -> BoxFuture<'a, Result<Option<Duration>, Error>>;
+}
+
+/// A limiter allowing `limit` sends to each device token per `window`,
+/// within a single process.
+pub struct MemoryRateLimiter {
+    limit: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        MemoryRateLimiter {
+            limit,
+            window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RateLimiter for MemoryRateLimiter {
+    fn acquire<'a>(&'a self, device_token: &'a str) -> BoxFuture<'a, Result<Option<Duration>, Error>> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < self.window);
+        let (started, count) = windows.entry(device_token.to_owned()).or_insert((now, 0));
+        let wait = if *count < self.limit {
+            *count += 1;
+            None
+        } else {
+            Some(self.window - now.duration_since(*started))
+        };
+        Box::pin(future::ready(Ok(wait)))
+    }
+}
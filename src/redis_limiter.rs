@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use crate::Error;
+use futures::future::BoxFuture;
+use redis::aio::MultiplexedConnection;
+use redis::Script;
+
+use crate::rate_limit::RateLimiter;
+
+
+/// Counts the send in the current window of the token, starting the window
+/// with the first send, and answers the time left in the window once the
+/// count is over the limit, or 0.
+const ACQUIRE: &str = r"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+end
+if count > tonumber(ARGV[2]) then
+    return math.max(redis.call('PTTL', KEYS[1]), 1)
+end
+return 0
+";
+
+/// A limiter allowing `limit` sends to each device token per `window`,
+/// counted in Redis so that every sender sharing the Redis server shares the
+/// limits.
+///
+/// Only available with the `redis` feature.
+pub struct RedisRateLimiter {
+    connection: MultiplexedConnection,
+    prefix: String,
+    limit: u32,
+    window: Duration,
+    script: Script,
+}
+
+impl RedisRateLimiter {
+    /// Connect to the Redis server at `url`, e.g. `redis://127.0.0.1/`.
+    pub async fn connect(url: &str, limit: u32, window: Duration) -> Result<Self, Error> {
+        let connection = redis::Client::open(url)?.get_multiplexed_tokio_connection().await?;
+        Ok(Self::new(connection, limit, window))
+    }
+
+    /// Use an existing connection.
+    pub fn new(connection: MultiplexedConnection, limit: u32, window: Duration) -> Self {
+        RedisRateLimiter {
+            connection,
+            prefix: "apns:rate:".to_owned(),
+            limit,
+            window,
+            script: Script::new(ACQUIRE),
+        }
+    }
+
+    /// Set the prefix of the keys the counts are stored under, `apns:rate:`
+    /// by default. The device token follows it.
+    pub fn set_key_prefix(&mut self, prefix: &str) {
+        self.prefix = prefix.to_owned();
+    }
+}
+
+impl RateLimiter for RedisRateLimiter {
+    fn acquire<'a>(&'a self, device_token: &'a str) -> BoxFuture<'a, Result<Option<Duration>, Error>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let wait: u64 = self.script
+                .key(format!("{}{}", self.prefix, device_token))
+                .arg(self.window.as_millis() as u64)
+                .arg(self.limit)
+                .invoke_async(&mut connection)
+                .await?;
+            Ok(if wait == 0 { None } else { Some(Duration::from_millis(wait)) })
+        })
+    }
+}
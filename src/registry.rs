@@ -0,0 +1,146 @@
+//! Bookkeeping of the device tokens registered by the users of an app, and
+//! of their Live Activity push-to-start tokens.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{Environment, SendError};
+
+mod push_to_start;
+
+pub use self::push_to_start::{MemoryPushToStartStore, PushToStartStore, PushToStartToken};
+
+
+/// A device token registered by a user of the app.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Registration {
+    pub user_id: String,
+    pub device_token: String,
+    /// The topic to send to. Usually the app bundle id.
+    pub topic: String,
+    pub environment: Environment,
+    /// When the device last registered this token.
+    pub last_seen: SystemTime,
+}
+
+impl Registration {
+    /// Create a registration last seen now.
+    pub fn new(user_id: &str, device_token: &str, topic: &str, environment: Environment) -> Self {
+        Registration {
+            user_id: user_id.into(),
+            device_token: device_token.into(),
+            topic: topic.into(),
+            environment,
+            last_seen: SystemTime::now(),
+        }
+    }
+}
+
+/// Storage for registrations. A registration is identified by its device
+/// token and topic.
+pub trait RegistryStore: Send + Sync {
+    /// Record a registration, replacing any existing one for the same device
+    /// token and topic.
+    fn record(&self, registration: Registration) -> Result<(), Error>;
+
+    /// Remove the registration for a device token and topic, if any.
+    fn remove(&self, device_token: &str, topic: &str) -> Result<(), Error>;
+
+    /// All registrations of a user.
+    fn for_user(&self, user_id: &str) -> Result<Vec<Registration>, Error>;
+
+    /// All registrations for a topic, for fanning out to every device.
+    fn for_topic(&self, topic: &str) -> Result<Vec<Registration>, Error>;
+
+    /// The registration for a device token and topic, if any. Looks through
+    /// `for_topic` unless overridden.
+    fn get(&self, device_token: &str, topic: &str) -> Result<Option<Registration>, Error> {
+        Ok(self.for_topic(topic)?.into_iter().find(|r| r.device_token == device_token))
+    }
+
+    /// Remove the registration if `error` means the device token will never
+    /// be valid again (see `ApiError::should_remove_token`), and APNS
+    /// found it invalid after the registration was last seen: a device that
+    /// registered the token again since keeps it. See
+    /// `ApiError::invalidates_registration`.
+    /// Returns whether the registration was removed.
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use apple_push::{ApiError, ApiErrorReason, Environment, SendError};
+    /// use apple_push::registry::{MemoryRegistry, Registration, RegistryStore};
+    ///
+    /// let registry = MemoryRegistry::new();
+    /// registry.record(Registration::new("user", "token", "com.example.app", Environment::Production)).unwrap();
+    /// let unregistered_at = |at| {
+    ///     let mut error = ApiError::new(410, ApiErrorReason::Unregistered);
+    ///     error.timestamp = Some(at);
+    ///     SendError::Api(error)
+    /// };
+    ///
+    /// // The device registered the token again after APNS found it invalid.
+    /// let before = SystemTime::now() - Duration::from_secs(60);
+    /// assert!(!registry.invalidate_on_error("token", "com.example.app", &unregistered_at(before)).unwrap());
+    /// assert!(registry.get("token", "com.example.app").unwrap().is_some());
+    ///
+    /// let after = SystemTime::now() + Duration::from_secs(60);
+    /// assert!(registry.invalidate_on_error("token", "com.example.app", &unregistered_at(after)).unwrap());
+    /// assert!(registry.get("token", "com.example.app").unwrap().is_none());
+    /// ```
+    fn invalidate_on_error(&self, device_token: &str, topic: &str, error: &SendError) -> Result<bool, Error> {
+        let error = match error.as_api_error() {
+            Some(error) if error.should_remove_token() => error,
+            _ => return Ok(false),
+        };
+        let invalid = self.get(device_token, topic)?.is_some_and(|r| error.invalidates_registration(r.last_seen));
+        if invalid {
+            self.remove(device_token, topic)?;
+        }
+        Ok(invalid)
+    }
+}
+
+/// An in-memory `RegistryStore`, for tests and single-process deployments.
+#[derive(Default)]
+pub struct MemoryRegistry {
+    registrations: Mutex<HashMap<(String, String), Registration>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn filter<F: Fn(&Registration) -> bool>(&self, f: F) -> Vec<Registration> {
+        self.registrations.lock().unwrap().values().filter(|r| f(r)).cloned().collect()
+    }
+}
+
+impl RegistryStore for MemoryRegistry {
+    fn record(&self, registration: Registration) -> Result<(), Error> {
+        let key = (registration.device_token.clone(), registration.topic.clone());
+        self.registrations.lock().unwrap().insert(key, registration);
+        Ok(())
+    }
+
+    fn remove(&self, device_token: &str, topic: &str) -> Result<(), Error> {
+        self.registrations.lock().unwrap().remove(&(device_token.to_owned(), topic.to_owned()));
+        Ok(())
+    }
+
+    fn for_user(&self, user_id: &str) -> Result<Vec<Registration>, Error> {
+        Ok(self.filter(|r| r.user_id == user_id))
+    }
+
+    fn for_topic(&self, topic: &str) -> Result<Vec<Registration>, Error> {
+        Ok(self.filter(|r| r.topic == topic))
+    }
+
+    fn get(&self, device_token: &str, topic: &str) -> Result<Option<Registration>, Error> {
+        Ok(self.registrations.lock().unwrap().get(&(device_token.to_owned(), topic.to_owned())).cloned())
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::{Environment, SendError};
+
+
+/// A push-to-start token, with which the app lets Live Activities of one of
+/// its `ActivityAttributes` types be started remotely on one device. See
+/// `live_activity::start`.
+///
+/// Unlike device tokens, the system rotates these as it pleases, and the
+/// app reports each new one through `pushToStartTokenUpdates`; the old one
+/// stops working.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PushToStartToken {
+    pub user_id: String,
+    /// Identifies the app installation the token belongs to, such as its
+    /// device token. A new token for the same installation and attributes
+    /// type replaces the old one.
+    pub installation_id: String,
+    pub bundle_id: String,
+    /// The name of the `ActivityAttributes` type the token starts.
+    pub attributes_type: String,
+    pub token: String,
+    pub environment: Environment,
+    /// When the app last reported the token.
+    pub updated_at: SystemTime,
+}
+
+impl PushToStartToken {
+    /// Create a token reported now.
+    pub fn new(user_id: &str, installation_id: &str, bundle_id: &str, attributes_type: &str, token: &str, environment: Environment) -> Self {
+        PushToStartToken {
+            user_id: user_id.into(),
+            installation_id: installation_id.into(),
+            bundle_id: bundle_id.into(),
+            attributes_type: attributes_type.into(),
+            token: token.into(),
+            environment,
+            updated_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Storage for push-to-start tokens. A token is identified by its
+/// installation and attributes type.
+pub trait PushToStartStore: Send + Sync {
+    /// Record a token reported by the app, replacing the one previously
+    /// recorded for the same installation and attributes type, which is
+    /// returned if it was a different token.
+    fn record(&self, token: PushToStartToken) -> Result<Option<PushToStartToken>, Error>;
+
+    /// Remove a token, if it is recorded.
+    fn remove(&self, token: &str) -> Result<(), Error>;
+
+    /// A token, if it is recorded.
+    fn get(&self, token: &str) -> Result<Option<PushToStartToken>, Error>;
+
+    /// The tokens of a user for an attributes type, for starting an
+    /// activity on each of their devices.
+    fn for_user(&self, user_id: &str, attributes_type: &str) -> Result<Vec<PushToStartToken>, Error>;
+
+    /// The tokens of every installation of an app for an attributes type.
+    fn for_bundle(&self, bundle_id: &str, attributes_type: &str) -> Result<Vec<PushToStartToken>, Error>;
+
+    /// Remove and return the tokens that haven't been reported since
+    /// `cutoff`, which the system has most likely replaced by now.
+    fn remove_stale(&self, cutoff: SystemTime) -> Result<Vec<PushToStartToken>, Error>;
+
+    /// Remove the token if `error` means it will never be valid again, and
+    /// APNS found it invalid after the app last reported it. See
+    /// `ApiError::invalidates_registration`.
+    /// Returns whether the token was removed.
+    fn invalidate_on_error(&self, token: &str, error: &SendError) -> Result<bool, Error> {
+        let error = match error.as_api_error() {
+            Some(error) if error.should_remove_token() => error,
+            _ => return Ok(false),
+        };
+        let invalid = self.get(token)?.is_some_and(|t| error.invalidates_registration(t.updated_at));
+        if invalid {
+            self.remove(token)?;
+        }
+        Ok(invalid)
+    }
+}
+
+/// An in-memory `PushToStartStore`, for tests and single-process
+/// deployments.
+///
+/// ```
+/// use apple_push::Environment;
+/// use apple_push::registry::{MemoryPushToStartStore, PushToStartStore, PushToStartToken};
+///
+/// let store = MemoryPushToStartStore::new();
+/// let token = |token| PushToStartToken::new("user", "install", "com.example.app", "MatchAttributes", token, Environment::Production);
+/// assert!(store.record(token("first")).unwrap().is_none());
+/// assert_eq!(store.record(token("second")).unwrap().unwrap().token, "first");
+/// assert_eq!(store.for_user("user", "MatchAttributes").unwrap().len(), 1);
+/// ```
+#[derive(Default)]
+pub struct MemoryPushToStartStore {
+    tokens: Mutex<HashMap<(String, String), PushToStartToken>>,
+}
+
+impl MemoryPushToStartStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn filter<F: Fn(&PushToStartToken) -> bool>(&self, f: F) -> Vec<PushToStartToken> {
+        self.tokens.lock().unwrap().values().filter(|t| f(t)).cloned().collect()
+    }
+}
+
+impl PushToStartStore for MemoryPushToStartStore {
+    fn record(&self, token: PushToStartToken) -> Result<Option<PushToStartToken>, Error> {
+        let key = (token.installation_id.clone(), token.attributes_type.clone());
+        let replaced = self.tokens.lock().unwrap().insert(key, token.clone());
+        Ok(replaced.filter(|old| old.token != token.token))
+    }
+
+    fn remove(&self, token: &str) -> Result<(), Error> {
+        self.tokens.lock().unwrap().retain(|_, t| t.token != token);
+        Ok(())
+    }
+
+    fn get(&self, token: &str) -> Result<Option<PushToStartToken>, Error> {
+        Ok(self.filter(|t| t.token == token).pop())
+    }
+
+    fn for_user(&self, user_id: &str, attributes_type: &str) -> Result<Vec<PushToStartToken>, Error> {
+        Ok(self.filter(|t| t.user_id == user_id && t.attributes_type == attributes_type))
+    }
+
+    fn for_bundle(&self, bundle_id: &str, attributes_type: &str) -> Result<Vec<PushToStartToken>, Error> {
+        Ok(self.filter(|t| t.bundle_id == bundle_id && t.attributes_type == attributes_type))
+    }
+
+    fn remove_stale(&self, cutoff: SystemTime) -> Result<Vec<PushToStartToken>, Error> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let stale: Vec<_> = tokens.iter()
+            .filter(|(_, t)| t.updated_at < cutoff)
+            .map(|(key, _)| key.clone())
+            .collect();
+        Ok(stale.into_iter().filter_map(|key| tokens.remove(&key)).collect())
+    }
+}
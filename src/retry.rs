@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "client")]
+use crate::rng::{self, Rng};
+use crate::error::SendError;
+
+
+/// How failed sends are retried.
+///
+/// Sends are retried when `SendError::is_retriable` holds: after connection
+/// errors, the rate limits and the APNS responses that indicate a temporary
+/// condition, waiting twice as long before each retry as before the last
+/// one, or as long as a response or rate limit asked with `retry-after` if
+/// that is longer. Sends refused with `ExpiredProviderToken` are retried
+/// with a newly signed token.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the wait before any retry.
+    pub max_backoff: Duration,
+    /// Fraction of each wait that is random, between 0 and 1, so that
+    /// clients failing together don't retry together.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before the retry following `failed` failed attempts, or
+    /// `None` if `error` shouldn't be retried.
+    #[cfg(feature = "client")]
+    pub(crate) fn backoff(&self, failed: u32, error: &SendError, rng: &dyn Rng) -> Option<Duration> {
+        if failed >= self.max_attempts || !error.is_retriable() {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(failed - 1);
+        let mut backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter > 0.0 {
+            backoff = backoff.mul_f64(1.0 - jitter * rng::fraction(rng));
+        }
+        let asked = match error {
+            SendError::RateLimited { retry_after } => *retry_after,
+            _ => error.as_api_error().and_then(|e| e.retry_after).unwrap_or_default(),
+        };
+        Some(backoff.max(asked.min(self.max_backoff)))
+    }
+}
+
+/// One failed attempt to send a notification.
+#[derive(Debug)]
+pub struct AttemptRecord {
+    /// When the attempt started.
+    pub at: SystemTime,
+    pub error: SendError,
+    /// How long the client waited before the next attempt, or `None` if this
+    /// was the last one.
+    pub backoff: Option<Duration>,
+}
+
+/// Every attempt made to send a notification with retries enabled, in order.
+/// There is always at least one.
+#[derive(Debug)]
+pub struct AttemptHistory {
+    pub attempts: Vec<AttemptRecord>,
+}
+
+impl AttemptHistory {
+    /// The error of the final attempt.
+    pub fn last_error(&self) -> &SendError {
+        &self.attempts.last().expect("empty attempt history").error
+    }
+}
+
+impl fmt::Display for AttemptHistory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (after {} attempts)", self.last_error(), self.attempts.len())
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+/// A cap on retries shared by every send of a client, so that retrying
+/// during an APNS incident can't multiply the load on it.
+///
+/// A retry is only made while the retries of the last `window` are fewer
+/// than `ratio` of the first attempts made in it, or than `min_retries`,
+/// which lets a quiet client still retry now and then. Sends that would go
+/// over the budget fail with the error of their last attempt.
+#[derive(Clone, Debug)]
+pub struct RetryBudget {
+    /// Retries allowed per first attempt, e.g. 0.2 for one in five.
+    pub ratio: f64,
+    /// How far back attempts are counted.
+    pub window: Duration,
+    /// Retries allowed in any window regardless of `ratio`.
+    pub min_retries: u32,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        RetryBudget {
+            ratio: 0.2,
+            window: Duration::from_secs(10),
+            min_retries: 10,
+        }
+    }
+}
+
+pub(crate) struct Budget {
+    pub budget: RetryBudget,
+    /// When each attempt in the window was made, and whether it was a retry.
+    attempts: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+impl Budget {
+    pub fn new(budget: RetryBudget) -> Self {
+        Budget {
+            budget,
+            attempts: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record the first attempt of a send.
+    pub fn record_attempt(&self) {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        self.expire(&mut attempts, now);
+        attempts.push_back((now, false));
+    }
+
+    /// Take a retry from the budget, returning whether there was one left.
+    pub fn try_retry(&self) -> bool {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+        self.expire(&mut attempts, now);
+        let retries = attempts.iter().filter(|(_, retry)| *retry).count();
+        let allowed = (self.budget.ratio * (attempts.len() - retries) as f64) as usize;
+        if retries >= allowed.max(self.budget.min_retries as usize) {
+            return false;
+        }
+        attempts.push_back((now, true));
+        true
+    }
+
+    fn expire(&self, attempts: &mut VecDeque<(Instant, bool)>, now: Instant) {
+        while attempts.front().is_some_and(|(at, _)| now.duration_since(*at) > self.budget.window) {
+            attempts.pop_front();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+
+/// The source of the random numbers behind retry jitter, provider token
+/// refresh jitter and the chaos policy. See `ApplePushClient::set_rng`.
+///
+/// apns-ids and signatures always use the system generator.
+pub trait Rng: Send + Sync {
+    /// A uniformly distributed number.
+    fn next_u64(&self) -> u64;
+}
+
+/// The system's secure random number generator, used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemRng;
+
+impl Rng for SystemRng {
+    fn next_u64(&self) -> u64 {
+        let mut bytes = [0; 8];
+        match SystemRandom::new().fill(&mut bytes) {
+            Ok(()) => u64::from_le_bytes(bytes),
+            Err(_) => 0,
+        }
+    }
+}
+
+/// A generator that produces the same numbers from the same seed on every
+/// run, for tests of code whose timing depends on jitter. Its numbers are
+/// predictable, so it has no place in production.
+///
+/// ```
+/// use apple_push::{DeterministicRng, Rng};
+///
+/// let (a, b) = (DeterministicRng::new(7), DeterministicRng::new(7));
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// assert_ne!(a.next_u64(), DeterministicRng::new(8).next_u64());
+/// ```
+#[derive(Debug)]
+pub struct DeterministicRng {
+    state: AtomicU64,
+}
+
+impl DeterministicRng {
+    pub fn new(seed: u64) -> Self {
+        DeterministicRng { state: AtomicU64::new(seed) }
+    }
+}
+
+impl Rng for DeterministicRng {
+    /// SplitMix64, which is fast and good enough for jitter.
+    fn next_u64(&self) -> u64 {
+        let mut z = self.state.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// A random number in `0..=bound`.
+#[cfg(feature = "client")]
+pub(crate) fn below(rng: &dyn Rng, bound: u64) -> u64 {
+    match bound.checked_add(1) {
+        Some(range) => rng.next_u64() % range,
+        None => rng.next_u64(),
+    }
+}
+
+/// A random fraction between 0 and 1.
+#[cfg(feature = "client")]
+pub(crate) fn fraction(rng: &dyn Rng) -> f64 {
+    below(rng, u32::MAX as u64) as f64 / u32::MAX as f64
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use crate::error::ValidationError;
+use crate::types::{Alert, Payload};
+
+
+/// What to do with control characters found in user-visible alert strings.
+///
+/// Rust strings are always valid UTF-8, so unpaired surrogates can't reach
+/// the payload; control characters (other than newlines and tabs) can, and
+/// render unpredictably on devices.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum SanitizePolicy {
+    /// Send alert strings unchanged.
+    #[default]
+    Allow,
+    /// Remove control characters from alert strings.
+    Strip,
+    /// Refuse to send notifications whose alert strings contain control
+    /// characters.
+    Reject,
+}
+
+impl SanitizePolicy {
+    /// Apply the policy to the user-visible strings of the alert. A shared
+    /// payload is only copied if something needs to be stripped.
+    pub(crate) fn apply(self, payload: &mut Arc<Payload>) -> Result<(), ValidationError> {
+        if self == SanitizePolicy::Allow {
+            return Ok(());
+        }
+        let mut found = None;
+        visit(payload, &mut |field, value| {
+            if found.is_none() {
+                found = value.chars().find(|&c| is_disallowed(c)).map(|character| (field, character));
+            }
+        });
+        match found {
+            None => Ok(()),
+            Some((field, character)) if self == SanitizePolicy::Reject => {
+                Err(ValidationError::ControlCharacter { field, character })
+            }
+            Some(_) => {
+                visit_mut(Arc::make_mut(payload), &mut |_, value| value.retain(|c| !is_disallowed(c)));
+                Ok(())
+            }
+        }
+    }
+}
+
+fn visit(payload: &Payload, f: &mut dyn FnMut(&'static str, &str)) {
+    match payload.alert {
+        Some(Alert::Simple(ref body)) => f("body", body),
+        Some(Alert::Payload(ref alert)) => {
+            if let Some(ref title) = alert.title {
+                f("title", title);
+            }
+            if let Some(ref subtitle) = alert.subtitle {
+                f("subtitle", subtitle);
+            }
+            if let Some(ref body) = alert.body {
+                f("body", body);
+            }
+            for arg in alert.title_loc_args.iter().flatten() {
+                f("title-loc-args", arg);
+            }
+            for arg in alert.subtitle_loc_args.iter().flatten() {
+                f("subtitle-loc-args", arg);
+            }
+            for arg in alert.loc_args.iter().flatten() {
+                f("loc-args", arg);
+            }
+        }
+        None => (),
+    }
+}
+
+fn visit_mut(payload: &mut Payload, f: &mut dyn FnMut(&'static str, &mut String)) {
+    match payload.alert {
+        Some(Alert::Simple(ref mut body)) => f("body", body),
+        Some(Alert::Payload(ref mut alert)) => {
+            if let Some(ref mut title) = alert.title {
+                f("title", title);
+            }
+            if let Some(ref mut subtitle) = alert.subtitle {
+                f("subtitle", subtitle);
+            }
+            if let Some(ref mut body) = alert.body {
+                f("body", body);
+            }
+            for arg in alert.title_loc_args.iter_mut().flatten() {
+                f("title-loc-args", arg);
+            }
+            for arg in alert.subtitle_loc_args.iter_mut().flatten() {
+                f("subtitle-loc-args", arg);
+            }
+            for arg in alert.loc_args.iter_mut().flatten() {
+                f("loc-args", arg);
+            }
+        }
+        None => (),
+    }
+}
+
+fn is_disallowed(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
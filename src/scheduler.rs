@@ -0,0 +1,209 @@
+//! Notifications sent at a later time.
+//!
+//! A `Scheduler` keeps the notifications scheduled with it in a
+//! `ScheduleStore` and sends them once they are due. A scheduled
+//! notification is only removed from the store after it was sent, so with a
+//! durable store nothing is lost if the process stops in between.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::Error;
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{ApnsId, ApplePushClient, Notification, QueueStats, SendError};
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteScheduleStore;
+
+
+/// The longest `Scheduler::run` waits between batches that failed.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// A notification waiting in a `ScheduleStore`.
+#[derive(Clone, Debug)]
+pub struct Scheduled {
+    /// The id the store gave the notification when it was scheduled.
+    pub id: u64,
+    /// When the notification is due.
+    pub at: SystemTime,
+    pub notification: Notification,
+}
+
+/// Storage for scheduled notifications.
+pub trait ScheduleStore: Send + Sync {
+    /// Store a notification due at `at`, returning its id.
+    fn insert(&self, at: SystemTime, notification: &Notification) -> Result<u64, Error>;
+
+    /// Remove a notification. Returns whether it was still stored.
+    fn remove(&self, id: u64) -> Result<bool, Error>;
+
+    /// Up to `limit` notifications due at `now`, the earliest first.
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, Error>;
+
+    /// The backlog at `now`: the number of notifications due but not yet
+    /// sent, and how long ago the earliest was due. By default reads every
+    /// due notification; stores that can count them should.
+    fn backlog(&self, now: SystemTime) -> Result<QueueStats, Error> {
+        let due = self.due(now, usize::MAX)?;
+        Ok(backlog(now, due.len(), due.first().map(|scheduled| scheduled.at)))
+    }
+}
+
+fn backlog(now: SystemTime, depth: usize, earliest: Option<SystemTime>) -> QueueStats {
+    QueueStats {
+        depth,
+        oldest_age: earliest.map(|at| now.duration_since(at).unwrap_or_default()),
+    }
+}
+
+/// An in-memory `ScheduleStore`, for tests and deployments that can afford
+/// to lose scheduled notifications on restart.
+#[derive(Default)]
+pub struct MemoryScheduleStore {
+    next_id: AtomicU64,
+    scheduled: Mutex<BTreeMap<(SystemTime, u64), Notification>>,
+}
+
+impl MemoryScheduleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ScheduleStore for MemoryScheduleStore {
+    fn insert(&self, at: SystemTime, notification: &Notification) -> Result<u64, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.scheduled.lock().unwrap().insert((at, id), notification.clone());
+        Ok(id)
+    }
+
+    fn remove(&self, id: u64) -> Result<bool, Error> {
+        let mut scheduled = self.scheduled.lock().unwrap();
+        let key = scheduled.keys().find(|(_, i)| *i == id).cloned();
+        Ok(key.and_then(|key| scheduled.remove(&key)).is_some())
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, Error> {
+        let scheduled = self.scheduled.lock().unwrap();
+        Ok(scheduled.iter()
+            .take_while(|((at, _), _)| *at <= now)
+            .take(limit)
+            .map(|(&(at, id), n)| Scheduled { id, at, notification: n.clone() })
+            .collect())
+    }
+
+    fn backlog(&self, now: SystemTime) -> Result<QueueStats, Error> {
+        let scheduled = self.scheduled.lock().unwrap();
+        let mut due = scheduled.keys().take_while(|(at, _)| *at <= now);
+        let earliest = due.next().map(|&(at, _)| at);
+        Ok(backlog(now, earliest.map_or(0, |_| 1 + due.count()), earliest))
+    }
+}
+
+/// Sends the notifications in a `ScheduleStore` once they are due.
+pub struct Scheduler<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    client: Arc<ApplePushClient<S>>,
+    store: Arc<dyn ScheduleStore>,
+    /// How many due notifications are sent per call to `send_due`.
+    pub batch_size: usize,
+    /// How many times a notification that keeps failing in a way that might
+    /// not happen again is sent before it is removed anyway, so that it
+    /// doesn't hold up the ones due after it for good.
+    pub max_attempts: u32,
+    /// The sends of each notification that failed so far, by id.
+    attempts: Mutex<HashMap<u64, u32>>,
+}
+
+impl<S> Scheduler<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    pub fn new(client: Arc<ApplePushClient<S>>, store: Arc<dyn ScheduleStore>) -> Self {
+        Scheduler { client, store, batch_size: 100, max_attempts: 5, attempts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Schedule `n` to be sent at `at`, returning the id to cancel it with.
+    pub fn schedule(&self, n: &Notification, at: SystemTime) -> Result<u64, Error> {
+        self.store.insert(at, n)
+    }
+
+    /// Cancel a scheduled notification. Returns whether it hadn't been sent
+    /// yet.
+    pub fn cancel(&self, id: u64) -> Result<bool, Error> {
+        self.store.remove(id)
+    }
+
+    /// Send the notifications that are due, one batch of them, and return
+    /// the result for each. Notifications that failed in a way that might
+    /// not happen again, like a connection failure, are kept to be tried
+    /// again by the next call, up to `max_attempts` sends; all others are
+    /// removed.
+    pub async fn send_due(&self) -> Result<Vec<(u64, Result<ApnsId, SendError>)>, Error> {
+        Ok(self.send_batch().await?.0)
+    }
+
+    /// `send_due`, also returning how many notifications were removed.
+    async fn send_batch(&self) -> Result<(Vec<(u64, Result<ApnsId, SendError>)>, usize), Error> {
+        let due = self.store.due(SystemTime::now(), self.batch_size)?;
+        let mut results = Vec::with_capacity(due.len());
+        let mut removed = 0;
+        for scheduled in due {
+            let result = self.client.send(scheduled.notification).await;
+            let keep = result.as_ref().err().is_some_and(SendError::is_retriable) && {
+                let mut attempts = self.attempts.lock().unwrap();
+                let failed = attempts.entry(scheduled.id).or_insert(0);
+                *failed += 1;
+                *failed < self.max_attempts
+            };
+            if !keep {
+                self.attempts.lock().unwrap().remove(&scheduled.id);
+                self.store.remove(scheduled.id)?;
+                removed += 1;
+            }
+            results.push((scheduled.id, result));
+        }
+        Ok((results, removed))
+    }
+
+    /// The notifications that are due but not yet sent. A growing backlog
+    /// means that `send_due` isn't keeping up.
+    pub fn backlog(&self) -> Result<QueueStats, Error> {
+        self.store.backlog(SystemTime::now())
+    }
+
+    /// Call `send_due` every `interval`, until the store fails. Batches
+    /// that leave a full batch due are followed at once by the next, and
+    /// batches with notifications kept to be tried again by a wait that
+    /// doubles each time, up to five minutes, so that an outage isn't met
+    /// with the same sends over and over.
+    pub async fn run(&self, interval: Duration) -> Result<(), Error> {
+        let mut retry_delay = interval;
+        loop {
+            let (sent, removed) = self.send_batch().await?;
+            if removed < sent.len() {
+                tokio::time::delay_for(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY.max(interval));
+            } else {
+                retry_delay = interval;
+                if removed < self.batch_size {
+                    tokio::time::delay_for(interval).await;
+                }
+            }
+        }
+    }
+}
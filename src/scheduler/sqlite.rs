@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Error;
+use rusqlite::{params, Connection};
+
+use super::{backlog, Scheduled, ScheduleStore};
+use crate::{Notification, QueueStats};
+
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS scheduled_notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    due_at INTEGER NOT NULL,
+    notification TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS scheduled_notifications_due_at ON scheduled_notifications (due_at);";
+
+/// A `ScheduleStore` in an SQLite database, so that scheduled notifications
+/// survive restarts without any other infrastructure.
+///
+/// Only available with the `sqlite` feature.
+pub struct SqliteScheduleStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteScheduleStore {
+    /// Open or create the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Use an open connection, creating the table if it doesn't exist.
+    pub fn new(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteScheduleStore { connection: Mutex::new(connection) })
+    }
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+impl ScheduleStore for SqliteScheduleStore {
+    fn insert(&self, at: SystemTime, notification: &Notification) -> Result<u64, Error> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT INTO scheduled_notifications (due_at, notification) VALUES (?1, ?2)",
+            params![to_millis(at), serde_json::to_string(notification)?],
+        )?;
+        Ok(connection.last_insert_rowid() as u64)
+    }
+
+    fn remove(&self, id: u64) -> Result<bool, Error> {
+        let connection = self.connection.lock().unwrap();
+        let removed = connection.execute("DELETE FROM scheduled_notifications WHERE id = ?1", params![id as i64])?;
+        Ok(removed > 0)
+    }
+
+    fn due(&self, now: SystemTime, limit: usize) -> Result<Vec<Scheduled>, Error> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT id, due_at, notification FROM scheduled_notifications
+             WHERE due_at <= ?1 ORDER BY due_at, id LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![to_millis(now), limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+        })?;
+        let mut due = Vec::new();
+        for row in rows {
+            let (id, at, notification) = row?;
+            due.push(Scheduled {
+                id: id as u64,
+                at: UNIX_EPOCH + Duration::from_millis(at as u64),
+                notification: serde_json::from_str(&notification)?,
+            });
+        }
+        Ok(due)
+    }
+
+    fn backlog(&self, now: SystemTime) -> Result<QueueStats, Error> {
+        let connection = self.connection.lock().unwrap();
+        let (depth, earliest) = connection.query_row(
+            "SELECT COUNT(*), MIN(due_at) FROM scheduled_notifications WHERE due_at <= ?1",
+            params![to_millis(now)],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?)),
+        )?;
+        let earliest = earliest.map(|at| UNIX_EPOCH + Duration::from_millis(at as u64));
+        Ok(backlog(now, depth as usize, earliest))
+    }
+}
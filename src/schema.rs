@@ -0,0 +1,178 @@
+//! JSON Schemas (draft 7) of the JSON serialization of `Notification` and
+//! `Payload`, for services in other languages that produce notifications
+//! into queues read by this crate, such as the `gateway` and `apnsd`.
+//!
+//! The schemas describe what deserializes, not everything APNS accepts:
+//! `aps` keys the crate doesn't model are allowed, and end up in
+//! `Payload::raw`. The schemas are written by hand; the tests check them
+//! against what the serde implementations read and write, so change them
+//! together.
+//!
+//! ```
+//! let schema = apple_push::schema::notification();
+//! assert_eq!(schema["required"], serde_json::json!(["topic", "device_token", "payload"]));
+//! ```
+
+use serde_json::{json, Value};
+
+use crate::consts::MAX_COLLAPSE_ID_SIZE;
+
+
+const DRAFT_7: &str = "http://json-schema.org/draft-07/schema#";
+
+/// The schema of a `Notification`.
+///
+/// ```
+/// use apple_push::{CollapseId, InterruptionLevel, NotificationBuilder};
+///
+/// // Every key a notification serializes to is described.
+/// let mut n = NotificationBuilder::new("com.example.app", "device-token")
+///     .title("Hello")
+///     .badge(1)
+///     .interruption_level(InterruptionLevel::Active)
+///     .collapse_id(CollapseId::new("greeting").unwrap())
+///     .build();
+/// n.ttl = Some(std::time::Duration::from_secs(60));
+/// let schema = apple_push::schema::notification();
+/// for key in serde_json::to_value(&n).unwrap().as_object().unwrap().keys() {
+///     assert!(schema["properties"].get(key).is_some(), "{} isn't in the schema", key);
+/// }
+/// ```
+pub fn notification() -> Value {
+    json!({
+        "$schema": DRAFT_7,
+        "title": "Notification",
+        "type": "object",
+        "required": ["topic", "device_token", "payload"],
+        "properties": {
+            "topic": { "type": "string", "description": "The topic to use. Usually the app bundle id." },
+            "device_token": { "type": "string" },
+            "payload": { "$ref": "#/definitions/Payload" },
+            "id": nullable(json!({
+                "type": "string",
+                "pattern": "^[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}$",
+            })),
+            "expiration": nullable(json!({ "type": "integer", "minimum": 0, "description": "UNIX timestamp." })),
+            "ttl": nullable(json!({
+                "type": "object",
+                "required": ["secs", "nanos"],
+                "properties": {
+                    "secs": { "type": "integer", "minimum": 0 },
+                    "nanos": { "type": "integer", "minimum": 0, "maximum": 999_999_999 },
+                },
+            })),
+            "priority": nullable(json!({ "enum": ["5", "10"] })),
+            "push_type": nullable(json!({
+                "enum": [
+                    "alert", "background", "location", "voip", "complication", "fileprovider", "mdm",
+                    "liveactivity", "pushtotalk",
+                ],
+            })),
+            "collapse_id": nullable(json!({ "type": "string", "maxLength": MAX_COLLAPSE_ID_SIZE })),
+            "media_url": nullable(json!({ "type": "string" })),
+            "custom": nullable(json!({ "type": "object", "not": { "required": ["aps"] } })),
+            "environment": nullable(json!({ "enum": ["Production", "Sandbox"] })),
+            "extra_apns_headers": nullable(json!({
+                "type": "object",
+                "propertyNames": { "pattern": "^apns-[0-9a-z-]+$" },
+                "additionalProperties": { "type": "string" },
+            })),
+        },
+        "definitions": definitions(),
+    })
+}
+
+/// The schema of a `Payload`, the `aps` dictionary.
+///
+/// ```
+/// use apple_push::NotificationBuilder;
+///
+/// let n = NotificationBuilder::critical_alert("com.example.app", "device-token", "Smoke detected", "Kitchen")
+///     .badge(1)
+///     .category("ALARM".to_owned())
+///     .relevance_score(1.0)
+///     .build();
+/// let schema = apple_push::schema::payload();
+/// let properties = &schema["definitions"]["Payload"]["properties"];
+/// for key in serde_json::to_value(&n.payload).unwrap().as_object().unwrap().keys() {
+///     assert!(properties.get(key).is_some(), "{} isn't in the schema", key);
+/// }
+/// ```
+pub fn payload() -> Value {
+    json!({
+        "$schema": DRAFT_7,
+        "$ref": "#/definitions/Payload",
+        "definitions": definitions(),
+    })
+}
+
+fn definitions() -> Value {
+    json!({
+        "Payload": {
+            "type": "object",
+            "properties": {
+                "alert": {
+                    "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/AlertPayload" }],
+                },
+                "badge": { "type": "integer", "minimum": 0 },
+                "sound": {
+                    "oneOf": [{ "type": "string" }, { "$ref": "#/definitions/CriticalSound" }],
+                },
+                "content-available": flag(),
+                "category": { "type": "string" },
+                "thread-id": { "type": "string" },
+                "mutable-content": flag(),
+                "interruption-level": { "enum": ["passive", "active", "time-sensitive", "critical"] },
+                "target-content-id": { "type": "string" },
+                "relevance-score": { "type": "number", "minimum": 0, "maximum": 1 },
+                "filter-criteria": { "type": "string" },
+                "timestamp": { "type": "integer", "minimum": 0 },
+                "event": { "enum": ["start", "update", "end"] },
+                "content-state": {},
+                "dismissal-date": { "type": "integer", "minimum": 0 },
+                "stale-date": { "type": "integer", "minimum": 0 },
+                "attributes-type": { "type": "string" },
+                "attributes": {},
+            },
+        },
+        "AlertPayload": {
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "body": { "type": "string" },
+                "title-loc-key": { "type": "string" },
+                "title-loc-args": strings(),
+                "subtitle": { "type": "string" },
+                "subtitle-loc-key": { "type": "string" },
+                "subtitle-loc-args": strings(),
+                "action-loc-key": { "type": "string" },
+                "loc-key": { "type": "string" },
+                "loc-args": strings(),
+                "launch-image": { "type": "string" },
+                "summary-arg": { "type": "string" },
+                "summary-arg-count": { "type": "integer", "minimum": 0 },
+            },
+        },
+        "CriticalSound": {
+            "type": "object",
+            "required": ["critical", "name", "volume"],
+            "properties": {
+                "critical": { "type": "integer", "minimum": 0, "maximum": 255 },
+                "name": { "type": "string" },
+                "volume": { "type": "number" },
+            },
+        },
+    })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "oneOf": [{ "type": "null" }, schema] })
+}
+
+fn flag() -> Value {
+    json!({ "oneOf": [{ "type": "integer", "minimum": 0, "maximum": 255 }, { "type": "boolean" }] })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
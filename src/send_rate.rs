@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+/// The longest a send waits for the rate, however low it is.
+const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A limit on the rate at which a client sends requests to APNS, as a token
+/// bucket, and how it backs off from 429 responses. See
+/// `ApplePushClient::set_send_rate`.
+///
+/// Sends over the rate wait for their turn rather than failing.
+///
+/// This is one of three limits a send passes through, in this order:
+///
+/// - a `ThrottlePolicy` spaces out and lowers the priority of sends to a
+///   topic APNS has throttled;
+/// - the device tokens APNS answered `TooManyRequests` for, as recorded
+///   here, and then a `RateLimiter`, refuse sends to a device token with
+///   `SendError::RateLimited` without sending them;
+/// - the token bucket here makes whatever is left wait for its turn.
+///
+/// A send refused by one limit doesn't count against the ones after it,
+/// so a `RateLimiter` only counts sends that weren't backed off, and the
+/// bucket only those actually sent.
+#[derive(Clone, Debug)]
+pub struct SendRate {
+    per_second: f64,
+    burst: u32,
+    /// How long every send pauses after a 429 response, unless the
+    /// response says how long with `retry-after`.
+    pub backoff: Duration,
+    /// How long sends to a device token fail with
+    /// `SendError::RateLimited` after APNS answered `TooManyRequests` for
+    /// it, unless the response says how long with `retry-after`.
+    pub device_backoff: Duration,
+}
+
+impl SendRate {
+    /// Send `per_second` requests a second in the long run, and up to
+    /// `burst` at once after a quiet spell.
+    ///
+    /// # Panics
+    ///
+    /// If `per_second` isn't a positive number.
+    ///
+    /// ```should_panic
+    /// apple_push::SendRate::new(0.0, 1);
+    /// ```
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        assert!(per_second.is_finite() && per_second > 0.0, "send rate must be a positive number of requests per second, not {}", per_second);
+        SendRate {
+            per_second,
+            burst,
+            backoff: Duration::from_secs(1),
+            device_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// Requests per second sent in the long run.
+    pub fn per_second(&self) -> f64 {
+        self.per_second
+    }
+
+    /// Requests that can be sent at once after a quiet spell.
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Nothing is sent before this, after a 429.
+    paused_until: Instant,
+}
+
+pub(crate) struct SendRateLimiter {
+    pub rate: SendRate,
+    bucket: Mutex<Bucket>,
+    devices: Mutex<HashMap<String, Instant>>,
+}
+
+impl SendRateLimiter {
+    pub fn new(rate: SendRate) -> Self {
+        let now = Instant::now();
+        SendRateLimiter {
+            bucket: Mutex::new(Bucket { tokens: rate.burst.max(1) as f64, updated: now, paused_until: now }),
+            devices: Mutex::new(HashMap::new()),
+            rate,
+        }
+    }
+
+    /// Take a token for a send, and return how long to wait before sending.
+    /// Tokens are taken ahead of time, so concurrent sends queue up in turn.
+    ///
+    /// A rate so low that the wait would be longer than `MAX_WAIT` waits
+    /// that long.
+    pub fn take(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut bucket = self.bucket.lock().unwrap();
+        let per_second = self.rate.per_second;
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(self.rate.burst.max(1) as f64);
+        bucket.updated = now;
+        bucket.tokens -= 1.0;
+        let refill = if bucket.tokens < 0.0 {
+            Duration::try_from_secs_f64(-bucket.tokens / per_second).unwrap_or(MAX_WAIT).min(MAX_WAIT)
+        } else {
+            Duration::from_secs(0)
+        };
+        let wait = refill.max(bucket.paused_until.saturating_duration_since(now));
+        if wait > Duration::from_secs(0) { Some(wait) } else { None }
+    }
+
+    /// How long sends to `device_token` are still refused for, if they are.
+    /// An expired backoff is dropped when it is looked up.
+    pub fn device_backoff(&self, device_token: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let mut devices = self.devices.lock().unwrap();
+        let until = *devices.get(device_token)?;
+        if until > now {
+            Some(until - now)
+        } else {
+            devices.remove(device_token);
+            None
+        }
+    }
+
+    /// Back off after a 429 response to a send to `device_token`.
+    /// `too_many_requests` is whether APNS blamed the device token.
+    pub fn record_429(&self, device_token: &str, too_many_requests: bool, retry_after: Option<Duration>) {
+        let now = Instant::now();
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.paused_until = bucket.paused_until.max(now + retry_after.unwrap_or(self.rate.backoff));
+        drop(bucket);
+        if too_many_requests {
+            let until = now + retry_after.unwrap_or(self.rate.device_backoff);
+            let mut devices = self.devices.lock().unwrap();
+            // Device tokens that are never sent to again aren't looked up,
+            // so their backoffs are swept here instead, off the path of
+            // sends that weren't throttled.
+            devices.retain(|_, until| *until > now);
+            devices.insert(device_token.to_owned(), until);
+        }
+    }
+}
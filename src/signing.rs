@@ -0,0 +1,155 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use base64::URL_SAFE_NO_PAD;
+use crate::Error;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Serialize;
+
+use crate::pem::{pkcs8_from_pem, read_key_file};
+
+
+/// How long APNS accepts a provider token after it was issued.
+#[cfg(feature = "client")]
+pub(crate) const TOKEN_LIFETIME: i64 = 3600;
+
+/// Length of the base64url encoding of an ES256 signature.
+const SIGNATURE_LEN: usize = 86;
+
+/// The provider token currently cached by a client. See
+/// `ApplePushClient::token_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenInfo {
+    /// When the token was issued.
+    pub issued_at: SystemTime,
+    /// How long until APNS stops accepting the token. Zero if it already
+    /// has; the client refreshes tokens before that.
+    pub expires_in: Duration,
+    /// The id of the key the token was signed with.
+    pub kid: String,
+}
+
+/// A provider token signing key and its key id.
+pub(crate) struct SigningKey {
+    pub kid: String,
+    keypair: EcdsaKeyPair,
+    /// The encoded JOSE header, which is the same for every token.
+    header: String,
+}
+
+#[derive(Serialize)]
+struct Header<'a> {
+    alg: &'static str,
+    kid: &'a str,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+impl SigningKey {
+    /// Parse a PKCS#8 DER encoded P-256 key.
+    pub fn from_pkcs8(kid: &str, key: &[u8]) -> Result<Self, Error> {
+        let keypair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, key).map_err(|e| format!("bad key: {:?}", e))?;
+        let header = serde_json::to_vec(&Header { alg: "ES256", kid })?;
+        Ok(SigningKey {
+            kid: kid.to_owned(),
+            keypair,
+            header: base64::encode_config(&header, URL_SAFE_NO_PAD),
+        })
+    }
+
+    /// Sign a provider token for `team_id` issued at `issued_at`.
+    ///
+    /// The token is built in a single buffer: the signing input is written
+    /// into it, signed in place and followed by the signature.
+    pub fn sign(&self, team_id: &str, issued_at: i64) -> Result<String, Error> {
+        let claims = serde_json::to_vec(&Claims { iss: team_id, iat: issued_at })?;
+        let mut token = String::with_capacity(self.header.len() + 1 + (claims.len() * 4).div_ceil(3) + 1 + SIGNATURE_LEN);
+        token.push_str(&self.header);
+        token.push('.');
+        base64::encode_config_buf(&claims, URL_SAFE_NO_PAD, &mut token);
+        let signature = self.keypair.sign(&SystemRandom::new(), token.as_bytes()).map_err(|_| "signing failed")?;
+        token.push('.');
+        base64::encode_config_buf(signature.as_ref(), URL_SAFE_NO_PAD, &mut token);
+        Ok(token)
+    }
+}
+
+/// Signs provider tokens for a team, for applications that send
+/// notifications through their own HTTP/2 stack and only need the
+/// authentication part of this crate.
+pub struct TokenSigner {
+    team_id: String,
+    key: SigningKey,
+}
+
+impl TokenSigner {
+    /// Create a signer from the team id, the key id and a PKCS#8 DER
+    /// encoded P-256 key, as downloaded from the developer account.
+    pub fn new(team_id: &str, key_id: &str, key: &[u8]) -> Result<Self, Error> {
+        Ok(TokenSigner {
+            team_id: team_id.to_owned(),
+            key: SigningKey::from_pkcs8(key_id, key)?,
+        })
+    }
+
+    /// Like `new`, with the key in the PEM format of the `.p8` file.
+    pub fn from_pem(team_id: &str, key_id: &str, pem: &[u8]) -> Result<Self, Error> {
+        Self::new(team_id, key_id, &pkcs8_from_pem(pem)?)
+    }
+
+    /// Like `new`, reading the key from the `.p8` file at `path`.
+    pub fn from_key_file<P: AsRef<Path>>(team_id: &str, key_id: &str, path: P) -> Result<Self, Error> {
+        Self::new(team_id, key_id, &read_key_file(path)?)
+    }
+
+    /// The id of the key tokens are signed with.
+    pub fn key_id(&self) -> &str {
+        &self.key.kid
+    }
+
+    /// Sign a provider token issued now, to be sent as `bearer <token>` in
+    /// the authorization header.
+    pub fn sign(&self) -> Result<String, Error> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64;
+        self.key.sign(&self.team_id, now)
+    }
+}
+
+/// The key tokens are signed with, and the one to switch to if APNS
+/// refuses it.
+#[cfg(feature = "client")]
+pub(crate) struct SigningKeys {
+    pub active: SigningKey,
+    pub secondary: Option<SigningKey>,
+}
+
+#[cfg(feature = "client")]
+impl SigningKeys {
+    /// The id of the active key, if there is a secondary key to fail over
+    /// to.
+    pub fn failover_kid(&self) -> Option<String> {
+        self.secondary.as_ref().map(|_| self.active.kid.clone())
+    }
+
+    /// Switch to the secondary key if tokens signed with `kid` were refused
+    /// and it is still the active key. Returns whether the active key is now
+    /// a different one than `kid`, and the ids of the keys switched between
+    /// if this call switched.
+    pub fn fail_over(&mut self, kid: &str) -> (bool, Option<(String, String)>) {
+        if self.active.kid != kid {
+            return (true, None);
+        }
+        match self.secondary.take() {
+            Some(secondary) => {
+                let from = std::mem::replace(&mut self.active, secondary);
+                (true, Some((from.kid, self.active.kid.clone())))
+            }
+            None => (false, None),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc;
+use futures::sink::Sink;
+use futures::stream::{FuturesUnordered, Stream};
+use hyper::{client::connect::Connection, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{ApnsId, ApplePushClient, Notification, SendError};
+
+
+/// The result of a notification sent through a `SendSink`, with its device
+/// token.
+pub type SinkResult = (String, Result<ApnsId, SendError>);
+
+type InFlight<'a> = Pin<Box<dyn Future<Output = SinkResult> + Send + 'a>>;
+
+/// A `Sink` that sends the notifications written to it, with up to a fixed
+/// number in flight at once. See `ApplePushClient::sink`.
+///
+/// The sink itself never fails: the result of each notification goes to the
+/// receiver returned by `ApplePushClient::sink_with_results`, if any.
+/// Flushing or closing the sink waits for every notification in flight.
+pub struct SendSink<'a> {
+    send: Box<dyn Fn(Notification) -> InFlight<'a> + Send + Sync + 'a>,
+    in_flight: FuturesUnordered<InFlight<'a>>,
+    max_in_flight: usize,
+    results: Option<mpsc::UnboundedSender<SinkResult>>,
+}
+
+impl<'a> SendSink<'a> {
+    /// Collect finished sends, returning whether none are left in flight.
+    fn poll_in_flight(&mut self, cx: &mut Context) -> bool {
+        while let Poll::Ready(Some(result)) = Pin::new(&mut self.in_flight).poll_next(cx) {
+            if let Some(ref results) = self.results {
+                // Results nobody receives are dropped.
+                let _ = results.unbounded_send(result);
+            }
+        }
+        self.in_flight.is_empty()
+    }
+}
+
+impl<'a> Sink<Notification> for SendSink<'a> {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        let this = self.get_mut();
+        this.poll_in_flight(cx);
+        if this.in_flight.len() < this.max_in_flight {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, n: Notification) -> Result<(), Infallible> {
+        let this = self.get_mut();
+        this.in_flight.push((this.send)(n));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        if self.get_mut().poll_in_flight(cx) {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<S> ApplePushClient<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// A sink sending the notifications written to it, up to
+    /// `max_in_flight` at once, for feeding the client from a stream:
+    ///
+    /// ```no_run
+    /// # async fn run(client: apple_push::ApplePushClient<hyper::client::HttpConnector>, notifications: Vec<apple_push::Notification>) {
+    /// use futures::stream::{self, StreamExt};
+    ///
+    /// stream::iter(notifications).map(Ok).forward(client.sink(16)).await.unwrap();
+    /// # }
+    /// ```
+    ///
+    /// The results of the sends are discarded; use `sink_with_results` to
+    /// receive them.
+    pub fn sink(&self, max_in_flight: usize) -> SendSink<'_> {
+        SendSink {
+            send: Box::new(move |n| {
+                Box::pin(async move {
+                    let token = n.device_token.clone();
+                    (token, self.send(n).await)
+                })
+            }),
+            in_flight: FuturesUnordered::new(),
+            max_in_flight: max_in_flight.max(1),
+            results: None,
+        }
+    }
+
+    /// Like `sink`, also returning a stream of the result of each
+    /// notification, in the order the sends finish.
+    pub fn sink_with_results(&self, max_in_flight: usize) -> (SendSink<'_>, mpsc::UnboundedReceiver<SinkResult>) {
+        let (sender, receiver) = mpsc::unbounded();
+        let mut sink = self.sink(max_in_flight);
+        sink.results = Some(sender);
+        (sink, receiver)
+    }
+}
@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+
+
+/// The tasks a client runs in the background, so that they can be counted
+/// and waited for on shutdown.
+#[derive(Default)]
+pub(crate) struct TaskSet {
+    running: Arc<AtomicUsize>,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+}
+
+/// Counts a task as running until dropped, even if the task panics.
+struct Running(Arc<AtomicUsize>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl TaskSet {
+    /// Spawn `task` on the current tokio runtime.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut handles = self.handles.lock().unwrap();
+        // Forget the handles of finished tasks, so they don't pile up over
+        // reconnections.
+        if self.running.load(Ordering::SeqCst) == 0 {
+            handles.clear();
+        }
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let running = Running(self.running.clone());
+        handles.push(tokio::spawn(async move {
+            let _running = running;
+            task.await
+        }));
+    }
+
+    /// The number of tasks still running.
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Wait for every task spawned so far to finish.
+    pub async fn join(&self) {
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        for handle in handles {
+            let _ = handle.await;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::types::{Notification, Priority};
+
+
+/// Adaptive response to APNS throttling a topic (429 responses).
+///
+/// While a topic is throttled, its notifications are sent with priority 5 and
+/// spaced out. Notifications with an explicit priority of `Priority::High` are
+/// treated as critical and sent unchanged. A topic recovers once `cooldown`
+/// has passed without another 429.
+#[derive(Clone, Debug)]
+pub struct ThrottlePolicy {
+    /// How long a topic stays throttled after its last 429.
+    pub cooldown: Duration,
+    /// Minimum time between consecutive throttled sends for a topic.
+    pub spacing: Duration,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        ThrottlePolicy {
+            cooldown: Duration::from_secs(60),
+            spacing: Duration::from_millis(100),
+        }
+    }
+}
+
+struct TopicState {
+    last_throttled: Instant,
+    next_slot: Instant,
+}
+
+pub(crate) struct Throttle {
+    pub policy: ThrottlePolicy,
+    topics: Mutex<HashMap<String, TopicState>>,
+}
+
+impl Throttle {
+    pub fn new(policy: ThrottlePolicy) -> Self {
+        Throttle {
+            policy,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record that APNS throttled a send to `topic`.
+    pub fn record(&self, topic: &str) {
+        let now = Instant::now();
+        let mut topics = self.topics.lock().unwrap();
+        let state = topics.entry(topic.to_owned()).or_insert(TopicState {
+            last_throttled: now,
+            next_slot: now,
+        });
+        state.last_throttled = now;
+    }
+
+    /// Downgrade the notification if its topic is throttled, and return how
+    /// long to wait before sending it.
+    pub fn apply(&self, n: &mut Notification) -> Option<Duration> {
+        if n.priority == Some(Priority::High) {
+            return None;
+        }
+        let now = Instant::now();
+        let mut topics = self.topics.lock().unwrap();
+        let state = topics.get_mut(&n.topic)?;
+        if now.duration_since(state.last_throttled) >= self.policy.cooldown {
+            topics.remove(&n.topic);
+            return None;
+        }
+        n.priority = Some(Priority::Low);
+        let slot = state.next_slot.max(now);
+        state.next_slot = slot + self.policy.spacing;
+        Some(slot - now)
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use crate::id::ApnsId;
+use crate::types::Environment;
+
+
+/// A notification accepted by APNS.
+#[derive(Clone, Debug)]
+pub struct SendResponse {
+    /// The apns-id of the notification.
+    pub id: ApnsId,
+    /// The environment the notification was sent to.
+    pub environment: Environment,
+    /// The id under which the notification appears in the delivery log of
+    /// the Push Notifications Console. APNS only sends one in the sandbox.
+    pub unique_id: Option<String>,
+    /// Where the time of the final attempt went, if the client records
+    /// timings. See `ApplePushClient::set_record_timings`.
+    pub timings: Option<Timings>,
+}
+
+/// Where the time of sending a notification went.
+///
+/// The hyper transport can't tell connecting and writing the request apart
+/// from waiting for the response, so it reports all three as `ttfb` and
+/// leaves `connect`, `reused_connection` and `request_write` empty. The
+/// direct h2 transport reports every phase.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Waiting before the request could start: for a concurrency permit,
+    /// throttle spacing, and with the h2 transport, for a free stream.
+    pub queue_wait: Duration,
+    /// Getting a signing token, including signing a new one when due.
+    pub jwt: Duration,
+    /// Getting a connection, whether new or reused.
+    pub connect: Option<Duration>,
+    /// Whether an already open connection was used.
+    pub reused_connection: Option<bool>,
+    /// Writing the request headers and body.
+    pub request_write: Option<Duration>,
+    /// Waiting for the response headers once the request was written.
+    pub ttfb: Duration,
+    /// Reading the response body.
+    pub body_read: Duration,
+}
+
+/// Measures consecutive phases.
+pub(crate) struct Stopwatch(Instant);
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch(Instant::now())
+    }
+
+    /// The time since the start or the previous lap.
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.0;
+        self.0 = now;
+        elapsed
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use crate::Error;
+use hyper::client::HttpConnector;
+use hyper::Client;
+use hyper_tls::HttpsConnector;
+#[cfg(feature = "certificate-auth")]
+use native_tls::{Identity, TlsConnector};
+
+use crate::ApplePushClient;
+
+
+/// How often the clients built here PING their connection, so that a
+/// connection APNS dropped while idle is noticed before the next send.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a PING may go unanswered before the connection is closed.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long an unused connection is kept open.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A client for `connector` that keeps its HTTP/2 connections alive with
+/// PINGs, and closes those unused for long.
+fn http2_client(connector: HttpsConnector<HttpConnector>) -> Client<HttpsConnector<HttpConnector>> {
+    Client::builder()
+        .http2_only(true)
+        .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+        .http2_keep_alive_timeout(KEEP_ALIVE_TIMEOUT)
+        .http2_keep_alive_while_idle(true)
+        .pool_idle_timeout(IDLE_TIMEOUT)
+        .build(connector)
+}
+
+impl ApplePushClient<HttpsConnector<HttpConnector>> {
+    /// Create a client that connects through hyper-tls with the system's
+    /// trust roots, so that no `Client` has to be built by hand. `jwt_key`
+    /// is the DER contents of the `.p8` file, as for `new`. The connection
+    /// is PINGed every minute, and closed after 30 minutes unused.
+    pub fn new_default(team_id: &str, jwt_kid: &str, jwt_key: &[u8]) -> Result<Self, Error> {
+        Self::new(http2_client(HttpsConnector::new()), team_id, jwt_kid, jwt_key)
+    }
+
+    /// Create a client that authenticates with an APNS certificate instead
+    /// of provider tokens. `p12` is the certificate and its private key as
+    /// exported from Keychain Access, protected by `password`.
+    ///
+    /// Certificates are issued for one app and one environment; call
+    /// `set_production(false)` for a development certificate.
+    #[cfg(feature = "certificate-auth")]
+    pub fn with_certificate(p12: &[u8], password: &str) -> Result<Self, Error> {
+        let identity = Identity::from_pkcs12(p12, password)?;
+        let tls = TlsConnector::builder().identity(identity).build()?;
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let https = HttpsConnector::from((http, tls.into()));
+        Ok(Self::new_certificate_auth(http2_client(https)))
+    }
+}
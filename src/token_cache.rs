@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::Error;
+
+use crate::rng::{self, Rng};
+use crate::signing::{SigningKey, TokenInfo, TOKEN_LIFETIME};
+
+
+/// Default time after which a token is replaced. Well inside the hour APNS
+/// accepts it for, and over the 20 minutes APNS wants between refreshes.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(50 * 60);
+
+/// APNS refuses tokens refreshed more often than this.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(20 * 60);
+
+/// Tokens are never used in the last minute APNS would accept them.
+const MAX_REFRESH_INTERVAL: Duration = Duration::from_secs(TOKEN_LIFETIME as u64 - 60);
+
+/// Default upper bound of the random amount by which each token is refreshed
+/// early, so that a fleet of clients started together doesn't re-sign in
+/// lockstep.
+const DEFAULT_REFRESH_JITTER: Duration = Duration::from_secs(300);
+
+/// Default amount by which tokens are backdated, so that APNS doesn't refuse
+/// them as issued in the future when the local clock is a little ahead.
+const DEFAULT_BACKDATE: Duration = Duration::from_secs(10);
+
+/// The most tokens APNS wants signed an hour, one every
+/// `MIN_REFRESH_INTERVAL`. More may be refused with
+/// `TooManyProviderTokenUpdates`.
+pub const GUIDED_SIGNINGS_PER_HOUR: usize = 3;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// How long no token is signed after APNS answers
+/// `TooManyProviderTokenUpdates`, so that the next one is at least the
+/// interval APNS wants after the last.
+pub const COOL_DOWN: Duration = MIN_REFRESH_INTERVAL;
+
+struct CachedToken {
+    kid: String,
+    issued_at: i64,
+    token: String,
+    refresh_at: i64,
+}
+
+impl CachedToken {
+    fn is_usable(&self, kid: &str, now: i64) -> bool {
+        self.kid == kid && now < self.issued_at + MAX_REFRESH_INTERVAL.as_secs() as i64
+    }
+}
+
+/// The provider token a client sends, signed again once it is due.
+///
+/// Senders only take the read lock to copy the current token. When it
+/// comes due, one sender signs a new one while the others keep sending the
+/// old one, which APNS still accepts; senders only wait for signing when
+/// there is no usable token at all.
+pub(crate) struct TokenCache {
+    current: RwLock<Option<Arc<CachedToken>>>,
+    /// Held while signing, so that only one sender signs at a time.
+    signing: Mutex<()>,
+    /// When each token of the last hour was signed.
+    signed: Mutex<VecDeque<Instant>>,
+    /// Until when the current token is kept, even if it is due, after
+    /// `TooManyProviderTokenUpdates`.
+    cool_down_until: Mutex<Option<Instant>>,
+    pub refresh_interval: Duration,
+    pub refresh_jitter: Duration,
+    pub backdate: Duration,
+}
+
+impl TokenCache {
+    pub fn new() -> Self {
+        TokenCache {
+            current: RwLock::new(None),
+            signing: Mutex::new(()),
+            signed: Mutex::new(VecDeque::new()),
+            cool_down_until: Mutex::new(None),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            refresh_jitter: DEFAULT_REFRESH_JITTER,
+            backdate: DEFAULT_BACKDATE,
+        }
+    }
+
+    /// Set the refresh interval, within what APNS allows.
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = interval.clamp(MIN_REFRESH_INTERVAL, MAX_REFRESH_INTERVAL);
+    }
+
+    /// A token signed with `key` for `team_id`, signing one if needed, and
+    /// whether it was newly signed.
+    pub fn get(&self, key: &SigningKey, team_id: &str, rng: &dyn Rng) -> Result<(String, bool), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let current = self.current.read().unwrap().clone();
+        match current {
+            Some(ref token) if token.is_usable(&key.kid, now) => {
+                if now < token.refresh_at || self.is_cooling_down() {
+                    return Ok((token.token.clone(), false));
+                }
+                // Due, but still accepted: refresh unless another sender
+                // already is, in which case keep sending this one.
+                match self.signing.try_lock() {
+                    Ok(_signing) => self.sign_unless_fresh(key, team_id, now, rng),
+                    Err(_) => Ok((token.token.clone(), false)),
+                }
+            }
+            _ => {
+                let _signing = self.signing.lock().unwrap();
+                self.sign_unless_fresh(key, team_id, now, rng)
+            }
+        }
+    }
+
+    /// Sign a token, unless another sender signed one while this one
+    /// waited for the signing lock.
+    fn sign_unless_fresh(&self, key: &SigningKey, team_id: &str, now: i64, rng: &dyn Rng) -> Result<(String, bool), Error> {
+        if let Some(ref token) = *self.current.read().unwrap() {
+            if token.is_usable(&key.kid, now) && now < token.refresh_at {
+                return Ok((token.token.clone(), false));
+            }
+        }
+        self.sign(key, team_id, now, rng).map(|token| (token, true))
+    }
+
+    fn sign(&self, key: &SigningKey, team_id: &str, now: i64, rng: &dyn Rng) -> Result<String, Error> {
+        let issued_at = now - self.backdate.as_secs() as i64;
+        let token = key.sign(team_id, issued_at)?;
+        let jitter = rng::below(rng, self.refresh_jitter.min(self.refresh_interval - MIN_REFRESH_INTERVAL).as_secs()) as i64;
+        *self.current.write().unwrap() = Some(Arc::new(CachedToken {
+            kid: key.kid.clone(),
+            issued_at,
+            refresh_at: issued_at + self.refresh_interval.as_secs() as i64 - jitter,
+            token: token.clone(),
+        }));
+        self.signed.lock().unwrap().push_back(Instant::now());
+        Ok(token)
+    }
+
+    /// The number of tokens signed in the last hour.
+    pub fn signings_last_hour(&self) -> usize {
+        let mut signed = self.signed.lock().unwrap();
+        while signed.front().is_some_and(|at| at.elapsed() >= HOUR) {
+            signed.pop_front();
+        }
+        signed.len()
+    }
+
+    /// Drop the current token, unless cooling down.
+    pub fn clear(&self) {
+        if !self.is_cooling_down() {
+            *self.current.write().unwrap() = None;
+        }
+    }
+
+    /// Keep sending the current token for `COOL_DOWN`, rather than signing
+    /// new ones, unless it stops being accepted at all. Returns whether
+    /// this started a cool-down, rather than one being under way.
+    pub fn cool_down(&self) -> bool {
+        let mut until = self.cool_down_until.lock().unwrap();
+        let now = Instant::now();
+        if until.is_some_and(|until| now < until) {
+            return false;
+        }
+        *until = Some(now + COOL_DOWN);
+        true
+    }
+
+    fn is_cooling_down(&self) -> bool {
+        self.cool_down_until.lock().unwrap().is_some_and(|until| Instant::now() < until)
+    }
+
+    pub fn info(&self) -> Option<TokenInfo> {
+        let token = self.current.read().unwrap().clone()?;
+        let issued_at = UNIX_EPOCH + Duration::from_secs(token.issued_at as u64);
+        let expires_at = issued_at + Duration::from_secs(TOKEN_LIFETIME as u64);
+        Some(TokenInfo {
+            issued_at,
+            expires_in: expires_at.duration_since(SystemTime::now()).unwrap_or_default(),
+            kid: token.kid.clone(),
+        })
+    }
+}
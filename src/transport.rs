@@ -0,0 +1,78 @@
+use bytes::{Bytes, BytesMut};
+use hyper::{body::HttpBody, Body, client::connect::Connection, Client, Request, Response, service::Service, Uri};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::error::SendError;
+use crate::timings::{Stopwatch, Timings};
+#[cfg(feature = "h2")]
+use crate::h2_transport::H2Transport;
+#[cfg(feature = "wire-log")]
+use crate::wire_log;
+
+
+/// The HTTP/2 implementation requests are sent with.
+pub(crate) enum Transport<S> {
+    Hyper(Client<S, Body>),
+    #[cfg(feature = "h2")]
+    H2(H2Transport<S>),
+}
+
+impl<S> Transport<S>
+where
+    S: Service<Uri> + Clone + Send + Sync + 'static,
+    S::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    S::Future: Send + Unpin + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Send a request and read the whole response, recording the time
+    /// taken by each phase in `timings`. Fails with
+    /// `SendError::ResponseTooLarge` once the body is over `body_limit`
+    /// bytes.
+    pub async fn request(&self, req: Request<Vec<u8>>, body_limit: usize, timings: &mut Timings) -> Result<Response<Bytes>, SendError> {
+        #[cfg(feature = "wire-log")]
+        wire_log::request(&req);
+        let res = self.send(req, body_limit, timings).await;
+        #[cfg(feature = "wire-log")]
+        if let Ok(ref res) = res {
+            wire_log::response(res);
+        }
+        res
+    }
+
+    async fn send(&self, req: Request<Vec<u8>>, body_limit: usize, timings: &mut Timings) -> Result<Response<Bytes>, SendError> {
+        match self {
+            Transport::Hyper(client) => {
+                let mut stopwatch = Stopwatch::start();
+                let (parts, mut body) = client.request(req.map(Body::from)).await?.into_parts();
+                timings.ttfb = stopwatch.lap();
+                let mut bytes = BytesMut::new();
+                while let Some(chunk) = body.data().await {
+                    extend_limited(&mut bytes, &chunk?, body_limit)?;
+                }
+                timings.body_read = stopwatch.lap();
+                Ok(Response::from_parts(parts, bytes.freeze()))
+            }
+            #[cfg(feature = "h2")]
+            Transport::H2(transport) => transport.request(req, body_limit, timings).await,
+        }
+    }
+
+    /// Drop the current connection, if the transport manages one.
+    pub async fn reset(&self) {
+        match self {
+            Transport::Hyper(_) => (),
+            #[cfg(feature = "h2")]
+            Transport::H2(transport) => transport.reset().await,
+        }
+    }
+}
+
+/// Append a chunk of a response body, unless the body would get longer
+/// than `limit`.
+pub(crate) fn extend_limited(bytes: &mut BytesMut, chunk: &[u8], limit: usize) -> Result<(), SendError> {
+    if bytes.len() + chunk.len() > limit {
+        return Err(SendError::ResponseTooLarge { limit });
+    }
+    bytes.extend_from_slice(chunk);
+    Ok(())
+}
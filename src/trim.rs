@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use crate::types::{Alert, AlertPayload, ApnsRequest, Payload};
+
+
+/// Appended to an alert body that has been truncated.
+const ELLIPSIS: &str = "\u{2026}";
+
+/// What to do when the serialized payload of a notification exceeds the
+/// maximum payload size.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum TrimPolicy {
+    /// Refuse to send the notification.
+    #[default]
+    Disabled,
+    /// Truncate the alert body (on a character boundary) and append an
+    /// ellipsis until the payload fits.
+    TruncateBody,
+}
+
+impl TrimPolicy {
+    /// Serialize the request, trimming the alert body first if the policy
+    /// allows it and the payload would otherwise be larger than `limit`.
+    pub(crate) fn serialize(self, request: &mut ApnsRequest, limit: usize) -> Result<Vec<u8>, serde_json::Error> {
+        let encoded = serde_json::to_vec(request)?;
+        if self == TrimPolicy::Disabled || encoded.len() <= limit {
+            return Ok(encoded);
+        }
+
+        let original = match alert_body(Arc::make_mut(&mut request.aps)) {
+            Some(body) => body.clone(),
+            None => return Ok(encoded),
+        };
+
+        // JSON escaping means the encoded size of the body isn't simply its
+        // length, so search for the longest prefix that still fits.
+        let (mut low, mut high) = (0, original.len());
+        while low < high {
+            let mid = floor_char_boundary(&original, low + (high - low).div_ceil(2));
+            if mid <= low {
+                break;
+            }
+            if truncate(request, &original, mid)?.len() <= limit {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        let encoded = truncate(request, &original, low)?;
+        if encoded.len() > limit {
+            // Even an empty body doesn't fit, so the notification will be
+            // refused anyway; leave it as it was.
+            return truncate(request, &original, original.len());
+        }
+        Ok(encoded)
+    }
+}
+
+/// Replace the alert body with the first `keep` bytes of `original` (plus an
+/// ellipsis if anything was cut) and serialize the request.
+fn truncate(request: &mut ApnsRequest, original: &str, keep: usize) -> Result<Vec<u8>, serde_json::Error> {
+    let mut truncated = original[..keep].to_string();
+    if keep < original.len() {
+        truncated.truncate(truncated.trim_end().len());
+        truncated.push_str(ELLIPSIS);
+    }
+    if let Some(body) = alert_body(Arc::make_mut(&mut request.aps)) {
+        *body = truncated;
+    }
+    serde_json::to_vec(request)
+}
+
+fn alert_body(payload: &mut Payload) -> Option<&mut String> {
+    match payload.alert {
+        Some(Alert::Simple(ref mut body)) => Some(body),
+        Some(Alert::Payload(AlertPayload { body: Some(ref mut body), .. })) => Some(body),
+        _ => None,
+    }
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
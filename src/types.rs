@@ -1,13 +1,57 @@
-use failure::Fail;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use serde::{Serialize, Deserialize};
-use uuid::Uuid;
+use serde_json::{Map, Value};
+
+use crate::consts::{self, APN_URL_DEV, APN_URL_PRODUCTION, MAX_COLLAPSE_ID_SIZE, MEDIA_URL_KEY, PUSH_CONSOLE_URL};
+use crate::error::{BuildError, ValidationError};
+use crate::id::ApnsId;
+use crate::limits::PayloadLimits;
 
 
-/// APNS production endpoint.
-pub static APN_URL_PRODUCTION: &'static str = "https://api.push.apple.com";
+/// An APNS environment. Device tokens are only valid in the environment the
+/// app was built for.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Environment {
+    Production,
+    Sandbox,
+}
 
-/// APNS development endpoint.
-pub static APN_URL_DEV: &'static str = "https://api.development.push.apple.com";
+impl Environment {
+    /// The API endpoint of the environment.
+    pub fn url(self) -> &'static str {
+        match self {
+            Environment::Production => APN_URL_PRODUCTION,
+            Environment::Sandbox => APN_URL_DEV,
+        }
+    }
+
+    /// A link to the delivery log entry of a notification in the Push
+    /// Notifications Console, from the `apns-unique-id` APNS answered with.
+    /// See `ApplePushClient::console_url`.
+    ///
+    /// Apple doesn't document these links; this is the form the console
+    /// uses for them.
+    ///
+    /// ```
+    /// # use apple_push::Environment;
+    /// let url = Environment::Sandbox.console_url("TEAMID1234", "com.example.app", "b4c1d8f0-1a2b-4c3d-8e9f-0a1b2c3d4e5f");
+    /// assert!(url.ends_with("?environment=development&notificationId=b4c1d8f0-1a2b-4c3d-8e9f-0a1b2c3d4e5f"));
+    /// ```
+    pub fn console_url(self, team_id: &str, topic: &str, unique_id: &str) -> String {
+        let environment = match self {
+            Environment::Production => "production",
+            Environment::Sandbox => "development",
+        };
+        format!(
+            "{}/teams/{}/app/{}/deliveryLog?environment={}&notificationId={}",
+            PUSH_CONSOLE_URL, team_id, topic, environment, unique_id,
+        )
+    }
+}
 
 /// Notification priority.
 /// See APNS documentation for the effects.
@@ -29,21 +73,134 @@ impl Priority {
     }
 }
 
-#[derive(Fail, Debug)]
-#[fail(display = "CollapseId too long (must be at most 64 bytes)")]
+/// How the device interrupts the user with a notification.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum InterruptionLevel {
+    /// Added to the notification list without lighting up the screen or
+    /// playing a sound.
+    Passive,
+    /// Presented immediately. The default.
+    Active,
+    /// Presented immediately, breaking through Focus.
+    TimeSensitive,
+    /// Presented immediately, breaking through Focus and the ring/silent
+    /// switch. Requires an entitlement from Apple.
+    Critical,
+}
+
+impl InterruptionLevel {
+    /// Whether the level asks for immediate delivery, which Apple says
+    /// calls for priority 10.
+    pub fn is_urgent(self) -> bool {
+        matches!(self, InterruptionLevel::TimeSensitive | InterruptionLevel::Critical)
+    }
+}
+
+/// What a Live Activity notification does to the activity.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityEvent {
+    /// Starts an activity, sent to a push-to-start token.
+    Start,
+    Update,
+    End,
+}
+
+/// The kind of notification, sent as the `apns-push-type` header.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PushType {
+    Alert,
+    Background,
+    Location,
+    Voip,
+    Complication,
+    FileProvider,
+    Mdm,
+    LiveActivity,
+    PushToTalk,
+}
+
+impl PushType {
+    /// The value of the `apns-push-type` header.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PushType::Alert => "alert",
+            PushType::Background => "background",
+            PushType::Location => "location",
+            PushType::Voip => "voip",
+            PushType::Complication => "complication",
+            PushType::FileProvider => "fileprovider",
+            PushType::Mdm => "mdm",
+            PushType::LiveActivity => "liveactivity",
+            PushType::PushToTalk => "pushtotalk",
+        }
+    }
+
+    /// The suffix APNS requires on the bundle id in the topic of
+    /// notifications of this type, if any.
+    pub fn topic_suffix(self) -> Option<&'static str> {
+        match self {
+            PushType::Alert | PushType::Background | PushType::Mdm => None,
+            PushType::Location => Some(".location-query"),
+            PushType::Voip => Some(".voip"),
+            PushType::Complication => Some(".complication"),
+            PushType::FileProvider => Some(".pushkit.fileprovider"),
+            PushType::LiveActivity => Some(".push-type.liveactivity"),
+            PushType::PushToTalk => Some(".voip-ptt"),
+        }
+    }
+
+    /// The type of a notification without one, guessed from its topic and
+    /// payload: a topic suffix decides, else payloads with nothing but
+    /// `content-available` are background notifications, and the rest
+    /// alerts.
+    pub fn infer(topic: &str, payload: &Payload) -> Self {
+        let by_topic = [
+            PushType::Location, PushType::Voip, PushType::Complication, PushType::FileProvider,
+            PushType::LiveActivity, PushType::PushToTalk,
+        ];
+        let by_topic = by_topic.iter().find(|t| t.topic_suffix().is_some_and(|suffix| topic.ends_with(suffix)));
+        match by_topic {
+            Some(&push_type) => push_type,
+            None if payload.content_available == Some(true)
+                && payload.alert.is_none() && payload.badge.is_none() && payload.sound.is_none() => PushType::Background,
+            None => PushType::Alert,
+        }
+    }
+}
+
+impl ::std::fmt::Display for PushType {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
 pub struct CollapseIdTooLongError;
 
+impl ::std::fmt::Display for CollapseIdTooLongError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str("CollapseId too long (must be at most 64 bytes)")
+    }
+}
+
+impl ::std::error::Error for CollapseIdTooLongError {}
+
 /// Wrapper type for collapse ids.
-/// It may be an arbitrary string, but is limited in length to at most 63 bytes.
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// It may be an arbitrary string, but is limited in length to at most 64 bytes.
+/// The limit is also enforced when deserializing.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
 pub struct CollapseId(String);
 
 impl CollapseId {
     /// Construct a new collapse id.
     /// Returns an error if id exceeds the maximum length of 64 bytes.
-    pub fn new(value: String) -> Result<Self, CollapseIdTooLongError> {
-        // CollapseID must be at most 64 bytes long.
-        if value.len() > 64 {
+    pub fn new<S: Into<String>>(value: S) -> Result<Self, CollapseIdTooLongError> {
+        let value = value.into();
+        if value.len() > MAX_COLLAPSE_ID_SIZE {
             Err(CollapseIdTooLongError)
         } else {
             Ok(CollapseId(value))
@@ -56,6 +213,20 @@ impl CollapseId {
     }
 }
 
+impl TryFrom<String> for CollapseId {
+    type Error = CollapseIdTooLongError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        CollapseId::new(value)
+    }
+}
+
+impl From<CollapseId> for String {
+    fn from(id: CollapseId) -> Self {
+        id.0
+    }
+}
+
 /// Alert content for a notification.
 ///
 /// See the official documentation for details:
@@ -70,27 +241,54 @@ pub struct AlertPayload {
     pub title_loc_key: Option<String>,
     #[serde(rename = "title-loc-args", skip_serializing_if = "Option::is_none")]
     pub title_loc_args: Option<Vec<String>>,
+    /// Shown below the title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+    #[serde(rename = "subtitle-loc-key", skip_serializing_if = "Option::is_none")]
+    pub subtitle_loc_key: Option<String>,
+    #[serde(rename = "subtitle-loc-args", skip_serializing_if = "Option::is_none")]
+    pub subtitle_loc_args: Option<Vec<String>>,
     #[serde(rename = "action-loc-key", skip_serializing_if = "Option::is_none")]
     pub action_loc_key: Option<String>,
     #[serde(rename = "loc-key", skip_serializing_if = "Option::is_none")]
     pub loc_key: Option<String>,
     #[serde(rename = "loc-args", skip_serializing_if = "Option::is_none")]
     pub loc_args: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub loc_image: Option<String>,
+    /// The image file in the app bundle shown while the app launches from
+    /// the notification. Sent as `launch-image`; notifications serialized
+    /// with its old misnamed `loc_image` key still deserialize.
+    #[serde(rename = "launch-image", alias = "loc_image", skip_serializing_if = "Option::is_none")]
+    pub launch_image: Option<String>,
+    /// Names the thread in the summary shown for grouped notifications on
+    /// iOS 12 to 14, such as the sender in "3 more messages from Alice".
+    #[serde(rename = "summary-arg", skip_serializing_if = "Option::is_none")]
+    pub summary_arg: Option<String>,
+    /// How many items the notification counts as in the summary, such as
+    /// the number of messages it delivers. Defaults to 1 on the device.
+    #[serde(rename = "summary-arg-count", skip_serializing_if = "Option::is_none")]
+    pub summary_arg_count: Option<u32>,
+    /// Keys the crate doesn't model. See `Payload::merge_raw`.
+    #[serde(flatten)]
+    pub raw: Map<String, Value>,
 }
 
 impl AlertPayload {
     fn new(title: Option<String>, body: Option<String>) -> Self {
         AlertPayload {
-            title: title,
-            body: body,
+            title,
+            body,
             title_loc_key: None,
             title_loc_args: None,
+            subtitle: None,
+            subtitle_loc_key: None,
+            subtitle_loc_args: None,
             action_loc_key: None,
             loc_key: None,
             loc_args: None,
-            loc_image: None,
+            launch_image: None,
+            summary_arg: None,
+            summary_arg_count: None,
+            raw: Map::new(),
         }
     }
 }
@@ -98,6 +296,8 @@ impl AlertPayload {
 /// The alert content.
 /// This can either be a plain message string, or an AlertPayload with more
 /// configuration.
+// Boxing the payload would make alerts awkward to match on.
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(untagged)]
 pub enum Alert {
@@ -105,6 +305,48 @@ pub enum Alert {
     Payload(AlertPayload),
 }
 
+/// A sound played with a notification.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Sound {
+    /// The name of a sound file in the app bundle, or `default`.
+    Named(String),
+    Critical(CriticalSound),
+}
+
+impl From<String> for Sound {
+    fn from(name: String) -> Self {
+        Sound::Named(name)
+    }
+}
+
+impl From<&str> for Sound {
+    fn from(name: &str) -> Self {
+        Sound::Named(name.into())
+    }
+}
+
+/// The sound of a critical alert, which plays even when the device is
+/// muted or in Do Not Disturb. Needs the critical alerts entitlement.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CriticalSound {
+    critical: u8,
+    /// The name of a sound file in the app bundle, or `default`.
+    pub name: String,
+    /// Between 0 (silent) and 1 (full volume).
+    pub volume: f64,
+}
+
+impl CriticalSound {
+    pub fn new<S: Into<String>>(name: S, volume: f64) -> Self {
+        CriticalSound {
+            critical: 1,
+            name: name.into(),
+            volume: volume.clamp(0.0, 1.0),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct Payload {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -114,20 +356,189 @@ pub struct Payload {
     pub badge: Option<u32>,
     /// Sound to play. Use 'default' for the default sound.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub sound: Option<String>,
+    pub sound: Option<Sound>,
     /// Set to true to mark the app as having content available.
-    #[serde(rename = "content-available", skip_serializing_if = "Option::is_none")]
+    ///
+    /// Sent as `1`, as are the other flags; `true`, which earlier versions
+    /// sent, is still read.
+    ///
+    /// ```
+    /// # use apple_push::Payload;
+    /// let payload: Payload = serde_json::from_str(r#"{"content-available": true, "mutable-content": 1}"#).unwrap();
+    /// assert_eq!((payload.content_available, payload.mutable_content), (Some(true), Some(true)));
+    /// assert_eq!(serde_json::to_value(&payload).unwrap()["content-available"], 1);
+    /// ```
+    #[serde(rename = "content-available", with = "flag", skip_serializing_if = "Option::is_none", default)]
     pub content_available: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub category: Option<String>,
     #[serde(rename = "thread-id", skip_serializing_if = "Option::is_none")]
     pub thread_id: Option<String>,
+    /// Set to true to let the app's notification service extension modify
+    /// the notification before it is shown.
+    #[serde(rename = "mutable-content", with = "flag", skip_serializing_if = "Option::is_none", default)]
+    pub mutable_content: Option<bool>,
+    #[serde(rename = "interruption-level", skip_serializing_if = "Option::is_none")]
+    pub interruption_level: Option<InterruptionLevel>,
+    /// The identifier of the window brought forward when the notification
+    /// is opened.
+    #[serde(rename = "target-content-id", skip_serializing_if = "Option::is_none")]
+    pub target_content_id: Option<String>,
+    /// How the notification is ranked in the summary against the app's
+    /// other notifications, between 0 and 1.
+    #[serde(rename = "relevance-score", skip_serializing_if = "Option::is_none")]
+    pub relevance_score: Option<f64>,
+    /// The Focus filter criteria the notification must match to be shown.
+    #[serde(rename = "filter-criteria", skip_serializing_if = "Option::is_none")]
+    pub filter_criteria: Option<String>,
+    /// For Live Activities, when the update was made, in seconds since the
+    /// epoch. The device ignores updates older than the one it has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// For Live Activities, whether the activity is started, updated or
+    /// ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<ActivityEvent>,
+    /// For Live Activities, the new state of the activity's dynamic
+    /// content, as the app's `ContentState` type decodes it.
+    #[serde(rename = "content-state", skip_serializing_if = "Option::is_none")]
+    pub content_state: Option<Value>,
+    /// For ended Live Activities, when the activity leaves the Lock Screen,
+    /// in seconds since the epoch.
+    #[serde(rename = "dismissal-date", skip_serializing_if = "Option::is_none")]
+    pub dismissal_date: Option<u64>,
+    /// For Live Activities, when the content becomes outdated, in seconds
+    /// since the epoch.
+    #[serde(rename = "stale-date", skip_serializing_if = "Option::is_none")]
+    pub stale_date: Option<u64>,
+    /// For started Live Activities, the name of the app's
+    /// `ActivityAttributes` type.
+    #[serde(rename = "attributes-type", skip_serializing_if = "Option::is_none")]
+    pub attributes_type: Option<String>,
+    /// For started Live Activities, the static attributes of the activity,
+    /// as the app's `ActivityAttributes` type decodes them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<Value>,
+    /// Keys the crate doesn't model. See `merge_raw`.
+    #[serde(flatten)]
+    pub raw: Map<String, Value>,
+}
+
+/// Which value is kept where JSON merged with `Payload::merge_raw_with`
+/// conflicts with a value already in the payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MergePolicy {
+    /// Keep the value already in the payload.
+    #[default]
+    KeepExisting,
+    /// Replace it with the merged value.
+    Overwrite,
+}
+
+impl Payload {
+    /// Deep-merge `value`, a JSON object, into the `aps` dictionary, for
+    /// keys the crate doesn't model yet. Values already set in the payload
+    /// win over conflicting ones in `value`.
+    ///
+    /// Keys the crate models end up in their fields, the rest in `raw`.
+    /// Fails if `value` isn't an object or gives a modelled key a value of
+    /// the wrong type.
+    ///
+    /// ```
+    /// # use apple_push::Payload;
+    /// let mut payload = Payload { badge: Some(1), ..Payload::default() };
+    /// payload.merge_raw(serde_json::json!({ "badge": 2, "relevance-score": 0.5, "x-beta-flag": true })).unwrap();
+    /// assert_eq!(payload.badge, Some(1));
+    /// assert_eq!(payload.relevance_score, Some(0.5));
+    /// assert_eq!(payload.raw["x-beta-flag"], true);
+    /// ```
+    pub fn merge_raw(&mut self, value: Value) -> Result<(), serde_json::Error> {
+        self.merge_raw_with(value, MergePolicy::KeepExisting)
+    }
+
+    /// Like `merge_raw`, with `policy` deciding conflicts.
+    pub fn merge_raw_with(&mut self, value: Value, policy: MergePolicy) -> Result<(), serde_json::Error> {
+        if !value.is_object() {
+            return Err(serde::de::Error::custom("can only merge a JSON object into aps"));
+        }
+        let mut merged = serde_json::to_value(&*self)?;
+        merge(&mut merged, value, policy);
+        *self = serde_json::from_value(merged)?;
+        Ok(())
+    }
+}
+
+fn merge(existing: &mut Value, new: Value, policy: MergePolicy) {
+    match (existing, new) {
+        (Value::Object(existing), Value::Object(new)) => {
+            for (key, value) in new {
+                match existing.get_mut(&key) {
+                    Some(old) => merge(old, value, policy),
+                    None => {
+                        existing.insert(key, value);
+                    }
+                }
+            }
+        }
+        (existing, new) => {
+            if policy == MergePolicy::Overwrite {
+                *existing = new;
+            }
+        }
+    }
+}
+
+pub(crate) fn epoch_seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// Flags APNS expects as the number 1. Booleans are read too, as earlier
+/// versions wrote them.
+mod flag {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Int(u8),
+    }
+
+    pub fn serialize<S: Serializer>(value: &Option<bool>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(if *value == Some(true) { 1 } else { 0 })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+        Ok(Some(match Flag::deserialize(deserializer)? {
+            Flag::Bool(flag) => flag,
+            Flag::Int(flag) => flag != 0,
+        }))
+    }
+}
+
+/// See `Notification::validate_device_token`.
+pub(crate) fn validate_device_token(device_token: &str) -> Result<(), ValidationError> {
+    let problem = if device_token.is_empty() {
+        "is empty"
+    } else if !device_token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        "isn't hex digits"
+    } else if device_token.len() % 2 == 1 {
+        "has an odd number of hex digits"
+    } else {
+        return Ok(());
+    };
+    Err(ValidationError::DeviceToken { problem })
 }
 
 /// A full json request object for sending a notification to the API.
+#[cfg(feature = "client")]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct ApnsRequest {
-    pub aps: Payload,
+    pub aps: Arc<Payload>,
+    #[serde(rename = "media-url", skip_serializing_if = "Option::is_none")]
+    pub media_url: Option<String>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<Map<String, Value>>,
 }
 
 /// A notification struct contains all relevant data for a notification request
@@ -139,15 +550,35 @@ pub struct Notification {
     /// The topic to use. Usually the app bundle id.
     pub topic: String,
     pub device_token: String,
-    pub payload: Payload,
+    /// The payload, which may be shared with other notifications.
+    pub payload: Arc<Payload>,
 
     /// Optional id identifying the message.
-    pub id: Option<Uuid>,
+    pub id: Option<ApnsId>,
     /// Optional expiration time as UNIX timestamp.
     pub expiration: Option<u64>,
+    /// Optional time to live, making the notification expire that long
+    /// after it is sent. Ignored if `expiration` is set.
+    pub ttl: Option<Duration>,
     /// Priority for the notification.
     pub priority: Option<Priority>,
+    /// The kind of notification. Inferred from the topic and payload if not
+    /// set; see `PushType::infer`.
+    pub push_type: Option<PushType>,
     pub collapse_id: Option<CollapseId>,
+    /// URL of an image, sound or video for the app's notification service
+    /// extension to download and attach, sent as the `media-url` key next
+    /// to `aps`. See `NotificationBuilder::with_media_attachment`.
+    pub media_url: Option<String>,
+    /// Keys for the app, sent next to `aps`. They count towards the payload
+    /// size limit, and can't be `aps`, nor `media-url` if `media_url` is set.
+    pub custom: Option<Map<String, Value>>,
+    /// The environment to send the notification to, whichever the client
+    /// is set to.
+    pub environment: Option<Environment>,
+    /// `apns-*` request headers the crate doesn't model yet, sent as they
+    /// are. They can't replace the headers the crate sends itself.
+    pub extra_apns_headers: Option<BTreeMap<String, String>>,
 }
 
 impl Notification {
@@ -156,11 +587,207 @@ impl Notification {
         Notification {
             topic: topic.into(),
             device_token: device_token.into(),
-            payload,
+            payload: Arc::new(payload),
             id: None,
             expiration: None,
+            ttl: None,
             priority: None,
+            push_type: None,
             collapse_id: None,
+            media_url: None,
+            custom: None,
+            environment: None,
+            extra_apns_headers: None,
+        }
+    }
+
+    /// The priority the notification is sent with: the explicit priority,
+    /// or else priority 5 for background notifications, which APNS requires,
+    /// and priority 10 for time-sensitive and critical notifications, as
+    /// Apple recommends.
+    pub fn effective_priority(&self) -> Option<Priority> {
+        match self.priority {
+            Some(priority) => Some(priority),
+            None if self.effective_push_type() == PushType::Background => Some(Priority::Low),
+            None if self.payload.interruption_level.is_some_and(InterruptionLevel::is_urgent) => Some(Priority::High),
+            None => None,
+        }
+    }
+
+    /// The push type the notification is sent with: the explicit one, or
+    /// else the one inferred from its topic and payload.
+    pub fn effective_push_type(&self) -> PushType {
+        self.push_type.unwrap_or_else(|| PushType::infer(&self.topic, &self.payload))
+    }
+
+    /// Check that the notification is one APNS accepts for its push type:
+    /// the topic has the suffix the type requires, and background
+    /// notifications have priority 5 and nothing but `content-available`.
+    pub fn validate_push_type(&self) -> Result<(), ValidationError> {
+        let push_type = self.effective_push_type();
+        let refuse = |problem| Err(ValidationError::PushType { push_type, problem });
+        if let Some(suffix) = push_type.topic_suffix() {
+            if !self.topic.ends_with(suffix) {
+                return refuse(format!("need a topic ending in {}", suffix));
+            }
+        }
+        if push_type == PushType::LiveActivity && (self.payload.event.is_none() || self.payload.timestamp.is_none()) {
+            return refuse("need an event and a timestamp".to_owned());
+        }
+        if push_type == PushType::LiveActivity && self.payload.event == Some(ActivityEvent::Start)
+            && (self.payload.attributes_type.is_none() || self.payload.attributes.is_none() || self.payload.alert.is_none()) {
+            return refuse("need attributes-type, attributes and an alert to start an activity".to_owned());
+        }
+        if push_type == PushType::Background {
+            if self.effective_priority() == Some(Priority::High) {
+                return refuse("need priority 5".to_owned());
+            }
+            if self.payload.content_available != Some(true) {
+                return refuse("need content-available".to_owned());
+            }
+            if self.payload.alert.is_some() || self.payload.badge.is_some() || self.payload.sound.is_some() {
+                return refuse("can't have an alert, badge or sound".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the device token could be one APNS issued: an even
+    /// number of hex digits. APNS would refuse it with `BadDeviceToken`.
+    ///
+    /// ```
+    /// use apple_push::NotificationBuilder;
+    ///
+    /// assert!(NotificationBuilder::new("com.example.app", "00fc13adff785122").build().validate_device_token().is_ok());
+    /// assert!(NotificationBuilder::new("com.example.app", "<00fc 13ad>").build().validate_device_token().is_err());
+    /// ```
+    pub fn validate_device_token(&self) -> Result<(), ValidationError> {
+        validate_device_token(&self.device_token)
+    }
+
+    /// A key of `custom` that would collide with a key the crate sends.
+    pub fn reserved_custom_key(&self) -> Option<&str> {
+        let custom = self.custom.as_ref()?;
+        let mut reserved = vec!["aps"];
+        if self.media_url.is_some() {
+            reserved.push(MEDIA_URL_KEY);
+        }
+        reserved.into_iter().find(|key| custom.contains_key(*key))
+    }
+
+    /// Check that the extra headers are `apns-*` headers the crate doesn't
+    /// send itself, with values that can be sent.
+    pub fn validate_extra_apns_headers(&self) -> Result<(), ValidationError> {
+        let reserved = [
+            consts::APNS_ID, consts::APNS_TOPIC, consts::APNS_EXPIRATION, consts::APNS_PRIORITY,
+            consts::APNS_COLLAPSE_ID, consts::APNS_PUSH_TYPE,
+        ];
+        for (name, value) in self.extra_apns_headers.iter().flatten() {
+            let problem = if !name.starts_with("apns-") {
+                "doesn't start with apns-"
+            } else if http::header::HeaderName::from_lowercase(name.as_bytes()).is_err() {
+                "isn't a lowercase header name"
+            } else if reserved.contains(&name.as_str()) {
+                "is sent by the crate"
+            } else if http::header::HeaderValue::from_str(value).is_err() {
+                "has a value that isn't a header value"
+            } else {
+                continue;
+            };
+            return Err(ValidationError::ExtraHeader { name: name.clone(), problem });
+        }
+        Ok(())
+    }
+
+    /// What is wrong with the expiration of the notification for its push
+    /// type, if it is sent at `now`, a UNIX timestamp: it has already
+    /// passed, or it is kept well past when the notification is of any use,
+    /// or it is so soon that APNS will most likely drop the notification.
+    /// Notifications without an expiration have no problem.
+    ///
+    /// ```
+    /// # use apple_push::{NotificationBuilder, PushType};
+    /// let now = 1_700_000_000;
+    /// let call = NotificationBuilder::new("com.example.app.voip", "device-token")
+    ///     .push_type(PushType::Voip)
+    ///     .expiration(now + 3600)
+    ///     .build();
+    /// assert!(call.expiration_problem(now).is_some());
+    ///
+    /// let message = NotificationBuilder::new("com.example.app", "device-token").title("Hi").expiration(now + 3600).build();
+    /// assert!(message.expiration_problem(now).is_none());
+    /// ```
+    pub fn expiration_problem(&self, now: u64) -> Option<&'static str> {
+        let expiration = self.expiration?;
+        let push_type = self.effective_push_type();
+        if expiration != 0 && expiration < now {
+            Some("have expired before they are sent")
+        } else if matches!(push_type, PushType::Voip | PushType::PushToTalk) && expiration > now + 60 {
+            Some("shouldn't expire more than a minute after they are sent, being stale by then; use expiration 0")
+        } else if push_type == PushType::Background && expiration == 0 {
+            Some("are delivered when the system sees fit, so expiration 0 drops most of them")
+        } else if push_type == PushType::LiveActivity && self.payload.stale_date.is_some_and(|stale| expiration > stale) {
+            Some("shouldn't expire after their stale-date, being out of date by then")
+        } else {
+            None
+        }
+    }
+
+    /// Check the notification as sending it would: against the
+    /// requirements of its push type, the keys and headers the crate sends
+    /// itself, and the payload size limit of its topic in `limits`.
+    pub fn validate(&self, limits: &PayloadLimits) -> Result<(), ValidationError> {
+        self.validate_push_type()?;
+        if let Some(key) = self.reserved_custom_key() {
+            return Err(ValidationError::ReservedKey { key: key.to_owned() });
+        }
+        self.validate_extra_apns_headers()?;
+        let size = self.request_body().to_string().len();
+        let limit = limits.limit_for(self.effective_push_type(), &self.topic);
+        if size > limit {
+            return Err(ValidationError::PayloadTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    /// The request body the notification is sent with, pretty-printed.
+    pub fn payload_preview(&self) -> String {
+        serde_json::to_string_pretty(&self.request_body()).unwrap_or_default()
+    }
+
+    /// The request body, before any trimming.
+    fn request_body(&self) -> Value {
+        let mut body = self.custom.clone().unwrap_or_default();
+        if let Some(ref media_url) = self.media_url {
+            body.insert(MEDIA_URL_KEY.to_owned(), media_url.clone().into());
+        }
+        body.insert("aps".to_owned(), serde_json::to_value(&*self.payload).unwrap_or_default());
+        Value::Object(body)
+    }
+
+    /// Whether the notification is time-sensitive or critical but was
+    /// explicitly given the low priority, which delays it.
+    pub fn has_conflicting_priority(&self) -> bool {
+        self.priority == Some(Priority::Low) && self.payload.interruption_level.is_some_and(InterruptionLevel::is_urgent)
+    }
+
+    /// A copy of this notification for another device token, sharing its
+    /// payload. The id is not copied, since it identifies one notification.
+    pub fn for_token(&self, device_token: &str) -> Self {
+        Notification {
+            topic: self.topic.clone(),
+            device_token: device_token.into(),
+            payload: self.payload.clone(),
+            id: None,
+            expiration: self.expiration,
+            ttl: self.ttl,
+            priority: self.priority,
+            push_type: self.push_type,
+            collapse_id: self.collapse_id.clone(),
+            media_url: self.media_url.clone(),
+            custom: self.custom.clone(),
+            environment: self.environment,
+            extra_apns_headers: self.extra_apns_headers.clone(),
         }
     }
 }
@@ -177,19 +804,73 @@ impl NotificationBuilder {
         }
     }
 
+    /// A builder for an alert with a title and body.
+    pub fn message<T: Into<String>, B: Into<String>>(topic: &str, device_token: &str, title: T, body: B) -> Self {
+        Self::new(topic, device_token).title(title).body(body).push_type(PushType::Alert)
+    }
+
+    /// A builder for a notification that only sets the badge of the app
+    /// icon, without alerting the user.
+    pub fn badge_only(topic: &str, device_token: &str, badge: u32) -> Self {
+        Self::new(topic, device_token).badge(badge).push_type(PushType::Alert).priority(Priority::Low)
+    }
+
+    /// A builder for a background notification waking the app to fetch
+    /// new content, without alerting the user.
+    pub fn silent_refresh(topic: &str, device_token: &str) -> Self {
+        Self::new(topic, device_token).content_available().push_type(PushType::Background).priority(Priority::Low)
+    }
+
+    /// A builder for a critical alert, which breaks through Do Not Disturb
+    /// and plays the default sound at full volume even when the device is
+    /// muted. Needs the critical alerts entitlement.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::critical_alert("com.example.app", "device-token", "Smoke detected", "Kitchen").build();
+    /// assert_eq!(serde_json::to_value(&n.payload.sound).unwrap(), serde_json::json!({
+    ///     "critical": 1, "name": "default", "volume": 1.0,
+    /// }));
+    /// ```
+    pub fn critical_alert<T: Into<String>, B: Into<String>>(topic: &str, device_token: &str, title: T, body: B) -> Self {
+        Self::message(topic, device_token, title, body)
+            .interruption_level(InterruptionLevel::Critical)
+            .sound(Sound::Critical(CriticalSound::new("default", 1.0)))
+            .priority(Priority::High)
+    }
+
+    pub(crate) fn payload_mut(&mut self) -> &mut Payload {
+        Arc::make_mut(&mut self.notification.payload)
+    }
+
+    /// The alert dictionary, turning a plain alert string into its body.
+    fn alert_mut(&mut self) -> &mut AlertPayload {
+        let payload = self.payload_mut();
+        let alert = match payload.alert.take() {
+            None => AlertPayload::new(None, None),
+            Some(Alert::Simple(body)) => AlertPayload::new(None, Some(body)),
+            Some(Alert::Payload(alert)) => alert,
+        };
+        payload.alert = Some(Alert::Payload(alert));
+        match payload.alert {
+            Some(Alert::Payload(ref mut alert)) => alert,
+            _ => unreachable!(),
+        }
+    }
+
     pub fn payload(mut self, payload: Payload) -> Self {
-        self.notification.payload = payload;
+        self.notification.payload = Arc::new(payload);
         self
     }
 
     pub fn alert<S: Into<String>>(mut self, alert: S) -> Self {
-        self.notification.payload.alert = Some(Alert::Simple(alert.into()));
+        self.payload_mut().alert = Some(Alert::Simple(alert.into()));
         self
     }
 
     pub fn title<S: Into<String>>(mut self, title: S) -> Self {
         let title = title.into();
-        let payload = match self.notification.payload.alert.take() {
+        let payload = match self.payload_mut().alert.take() {
             None => AlertPayload::new(Some(title), None),
             Some(Alert::Simple(_)) => AlertPayload::new(Some(title), None),
             Some(Alert::Payload(mut payload)) => {
@@ -197,13 +878,13 @@ impl NotificationBuilder {
                 payload
             }
         };
-        self.notification.payload.alert = Some(Alert::Payload(payload));
+        self.payload_mut().alert = Some(Alert::Payload(payload));
         self
     }
 
     pub fn body<S: Into<String>>(mut self, body: S) -> Self {
         let body = body.into();
-        let payload = match self.notification.payload.alert.take() {
+        let payload = match self.payload_mut().alert.take() {
             None => AlertPayload::new(None, Some(body)),
             Some(Alert::Simple(title)) => AlertPayload::new(Some(title), Some(body)),
             Some(Alert::Payload(mut payload)) => {
@@ -211,42 +892,202 @@ impl NotificationBuilder {
                 payload
             }
         };
-        self.notification.payload.alert = Some(Alert::Payload(payload));
+        self.payload_mut().alert = Some(Alert::Payload(payload));
+        self
+    }
+
+    /// Set the title to the localized string `key` of the app, formatted
+    /// with `args`.
+    pub fn title_loc_key<S: Into<String>>(mut self, key: S, args: Vec<String>) -> Self {
+        let alert = self.alert_mut();
+        alert.title_loc_key = Some(key.into());
+        alert.title_loc_args = if args.is_empty() { None } else { Some(args) };
+        self
+    }
+
+    /// Set the subtitle shown below the title.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .title("Delivery")
+    ///     .subtitle("Order #1234")
+    ///     .body("Your parcel is on its way")
+    ///     .build();
+    /// assert_eq!(serde_json::to_value(&n.payload).unwrap()["alert"]["subtitle"], "Order #1234");
+    /// ```
+    pub fn subtitle<S: Into<String>>(mut self, subtitle: S) -> Self {
+        self.alert_mut().subtitle = Some(subtitle.into());
+        self
+    }
+
+    /// Set the subtitle to the localized string `key` of the app,
+    /// formatted with `args`.
+    pub fn subtitle_loc_key<S: Into<String>>(mut self, key: S, args: Vec<String>) -> Self {
+        let alert = self.alert_mut();
+        alert.subtitle_loc_key = Some(key.into());
+        alert.subtitle_loc_args = if args.is_empty() { None } else { Some(args) };
+        self
+    }
+
+    /// Set the image shown while the app launches from the notification,
+    /// instead of its usual launch screen.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .title("Sale")
+    ///     .launch_image("Sale.png")
+    ///     .build();
+    /// assert_eq!(serde_json::to_value(&n.payload).unwrap()["alert"]["launch-image"], "Sale.png");
+    /// ```
+    pub fn launch_image<S: Into<String>>(mut self, image: S) -> Self {
+        self.alert_mut().launch_image = Some(image.into());
+        self
+    }
+
+    /// Name the thread of the notification in the summary the device shows
+    /// for grouped notifications. See `NotificationGroup` for sending a
+    /// whole thread.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .thread_id("chat-42".to_owned())
+    ///     .body("3 new photos")
+    ///     .summary_arg("Alice")
+    ///     .summary_arg_count(3)
+    ///     .build();
+    /// let alert = &serde_json::to_value(&n.payload).unwrap()["alert"];
+    /// assert_eq!(alert["summary-arg"], "Alice");
+    /// assert_eq!(alert["summary-arg-count"], 3);
+    /// ```
+    pub fn summary_arg<S: Into<String>>(mut self, summary_arg: S) -> Self {
+        self.alert_mut().summary_arg = Some(summary_arg.into());
+        self
+    }
+
+    /// Count the notification as `count` items in the summary of its
+    /// thread.
+    pub fn summary_arg_count(mut self, count: u32) -> Self {
+        self.alert_mut().summary_arg_count = Some(count);
+        self
+    }
+
+    /// Set the body to the localized string `key` of the app, formatted
+    /// with `args`.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .loc_key("NEW_MESSAGE", vec!["Alice".into()])
+    ///     .content_available()
+    ///     .relevance_score(0.8)
+    ///     .build();
+    /// assert_eq!(serde_json::to_value(&*n.payload).unwrap(), serde_json::json!({
+    ///     "alert": { "loc-key": "NEW_MESSAGE", "loc-args": ["Alice"] },
+    ///     "content-available": 1,
+    ///     "relevance-score": 0.8,
+    /// }));
+    /// ```
+    pub fn loc_key<S: Into<String>>(mut self, key: S, args: Vec<String>) -> Self {
+        let alert = self.alert_mut();
+        alert.loc_key = Some(key.into());
+        alert.loc_args = if args.is_empty() { None } else { Some(args) };
+        self
+    }
+
+    /// Set the localized string `key` of the app used as the title of the
+    /// button that opens the app.
+    pub fn action_loc_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.alert_mut().action_loc_key = Some(key.into());
         self
     }
 
     pub fn badge(mut self, number: u32) -> Self {
-        self.notification.payload.badge = Some(number);
+        self.payload_mut().badge = Some(number);
         self
     }
 
-    pub fn sound<S: Into<String>>(mut self, sound: S) -> Self {
-        self.notification.payload.sound = Some(sound.into());
+    pub fn sound<S: Into<Sound>>(mut self, sound: S) -> Self {
+        self.payload_mut().sound = Some(sound.into());
         self
     }
 
     pub fn content_available(mut self) -> Self {
-        self.notification.payload.content_available = Some(true);
+        self.payload_mut().content_available = Some(true);
         self
     }
 
     pub fn category(mut self, category: String) -> Self {
-        self.notification.payload.category = Some(category);
+        self.payload_mut().category = Some(category);
         self
     }
 
     pub fn thread_id(mut self, thread_id: String) -> Self {
-        self.notification.payload.thread_id = Some(thread_id);
+        self.payload_mut().thread_id = Some(thread_id);
         self
     }
 
-    pub fn id(mut self, id: Uuid) -> Self {
+    /// Set the interruption level. Time-sensitive and critical
+    /// notifications are sent with priority 10 unless `priority` says
+    /// otherwise.
+    pub fn interruption_level(mut self, level: InterruptionLevel) -> Self {
+        self.payload_mut().interruption_level = Some(level);
+        self
+    }
+
+    /// Let the app's notification service extension modify the
+    /// notification before it is shown.
+    pub fn mutable_content(mut self) -> Self {
+        self.payload_mut().mutable_content = Some(true);
+        self
+    }
+
+    /// Bring forward the scene whose `targetContentIdentifier` is `id` when
+    /// the notification is opened, in apps with several windows.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .title("New message")
+    ///     .target_content_id("conversation-42")
+    ///     .build();
+    /// assert_eq!(serde_json::to_value(&n.payload).unwrap()["target-content-id"], "conversation-42");
+    /// ```
+    pub fn target_content_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.payload_mut().target_content_id = Some(id.into());
+        self
+    }
+
+    /// Set the relevance score, clamped between 0 and 1.
+    pub fn relevance_score(mut self, score: f64) -> Self {
+        self.payload_mut().relevance_score = Some(score.clamp(0.0, 1.0));
+        self
+    }
+
+    pub fn filter_criteria<S: Into<String>>(mut self, criteria: S) -> Self {
+        self.payload_mut().filter_criteria = Some(criteria.into());
+        self
+    }
+
+    pub fn id(mut self, id: ApnsId) -> Self {
         self.notification.id = Some(id);
         self
     }
 
     pub fn expiration(mut self, expiration: u64) -> Self {
         self.notification.expiration = Some(expiration);
+        self.notification.ttl = None;
+        self
+    }
+
+    /// Expire the notification `ttl` after it is sent, rather than at a
+    /// fixed time. The expiration is computed when sending starts, so time
+    /// spent in a queue beforehand doesn't count.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.notification.ttl = Some(ttl);
+        self.notification.expiration = None;
         self
     }
 
@@ -255,12 +1096,160 @@ impl NotificationBuilder {
         self
     }
 
+    /// Set when a Live Activity update was made, which defaults to when
+    /// the builder was made for `live_activity::update` and `end`.
+    pub fn timestamp(mut self, at: SystemTime) -> Self {
+        self.payload_mut().timestamp = Some(epoch_seconds(at));
+        self
+    }
+
+    /// Set when a Live Activity's content becomes outdated.
+    pub fn stale_date(mut self, at: SystemTime) -> Self {
+        self.payload_mut().stale_date = Some(epoch_seconds(at));
+        self
+    }
+
+    /// Set when an ended Live Activity leaves the Lock Screen.
+    pub fn dismissal_date(mut self, at: SystemTime) -> Self {
+        self.payload_mut().dismissal_date = Some(epoch_seconds(at));
+        self
+    }
+
+    /// Set the push type, instead of inferring it from the topic and
+    /// payload.
+    pub fn push_type(mut self, push_type: PushType) -> Self {
+        self.notification.push_type = Some(push_type);
+        self
+    }
+
     pub fn collapse_id(mut self, id: CollapseId) -> Self {
         self.notification.collapse_id = Some(id);
         self
     }
 
+    /// Send the notification to `environment`, whichever the client is set
+    /// to, for device tokens known to come from development builds.
+    pub fn environment(mut self, environment: Environment) -> Self {
+        self.notification.environment = Some(environment);
+        self
+    }
+
+    /// Attach the media at `url` to the notification.
+    ///
+    /// APNS doesn't download attachments itself: this sets `mutable-content`
+    /// and puts the URL in the `media-url` key next to `aps`, for the app's
+    /// notification service extension to download and attach. Using the
+    /// same key everywhere lets one extension handle every sender.
+    pub fn with_media_attachment<S: Into<String>>(mut self, url: S) -> Self {
+        self.payload_mut().mutable_content = Some(true);
+        self.notification.media_url = Some(url.into());
+        self
+    }
+
+    /// Send `value` under `key` next to `aps`, for the app to read.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .alert("New message")
+    ///     .custom("conversation", 42)
+    ///     .build();
+    /// assert_eq!(n.custom.unwrap()["conversation"], 42);
+    /// ```
+    pub fn custom<K: Into<String>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+        self.notification.custom.get_or_insert_with(Map::new).insert(key.into(), value.into());
+        self
+    }
+
+    /// Send the `apns-*` request header `name` with `value`, for headers
+    /// APNS added after this crate was released. See
+    /// `Notification::extra_apns_headers`.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .alert("Hello")
+    ///     .extra_apns_header("apns-future-option", "1")
+    ///     .build();
+    /// assert!(n.validate_extra_apns_headers().is_ok());
+    /// let n = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .extra_apns_header("apns-topic", "com.example.other")
+    ///     .build();
+    /// assert!(n.validate_extra_apns_headers().is_err());
+    /// ```
+    pub fn extra_apns_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.notification.extra_apns_headers.get_or_insert_with(BTreeMap::new).insert(name.into(), value.into());
+        self
+    }
+
     pub fn build(self) -> Notification {
         self.notification
     }
+
+    /// Like `build`, but check the notification with
+    /// `Notification::validate` against the default payload limits first.
+    /// The error includes a preview of the payload.
+    ///
+    /// ```
+    /// # use apple_push::NotificationBuilder;
+    /// let error = NotificationBuilder::new("com.example.app", "device-token")
+    ///     .title("Hi")
+    ///     .body("x".repeat(5000))
+    ///     .try_build()
+    ///     .unwrap_err();
+    /// assert!(error.to_string().starts_with("payload is"));
+    /// assert!(error.preview.contains("\"title\": \"Hi\""));
+    /// ```
+    pub fn try_build(self) -> Result<Notification, BuildError> {
+        let n = self.notification;
+        match n.validate(&PayloadLimits::default()) {
+            Ok(()) => Ok(n),
+            Err(error) => Err(BuildError { preview: n.payload_preview(), error }),
+        }
+    }
+}
+
+/// Notifications shown together on the device in one thread, such as the
+/// messages of a chat or the updates of an order.
+///
+/// ```
+/// # use apple_push::NotificationGroup;
+/// let group = NotificationGroup::new("chat-42").summary_arg("Alice");
+/// let n = group.builder("com.example.app", "device-token").body("Hi!").summary_arg_count(3).build();
+/// let aps = serde_json::to_value(&n.payload).unwrap();
+/// assert_eq!(aps["thread-id"], "chat-42");
+/// assert_eq!(aps["alert"]["summary-arg"], "Alice");
+/// assert_eq!(aps["alert"]["summary-arg-count"], 3);
+/// assert_eq!(aps["alert"]["body"], "Hi!");
+/// ```
+#[derive(Clone, Debug)]
+pub struct NotificationGroup {
+    thread_id: String,
+    summary_arg: Option<String>,
+}
+
+impl NotificationGroup {
+    pub fn new<S: Into<String>>(thread_id: S) -> Self {
+        NotificationGroup {
+            thread_id: thread_id.into(),
+            summary_arg: None,
+        }
+    }
+
+    /// Name the group in the summary the device shows for it, such as the
+    /// sender in "3 more messages from Alice".
+    pub fn summary_arg<S: Into<String>>(mut self, summary_arg: S) -> Self {
+        self.summary_arg = Some(summary_arg.into());
+        self
+    }
+
+    /// A builder for a notification in the group. Set its text with
+    /// `title` and `body`: `alert` replaces the summary argument.
+    pub fn builder(&self, topic: &str, device_token: &str) -> NotificationBuilder {
+        let builder = NotificationBuilder::new(topic, device_token).thread_id(self.thread_id.clone());
+        match self.summary_arg {
+            Some(ref summary_arg) => builder.summary_arg(summary_arg.clone()),
+            None => builder,
+        }
+    }
 }
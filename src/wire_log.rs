@@ -0,0 +1,53 @@
+//! Trace events for every request and response, for debugging what goes
+//! over the wire without turning on the trace logs of hyper or h2. Provider
+//! tokens are never logged.
+
+use std::fmt;
+
+use bytes::Bytes;
+use hyper::{HeaderMap, Request, Response};
+
+use crate::consts;
+
+
+/// The target of the events, for filtering them on their own.
+const TARGET: &str = "apple_push::wire";
+
+/// Header map formatting that hides the provider token.
+struct Scrubbed<'a>(&'a HeaderMap);
+
+impl fmt::Debug for Scrubbed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(name, value)| {
+                let value = if name == consts::AUTHORIZATION {
+                    "<redacted>"
+                } else {
+                    value.to_str().unwrap_or("<binary>")
+                };
+                (name.as_str(), value)
+            }))
+            .finish()
+    }
+}
+
+pub(crate) fn request(req: &Request<Vec<u8>>) {
+    tracing::trace!(
+        target: TARGET,
+        method = %req.method(),
+        uri = %req.uri(),
+        headers = ?Scrubbed(req.headers()),
+        body = %String::from_utf8_lossy(req.body()),
+        "request",
+    );
+}
+
+pub(crate) fn response(res: &Response<Bytes>) {
+    tracing::trace!(
+        target: TARGET,
+        status = res.status().as_u16(),
+        headers = ?Scrubbed(res.headers()),
+        body = %String::from_utf8_lossy(res.body()),
+        "response",
+    );
+}
@@ -0,0 +1,50 @@
+//! Sends notifications through the gateway to the mock server.
+#![cfg(all(feature = "gateway", feature = "mock"))]
+
+use apple_push::gateway;
+use apple_push::mock::MockApnsServer;
+use apple_push::ApplePushClient;
+use hyper::{Body, Request};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+async fn post(client: &ApplePushClient<apple_push::mock::MockConnector>, body: &str) -> (u16, serde_json::Value) {
+    let req = Request::post(gateway::SEND_PATH).body(Body::from(body.to_owned())).unwrap();
+    let response = gateway::handle(client, req).await;
+    let status = response.status().as_u16();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn sends_and_reports_errors() {
+    let server = MockApnsServer::start().unwrap();
+    let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+    let http = hyper::Client::builder().http2_only(true).build(server.connector());
+    let client = ApplePushClient::new(http, "TEAMID1234", "KEYID12345", key.as_ref()).unwrap();
+
+    let token = "8b2f85d2a1e74ef1f1ab2e9a6a4f4e0f3b0d2c6e5a7f9b1c3d5e7f9a1b3c5d7e";
+    let (status, body) = post(&client, &format!(r#"{{"topic":"com.example.app","device_token":"{}","payload":{{"alert":"Hello"}}}}"#, token)).await;
+    assert_eq!(status, 200);
+    assert!(body["id"].is_string());
+
+    let (status, body) = post(&client, r#"{"topic":"com.example.app","device_token":"nope","payload":{"alert":"Hello"}}"#).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["reason"], "BadDeviceToken");
+
+    let (status, _) = post(&client, "not json").await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn oversize_bodies_are_refused() {
+    let server = MockApnsServer::start().unwrap();
+    let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+    let http = hyper::Client::builder().http2_only(true).build(server.connector());
+    let client = ApplePushClient::new(http, "TEAMID1234", "KEYID12345", key.as_ref()).unwrap();
+
+    let (status, body) = post(&client, &" ".repeat(gateway::MAX_BODY_SIZE + 1)).await;
+    assert_eq!(status, 413);
+    assert!(body["error"].is_string());
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,946 @@
+//! Checks the requests the client sends against the rules of APNS, using the
+//! mock server.
+#![cfg(feature = "mock")]
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+
+use apple_push::mock::{MockApnsServer, MockConnector, MockResponse};
+use apple_push::metrics::Metrics;
+use apple_push::{AdaptiveConcurrency, ApiErrorReason, ApplePushClient, CloseReason, CollapseId, DeterministicRng, Event, ExpirationPolicy, FollowUp, RetryPolicy, Environment, PayloadLimits, PayloadMiddleware, Policy, NotificationBuilder, Priority, PushType, ReasonClass, SanitizePolicy, SendError, SendOptions, SendRate, ThrottlePolicy, TopicDefaults, TrimPolicy, ValidationError, Verdict};
+#[cfg(feature = "unstable-correlation")]
+use apple_push::{CorrelationStore, MemoryCorrelationStore};
+#[cfg(feature = "unstable-payload-signing")]
+use apple_push::HmacSigner;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+const TOPIC: &str = "com.example.app";
+const DEVICE_TOKEN: &str = "8b2f85d2a1e74ef1f1ab2e9a6a4f4e0f3b0d2c6e5a7f9b1c3d5e7f9a1b3c5d7e";
+
+fn client(server: &MockApnsServer) -> ApplePushClient<MockConnector> {
+    let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+    let http = hyper::Client::builder().http2_only(true).build(server.connector());
+    ApplePushClient::new(http, "TEAMID1234", "KEYID12345", key.as_ref()).unwrap()
+}
+
+#[tokio::test]
+async fn requests_conform() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN)
+        .title("Hello")
+        .body("World")
+        .badge(3)
+        .expiration(2_000_000_000)
+        .priority(Priority::Low)
+        .collapse_id(CollapseId::new("greeting").unwrap())
+        .build();
+    client.send(n).await.unwrap();
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn push_types_are_checked_before_sending() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).content_available().build();
+    client.send(n).await.unwrap();
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").push_type(PushType::Voip).build();
+    assert!(matches!(client.send(n).await, Err(SendError::Validation(ValidationError::PushType { push_type: PushType::Voip, .. }))));
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn live_activity_updates_conform() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let state = serde_json::json!({ "home": 1, "away": 0 });
+    let n = apple_push::live_activity::update(TOPIC, DEVICE_TOKEN, &state).unwrap().build();
+    client.send(n).await.unwrap();
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn violations_are_reported() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").build();
+    match client.send(n).await {
+        Err(SendError::Api(e)) => assert!(matches!(e.reason, ApiErrorReason::BadDeviceToken)),
+        other => panic!("expected BadDeviceToken, got {:?}", other),
+    }
+    let violations = server.violations();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].device_token, "not-a-token");
+    assert_eq!(violations[0].status, 400);
+}
+
+#[tokio::test]
+async fn custom_keys_cant_replace_aps() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").custom("conversation", 42).build();
+    client.send(n).await.unwrap();
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").custom("aps", "oops").build();
+    assert!(matches!(client.send(n).await, Err(SendError::Validation(ValidationError::ReservedKey { .. }))));
+    server.assert_conforming();
+}
+
+struct Truncate;
+
+impl PayloadMiddleware for Truncate {
+    fn on_payload(&self, _: &str, payload: &mut Vec<u8>) -> Result<(), apple_push::Error> {
+        payload.truncate(1);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn payload_middleware_sees_the_serialized_payload() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.add_payload_middleware(std::sync::Arc::new(Truncate));
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build();
+    assert!(client.send(n).await.is_err());
+    assert_eq!(server.violations()[0].status, 400);
+}
+
+struct OptOut(String);
+
+impl Policy for OptOut {
+    fn check<'a>(&'a self, n: &'a mut apple_push::Notification) -> future::BoxFuture<'a, Result<Verdict, apple_push::Error>> {
+        let verdict = if n.device_token == self.0 {
+            Verdict::Veto("opted out".to_owned())
+        } else {
+            std::sync::Arc::make_mut(&mut n.payload).sound = None;
+            Verdict::Allow
+        };
+        Box::pin(future::ok(verdict))
+    }
+}
+
+#[tokio::test]
+async fn policies_can_change_or_veto_notifications() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let opted_out = "ab".repeat(32);
+    client.add_policy(std::sync::Arc::new(OptOut(opted_out.clone())));
+
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").sound("chime").build()).await.unwrap();
+    assert_eq!(server.requests()[0].json()["aps"].get("sound"), None);
+
+    let error = client.send(NotificationBuilder::new(TOPIC, &opted_out).alert("Hello").build()).await.unwrap_err();
+    assert!(matches!(error, SendError::Vetoed(ref reason) if reason == "opted out"));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn oversize_bodies_are_truncated_to_fit() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_trim_policy(TrimPolicy::TruncateBody);
+    let body = "\u{e9}".repeat(3000);
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).title("Hello").body(body.as_str()).build()).await.unwrap();
+
+    let request = &server.requests()[0];
+    assert!(request.body.len() <= apple_push::MAX_PAYLOAD_SIZE);
+    assert!(request.body.len() > apple_push::MAX_PAYLOAD_SIZE - 8, "trimmed more than needed");
+    let sent = request.json()["aps"]["alert"]["body"].as_str().unwrap().to_owned();
+    let kept = sent.strip_suffix('\u{2026}').unwrap();
+    assert!(!kept.is_empty() && body.starts_with(kept));
+    assert_eq!(request.json()["aps"]["alert"]["title"], "Hello");
+}
+
+#[tokio::test]
+async fn bodies_are_only_truncated_when_that_is_enough() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let oversize = || NotificationBuilder::new(TOPIC, DEVICE_TOKEN).body("x".repeat(5000)).build();
+    let too_large = |result: Result<_, SendError>| matches!(result, Err(SendError::Validation(ValidationError::PayloadTooLarge { .. })));
+
+    assert!(too_large(client.send(oversize()).await));
+
+    client.set_trim_policy(TrimPolicy::TruncateBody);
+    let mut n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).body("Hello").custom("data", "x".repeat(5000)).build();
+    assert!(too_large(client.send(n.clone()).await));
+    n.custom = None;
+    client.send(n).await.unwrap();
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(server.requests()[0].json()["aps"]["alert"]["body"], "Hello");
+}
+
+#[tokio::test]
+async fn control_characters_are_stripped_or_refused() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let n = || NotificationBuilder::new(TOPIC, DEVICE_TOKEN).title("Hel\u{7}lo").body("line\none\u{0}").build();
+
+    client.set_sanitize_policy(SanitizePolicy::Strip);
+    client.send(n()).await.unwrap();
+    let alert = &server.requests()[0].json()["aps"]["alert"];
+    assert_eq!(alert["title"], "Hello");
+    assert_eq!(alert["body"], "line\none");
+
+    client.set_sanitize_policy(SanitizePolicy::Reject);
+    let error = client.send(n()).await.unwrap_err();
+    assert!(matches!(error, SendError::Validation(ValidationError::ControlCharacter { field: "title", character: '\u{7}' })));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn throttled_topics_send_at_low_priority_until_they_recover() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let ms = std::time::Duration::from_millis;
+    client.set_throttle_policy(Some(ThrottlePolicy { cooldown: ms(100), spacing: ms(1) }));
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build());
+
+    server.respond(MockResponse::too_many_requests(None));
+    assert!(send().await.is_err());
+    send().await.unwrap();
+    tokio::time::delay_for(ms(150)).await;
+    send().await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("apns-priority"), None);
+    assert_eq!(requests[1].header("apns-priority"), Some("5"));
+    assert_eq!(requests[2].header("apns-priority"), None);
+}
+
+#[tokio::test]
+async fn long_responses_are_dropped() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_response_body_limit(8);
+    let n = NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").build();
+    assert!(matches!(client.send(n).await, Err(SendError::ResponseTooLarge { limit: 8 })));
+}
+
+#[tokio::test]
+async fn sink_sends_everything_forwarded() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let notifications = vec![
+        NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("one").build(),
+        NotificationBuilder::new(TOPIC, "not-a-token").alert("two").build(),
+        NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("three").build(),
+    ];
+    let (sink, results) = client.sink_with_results(2);
+    stream::iter(notifications).map(Ok).forward(sink).await.unwrap();
+    let results: Vec<_> = results.collect().await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.iter().filter(|(_, result)| result.is_ok()).count(), 2);
+}
+
+#[tokio::test]
+async fn scoped_batch_reports_in_order() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = vec![DEVICE_TOKEN.to_owned(), "not-a-token".to_owned(), DEVICE_TOKEN.to_owned()];
+    let report = client.send_batch_scoped(template, tokens, SendOptions::new().concurrency(2)).await;
+    assert_eq!((report.sent(), report.failed()), (2, 1));
+    assert_eq!(report.results[1].0, "not-a-token");
+}
+
+#[tokio::test]
+async fn concurrent_batch_reports_each_token() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = vec!["not-a-token".to_owned(), DEVICE_TOKEN.to_owned(), DEVICE_TOKEN.to_owned()];
+    let results: Vec<_> = client.send_batch(template, tokens, SendOptions::new().concurrency(3)).collect().await;
+    assert_eq!(results[0].0, "not-a-token");
+    assert!(matches!(results[0].1, Err(SendError::Api(ref e)) if matches!(e.reason, ApiErrorReason::BadDeviceToken)));
+    assert!(results[1..].iter().all(|(_, result)| result.is_ok()));
+}
+
+#[tokio::test]
+async fn shutdown_drains_fired_notifications() {
+    let server = MockApnsServer::start().unwrap();
+    let client = std::sync::Arc::new(client(&server));
+    assert!(client.fire(NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").build()));
+    assert_eq!(client.background_task_count(), 1);
+    client.shutdown().await;
+    assert_eq!(client.background_task_count(), 0);
+    assert_eq!(server.violations().len(), 1);
+}
+
+#[tokio::test]
+async fn certificate_auth_sends_no_provider_token() {
+    let server = MockApnsServer::start().unwrap();
+    let http = hyper::Client::builder().http2_only(true).build(server.connector());
+    let client = ApplePushClient::new_certificate_auth(http);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build();
+    // The mock server has no TLS, so it can't see the certificate and
+    // refuses the request for lacking a token.
+    match client.send(n).await {
+        Err(SendError::Api(e)) => assert!(matches!(e.reason, ApiErrorReason::MissingProviderToken)),
+        other => panic!("expected MissingProviderToken, got {:?}", other),
+    }
+    assert!(client.token_info().is_none());
+}
+
+#[tokio::test]
+async fn environment_is_detected() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    assert_eq!(client.detect_environment(TOPIC, DEVICE_TOKEN).await.unwrap(), Some(Environment::Production));
+    assert_eq!(client.detect_environment(TOPIC, "not-a-token").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn presets_are_valid_notifications() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let presets = vec![
+        NotificationBuilder::message(TOPIC, DEVICE_TOKEN, "Hello", "World"),
+        NotificationBuilder::badge_only(TOPIC, DEVICE_TOKEN, 3),
+        NotificationBuilder::silent_refresh(TOPIC, DEVICE_TOKEN),
+        NotificationBuilder::critical_alert(TOPIC, DEVICE_TOKEN, "Smoke detected", "Kitchen"),
+    ];
+    for n in presets {
+        client.send(n.build()).await.unwrap();
+    }
+    server.assert_conforming();
+}
+
+#[cfg(feature = "h2")]
+#[tokio::test]
+async fn h2_pool_opens_up_to_its_size() {
+    let server = MockApnsServer::start().unwrap();
+    let key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+    let options = apple_push::H2Options { pool_size: 2, ..Default::default() };
+    let client = ApplePushClient::new_h2(server.connector(), options, "TEAMID1234", "KEYID12345", key.as_ref()).unwrap();
+    for _ in 0..4 {
+        client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    }
+    assert_eq!(client.connection_count().await, Some(2));
+    client.shutdown().await;
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn bad_device_tokens_fall_back_to_the_sandbox() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_sandbox_fallback(true);
+    let n = NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").build();
+    let error = client.send(n).await.unwrap_err();
+    assert!(error.is_bad_device_token());
+    // Refused by both environments, the token can't be valid.
+    assert!(error.should_remove_token());
+    let violations = server.violations();
+    assert_eq!(violations.len(), 2);
+    assert_eq!(violations[0].apns_id, violations[1].apns_id);
+
+    let n = NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").environment(Environment::Sandbox).build();
+    let error = client.send(n).await.unwrap_err();
+    assert!(error.is_bad_device_token());
+    // It may be a token of the other environment.
+    assert!(!error.should_remove_token());
+    assert_eq!(server.violations().len(), 3);
+}
+
+#[tokio::test]
+async fn provider_tokens_are_backdated() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_token_backdate(std::time::Duration::from_secs(30));
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    let info = client.token_info().unwrap();
+    assert!(info.issued_at.elapsed().unwrap() >= std::time::Duration::from_secs(30));
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn send_rate_spaces_out_sends() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_send_rate(Some(SendRate::new(20.0, 1)));
+    let started = std::time::Instant::now();
+    for _ in 0..3 {
+        client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    }
+    assert!(started.elapsed() >= std::time::Duration::from_millis(90));
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn scripted_responses_answer_conforming_requests() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    server.respond(MockResponse::unregistered(1_600_000_000_000));
+    server.respond(MockResponse::too_many_requests(Some(3)));
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).title("Hello").build());
+    match send().await {
+        Err(SendError::Api(e)) => {
+            assert!(matches!(e.reason, ApiErrorReason::Unregistered));
+            assert_eq!(e.timestamp, Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000)));
+        }
+        other => panic!("expected Unregistered, got {:?}", other),
+    }
+    match send().await {
+        Err(SendError::Api(e)) => assert_eq!((e.status, e.retry_after), (429, Some(std::time::Duration::from_secs(3)))),
+        other => panic!("expected TooManyRequests, got {:?}", other),
+    }
+    send().await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].device_token, DEVICE_TOKEN);
+    assert_eq!(requests[0].header("apns-topic"), Some(TOPIC));
+    assert_eq!(requests[0].json()["aps"]["alert"]["title"], "Hello");
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn topic_defaults_fill_in_what_notifications_leave_out() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let mut defaults = TopicDefaults::default();
+    defaults.payload.sound = Some("chime.caf".into());
+    defaults.custom.insert("app".to_owned(), "chat".into());
+    client.set_topic_defaults(TOPIC, defaults);
+
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).title("Hello").build()).await.unwrap();
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).title("Hello").sound("ping.caf").build()).await.unwrap();
+    client.send(NotificationBuilder::silent_refresh(TOPIC, DEVICE_TOKEN).build()).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].json()["aps"]["sound"], "chime.caf");
+    assert_eq!(requests[0].json()["app"], "chat");
+    assert_eq!(requests[1].json()["aps"]["sound"], "ping.caf");
+    assert!(requests[2].json()["aps"].get("sound").is_none());
+    assert_eq!(requests[2].json()["app"], "chat");
+    server.assert_conforming();
+}
+
+#[derive(Default)]
+struct Recorded(std::sync::Mutex<Vec<(&'static str, f64, Vec<String>)>>);
+
+impl Metrics for Recorded {
+    fn counter(&self, name: &'static str, value: u64) {
+        self.0.lock().unwrap().push((name, value as f64, Vec::new()));
+    }
+
+    fn tagged_counter(&self, name: &'static str, value: u64, tags: &[(&'static str, &str)]) {
+        self.histogram(name, value as f64, tags);
+    }
+
+    fn histogram(&self, name: &'static str, value: f64, tags: &[(&'static str, &str)]) {
+        let tags = tags.iter().map(|(tag, value)| format!("{}={}", tag, value)).collect();
+        self.0.lock().unwrap().push((name, value, tags));
+    }
+}
+
+#[tokio::test]
+async fn sends_are_measured() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let metrics = std::sync::Arc::new(Recorded::default());
+    client.set_metrics(metrics.clone());
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    assert!(client.send(NotificationBuilder::new(TOPIC, "not-a-token").alert("Hello").build()).await.is_err());
+
+    let recorded = metrics.0.lock().unwrap();
+    let named = |name| recorded.iter().filter(|(n, _, _)| *n == name).collect::<Vec<_>>();
+    assert_eq!(named("jwt.signed").len(), 1);
+    assert_eq!(named("send.latency_ms").len(), 2);
+    let sizes = named("send.payload_bytes");
+    assert!(sizes.iter().all(|(_, size, _)| *size > 0.0));
+    let errors = named("send.api_error");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].2.contains(&"reason=BadDeviceToken".to_owned()));
+}
+
+#[tokio::test]
+async fn frequent_token_signing_is_reported() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let metrics = std::sync::Arc::new(Recorded::default());
+    client.set_metrics(metrics.clone());
+    let mut events = client.events();
+    for _ in 0..4 {
+        client.invalidate_token();
+        client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    }
+    assert_eq!(client.token_signings_last_hour(), 4);
+    match events.try_recv() {
+        Ok(Event::FrequentTokenSigning { kid, last_hour }) => assert_eq!((kid.as_str(), last_hour), ("KEYID12345", 4)),
+        other => panic!("expected FrequentTokenSigning, got {:?}", other),
+    }
+    let recorded = metrics.0.lock().unwrap();
+    let last_hour: Vec<_> = recorded.iter().filter(|(n, _, _)| *n == "jwt.signed_last_hour").map(|(_, value, _)| *value).collect();
+    assert_eq!(last_hour, vec![1.0, 2.0, 3.0, 4.0]);
+}
+
+#[tokio::test]
+async fn too_many_token_updates_stops_signing() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let mut events = client.events();
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build());
+    send().await.unwrap();
+    server.respond(MockResponse::error(429, ApiErrorReason::TooManyProviderTokenUpdates));
+    assert!(send().await.is_err());
+    match events.try_recv() {
+        Ok(Event::TokenCoolDown { kid, .. }) => assert_eq!(kid, "KEYID12345"),
+        other => panic!("expected TokenCoolDown, got {:?}", other),
+    }
+    client.invalidate_token();
+    send().await.unwrap();
+    assert_eq!(client.token_signings_last_hour(), 1);
+    let requests = server.requests();
+    assert_eq!(requests[0].header("authorization"), requests[2].header("authorization"));
+}
+
+#[tokio::test]
+async fn unsuitable_expirations_are_reported_or_refused() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let mut events = client.events();
+    let call = || NotificationBuilder::new("com.example.app.voip", DEVICE_TOKEN)
+        .push_type(PushType::Voip)
+        .ttl(std::time::Duration::from_secs(3600))
+        .build();
+    client.send(call()).await.unwrap();
+    match events.try_recv() {
+        Ok(Event::UnsuitableExpiration { push_type, .. }) => assert_eq!(push_type, PushType::Voip),
+        other => panic!("expected UnsuitableExpiration, got {:?}", other),
+    }
+
+    client.set_expiration_policy(ExpirationPolicy::Reject);
+    match client.send(call()).await {
+        Err(SendError::Validation(ValidationError::Expiration { push_type: PushType::Voip, .. })) => (),
+        other => panic!("expected an expiration error, got {:?}", other),
+    }
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn queue_backlogs_are_reported() {
+    let server = MockApnsServer::start().unwrap();
+    let client = std::sync::Arc::new(client(&server));
+    for _ in 0..3 {
+        assert!(client.fire(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()));
+    }
+    // The worker hasn't had a turn yet.
+    let stats = client.fire_queue_stats();
+    assert_eq!(stats.depth, 3);
+    assert!(stats.oldest_age.is_some());
+    client.shutdown().await;
+    assert_eq!(client.fire_queue_stats(), apple_push::QueueStats::default());
+    assert_eq!(server.requests().len(), 3);
+
+    use apple_push::scheduler::{MemoryScheduleStore, ScheduleStore};
+    let store = MemoryScheduleStore::new();
+    let now = std::time::SystemTime::now();
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Later").build();
+    store.insert(now - std::time::Duration::from_secs(60), &n).unwrap();
+    store.insert(now - std::time::Duration::from_secs(5), &n).unwrap();
+    store.insert(now + std::time::Duration::from_secs(60), &n).unwrap();
+    let backlog = store.backlog(now).unwrap();
+    assert_eq!((backlog.depth, backlog.oldest_age), (2, Some(std::time::Duration::from_secs(60))));
+}
+
+#[tokio::test]
+async fn scheduled_notifications_that_keep_failing_are_given_up() {
+    use apple_push::scheduler::{MemoryScheduleStore, Scheduler};
+    let server = MockApnsServer::start().unwrap();
+    let store = std::sync::Arc::new(MemoryScheduleStore::new());
+    let mut scheduler = Scheduler::new(std::sync::Arc::new(client(&server)), store);
+    scheduler.max_attempts = 2;
+    let due = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    scheduler.schedule(&NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Later").build(), due).unwrap();
+    server.respond(MockResponse::error(503, ApiErrorReason::ServiceUnavailable));
+    server.respond(MockResponse::error(503, ApiErrorReason::ServiceUnavailable));
+
+    assert!(scheduler.send_due().await.unwrap()[0].1.is_err());
+    assert_eq!(scheduler.backlog().unwrap().depth, 1);
+    assert!(scheduler.send_due().await.unwrap()[0].1.is_err());
+    assert_eq!(scheduler.backlog().unwrap().depth, 0);
+}
+
+#[tokio::test]
+async fn scheduler_backs_off_during_outages() {
+    use apple_push::scheduler::{MemoryScheduleStore, Scheduler};
+    let server = MockApnsServer::start().unwrap();
+    let store = std::sync::Arc::new(MemoryScheduleStore::new());
+    let mut scheduler = Scheduler::new(std::sync::Arc::new(client(&server)), store);
+    scheduler.batch_size = 1;
+    scheduler.max_attempts = u32::MAX;
+    let due = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    scheduler.schedule(&NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Later").build(), due).unwrap();
+    for _ in 0..100 {
+        server.respond(MockResponse::error(503, ApiErrorReason::ServiceUnavailable));
+    }
+
+    let run = scheduler.run(std::time::Duration::from_millis(10));
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(200), run).await.is_err());
+    // Waiting 10, 20, 40 and 80ms between attempts.
+    let attempts = server.requests().len();
+    assert!((3..=6).contains(&attempts), "{} attempts", attempts);
+}
+
+#[tokio::test]
+async fn cancelled_adaptive_batches_resume_after_what_was_sent() {
+    use apple_push::{BatchReport, CancellationToken};
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let tokens: Vec<_> = (0..40).map(|i| format!("{:064x}", i)).collect();
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let cancellation = CancellationToken::new();
+    let cancel = cancellation.clone();
+    let opts = SendOptions::new()
+        // Starting low, so that most sends wait their turn under the limit.
+        .adaptive_concurrency(AdaptiveConcurrency { initial: 1, ..AdaptiveConcurrency::new(8) })
+        .cancellation(cancellation)
+        .on_progress(move |done, _, _| if done == 5 { cancel.cancel() });
+
+    let report = BatchReport::collect(client.send_batch(template.clone(), tokens.clone(), opts)).await;
+    let resume = report.resume_token.unwrap();
+    let mut sent: Vec<_> = server.requests().into_iter().map(|r| r.device_token).collect();
+    sent.sort();
+    assert_eq!(sent, tokens[..resume.position()]);
+
+    let report = BatchReport::collect(client.send_batch(template, tokens.clone(), SendOptions::new().resume_from(resume))).await;
+    assert!(report.results.iter().all(|(_, result)| result.is_ok()));
+    let mut sent: Vec<_> = server.requests().into_iter().map(|r| r.device_token).collect();
+    sent.sort();
+    assert_eq!(sent, tokens);
+}
+
+#[tokio::test]
+async fn expired_notifications_are_dropped() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let metrics = std::sync::Arc::new(Recorded::default());
+    client.set_metrics(metrics.clone());
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Too late").expiration(now - 30).build();
+    match client.send(n).await {
+        Err(SendError::Expired { expiration, late_by }) => assert!(expiration == now - 30 && late_by >= 30),
+        other => panic!("expected Expired, got {:?}", other),
+    }
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Now or never").expiration(0).build();
+    client.send(n).await.unwrap();
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(metrics.0.lock().unwrap().iter().filter(|(n, _, _)| *n == "send.expired").count(), 1);
+}
+
+#[tokio::test]
+async fn sandbox_sends_link_to_the_delivery_log() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build();
+    let response = client.send_detailed(n).await.unwrap();
+    assert!(response.unique_id.is_none());
+
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").environment(Environment::Sandbox).build();
+    let response = client.send_detailed(n).await.unwrap();
+    let url = client.console_url(TOPIC, &response).unwrap();
+    assert!(url.contains("/teams/TEAMID1234/app/com.example.app/"));
+    assert!(url.ends_with(response.unique_id.as_ref().unwrap()));
+}
+
+#[cfg(feature = "unstable-correlation")]
+#[tokio::test]
+async fn correlated_sends_can_be_looked_up_by_apns_id() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let store = std::sync::Arc::new(MemoryCorrelationStore::new(std::time::Duration::from_secs(60)));
+    client.set_correlation_store(Some(store.clone()));
+    let id = client.send_correlated(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build(), serde_json::json!({ "order": 1 })).await.unwrap();
+    assert_eq!(server.requests()[0].header("apns-id"), Some(id.to_string().as_str()));
+    assert_eq!(store.get(&id).await.unwrap().unwrap()["order"], 1);
+
+    // Failed sends are recorded too.
+    server.respond(MockResponse::unregistered(1_600_000_000_000));
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build();
+    assert!(client.send_correlated(n, serde_json::json!({ "order": 2 })).await.is_err());
+    let id = server.requests()[1].header("apns-id").unwrap().parse().unwrap();
+    assert_eq!(store.get(&id).await.unwrap().unwrap()["order"], 2);
+}
+
+#[tokio::test]
+async fn extra_apns_headers_are_sent_as_they_are() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").extra_apns_header("apns-future-option", "on").build();
+    client.send(n).await.unwrap();
+    assert_eq!(server.requests()[0].header("apns-future-option"), Some("on"));
+
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").extra_apns_header("x-debug", "1").build();
+    assert!(matches!(client.send(n).await, Err(SendError::Validation(ValidationError::ExtraHeader { .. }))));
+    assert_eq!(server.requests().len(), 1);
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn paced_batches_spread_their_sends() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = vec![DEVICE_TOKEN.to_owned(); 3];
+    let started = std::time::Instant::now();
+    let report = client.send_batch_scoped(template, tokens, SendOptions::new().concurrency(3).pace(20.0)).await;
+    assert_eq!(report.sent(), 3);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(90));
+    server.assert_conforming();
+}
+
+#[tokio::test]
+async fn batch_progress_is_reported_per_result() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = vec![DEVICE_TOKEN.to_owned(), "not-a-token".to_owned(), DEVICE_TOKEN.to_owned()];
+    let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = reported.clone();
+    let opts = SendOptions::new().concurrency(2).on_progress(move |done, total, result| {
+        recorded.lock().unwrap().push((done, total, result.is_ok()));
+    });
+    client.send_batch_scoped(template, tokens, opts).await;
+    assert_eq!(*reported.lock().unwrap(), vec![(1, 3, true), (2, 3, false), (3, 3, true)]);
+}
+
+#[tokio::test]
+async fn streamed_tokens_are_taken_as_they_are_sent() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let taken = std::sync::atomic::AtomicUsize::new(0);
+    let tokens = stream::repeat(DEVICE_TOKEN.to_owned()).take(1000).inspect(|_| {
+        taken.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    });
+    let mut results = client.send_batch_stream(template, tokens, SendOptions::new().concurrency(2));
+    for _ in 0..3 {
+        let (token, result) = results.next().await.unwrap();
+        assert_eq!(token, DEVICE_TOKEN);
+        result.unwrap();
+    }
+    assert!(taken.load(std::sync::atomic::Ordering::SeqCst) <= 5);
+}
+
+#[tokio::test]
+async fn duplicate_tokens_can_be_skipped() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens: Vec<String> = ["a", "b", "a", "c", "b", "a"].iter().map(|t| t.repeat(64)).collect();
+
+    let mut batch = client.send_batch(template.clone(), tokens.clone(), SendOptions::new().deduplicate());
+    let sent = batch.by_ref().count().await;
+    assert_eq!(sent, 3);
+    assert_eq!(batch.duplicates_skipped(), 3);
+    assert_eq!(server.requests().len(), 3);
+
+    let sent = client.send_batch(template, tokens, SendOptions::new()).count().await;
+    assert_eq!(sent, 6);
+}
+
+#[tokio::test]
+async fn invalid_batch_tokens_are_rejected_before_sending() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = vec!["a".repeat(64), "not a token".to_owned(), "b".repeat(64), "abc".to_owned()];
+
+    let report = client.send_batch_checked(template, tokens, SendOptions::new().concurrency(2)).await;
+    assert_eq!(report.sent(), 2);
+    assert_eq!(server.requests().len(), 2);
+    let rejected: Vec<_> = report.rejected.iter().map(|(token, _)| token.as_str()).collect();
+    assert_eq!(rejected, ["not a token", "abc"]);
+    assert!(matches!(report.rejected[1].1, ValidationError::DeviceToken { .. }));
+}
+
+#[tokio::test]
+async fn batch_outcomes_export_as_csv() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    server.respond(MockResponse::error(400, ApiErrorReason::BadDeviceToken));
+    let report = client.send_batch_scoped(template, vec![DEVICE_TOKEN.to_owned(); 3], SendOptions::new()).await;
+    assert_eq!(report.latencies.len(), 3);
+
+    let mut csv = Vec::new();
+    report.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().skip(1).map(|row| row.split(',').collect()).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0][1..3], ["400", "BadDeviceToken"]);
+    assert_eq!(rows[1][1..3], ["200", ""]);
+    assert_eq!(rows[0][0], rows[1][0]);
+    assert_eq!(rows[0][0].len(), 64);
+    assert!(rows[2][3].parse::<f64>().is_ok());
+}
+
+#[tokio::test]
+async fn adaptive_batches_grow_until_apns_pushes_back() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let template = NotificationBuilder::new(TOPIC, "").alert("Hello").build();
+    let tokens = || vec![DEVICE_TOKEN.to_owned(); 40];
+
+    let policy = AdaptiveConcurrency { initial: 2, ..AdaptiveConcurrency::new(16) };
+    let mut batch = client.send_batch(template.clone(), tokens(), SendOptions::new().adaptive_concurrency(policy));
+    while let Some((_, result)) = batch.next().await {
+        result.unwrap();
+    }
+    assert!(batch.concurrency().unwrap() > 2);
+
+    server.respond(MockResponse::too_many_requests(None));
+    let policy = AdaptiveConcurrency { initial: 8, ..AdaptiveConcurrency::new(16) };
+    let mut batch = client.send_batch(template, tokens().into_iter().take(10), SendOptions::new().adaptive_concurrency(policy));
+    let failed = batch.by_ref().filter(|(_, result)| future::ready(result.is_err())).count().await;
+    assert_eq!(failed, 1);
+    assert!(batch.concurrency().unwrap() < 8);
+}
+
+#[cfg(feature = "unstable-payload-signing")]
+#[tokio::test]
+async fn custom_data_is_signed_for_the_app() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_payload_signer(Some(std::sync::Arc::new(HmacSigner::new(b"shared secret"))));
+    let n = NotificationBuilder::new(TOPIC, DEVICE_TOKEN)
+        .alert("Hello")
+        .custom("order", 1234)
+        .custom("items", serde_json::json!([{ "sku": "A1", "count": 2 }]))
+        .custom("sig", "forged")
+        .custom("customer", serde_json::json!({ "id": 7, "country": "NZ" }))
+        .custom("app", "shop")
+        .build();
+    client.send(n).await.unwrap();
+
+    let mut body = server.requests()[0].json();
+    let custom = body.as_object_mut().unwrap();
+    custom.remove("aps");
+    let signature = base64::decode(custom.remove("sig").unwrap().as_str().unwrap()).unwrap();
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"shared secret");
+    let signed = br#"{"app":"shop","customer":{"country":"NZ","id":7},"items":[{"count":2,"sku":"A1"}],"order":1234}"#;
+    ring::hmac::verify(&key, signed, &signature).unwrap();
+}
+
+#[tokio::test]
+async fn unknown_reasons_are_classified_by_the_callback() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_retry_policy(Some(RetryPolicy { initial_backoff: std::time::Duration::from_millis(10), ..Default::default() }));
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build());
+
+    server.respond(MockResponse::error(500, ApiErrorReason::Other("DeviceTokenRevoked".to_owned())));
+    server.respond(MockResponse::error(500, ApiErrorReason::Other("DeviceTokenRevoked".to_owned())));
+    send().await.unwrap();
+
+    client.set_reason_classifier(|reason, _| match reason {
+        "DeviceTokenRevoked" => Some(ReasonClass { retriable: false, remove_token: true }),
+        _ => None,
+    });
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build());
+    server.respond(MockResponse::error(500, ApiErrorReason::Other("DeviceTokenRevoked".to_owned())));
+    let error = send().await.unwrap_err();
+    assert_eq!(error.attempts().len(), 1);
+    assert!(!error.is_retriable());
+    assert!(error.should_remove_token());
+}
+
+#[tokio::test]
+async fn badge_follows_up_an_accepted_alert() {
+    let server = MockApnsServer::start().unwrap();
+    let client = client(&server);
+    let alert = || NotificationBuilder::message(TOPIC, DEVICE_TOKEN, "New message", "Hi!").build();
+
+    let report = client.send_with_follow_up(alert(), FollowUp::Badge(3)).await;
+    assert!(report.is_ok());
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].json()["aps"], serde_json::json!({ "badge": 3 }));
+    assert_eq!(requests[1].header("apns-priority"), Some("5"));
+
+    server.respond(MockResponse::error(400, ApiErrorReason::BadTopic));
+    let report = client.send_with_follow_up(alert(), FollowUp::Sync).await;
+    assert!(report.error().unwrap().as_api_error().is_some());
+    assert!(report.follow_up.is_none());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn config_reflects_the_settings() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_production(false);
+    client.set_send_rate(Some(SendRate::new(100.0, 10)));
+    client.set_concurrency_limit(Some(8));
+    let config = client.config();
+    assert_eq!(config.environment, Environment::Sandbox);
+    assert_eq!(config.endpoint, apple_push::APN_URL_DEV);
+    assert_eq!(config.key_id.as_deref(), Some("KEYID12345"));
+    assert_eq!(config.send_rate.unwrap().burst(), 10);
+    assert_eq!(config.concurrency_limit, Some(8));
+    assert!(config.retry_policy.is_none());
+}
+
+#[tokio::test]
+async fn shutdown_fails_waiting_sends() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_send_rate(Some(SendRate::new(0.5, 1)));
+    let send = || client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build());
+    send().await.unwrap();
+    let (waiting, ()) = futures::join!(send(), async {
+        tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+        client.shutdown().await;
+    });
+    assert!(matches!(waiting, Err(SendError::ClientClosed(CloseReason::Shutdown))));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn payload_limits_apply_by_push_type() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    client.set_payload_limits(PayloadLimits::new().push_type(PushType::Mdm, 64));
+    let n = |push_type| NotificationBuilder::new(TOPIC, DEVICE_TOKEN).push_type(push_type).custom("mdm", "x".repeat(64)).build();
+
+    let error = client.send(n(PushType::Mdm)).await.unwrap_err();
+    assert!(matches!(error, SendError::Validation(ValidationError::PayloadTooLarge { limit: 64, .. })));
+    client.send(n(PushType::Alert)).await.unwrap();
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn rate_limited_sends_are_retried_once_the_limit_allows() {
+    let server = MockApnsServer::start().unwrap();
+    let mut client = client(&server);
+    let ms = std::time::Duration::from_millis;
+    let mut rate = SendRate::new(1000.0, 10);
+    rate.backoff = ms(10);
+    rate.device_backoff = ms(100);
+    client.set_send_rate(Some(rate));
+    client.set_retry_policy(Some(RetryPolicy { initial_backoff: ms(10), ..Default::default() }));
+    server.respond(MockResponse::too_many_requests(None));
+
+    client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap();
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn seeded_retry_jitter_is_repeatable() {
+    let server = MockApnsServer::start().unwrap();
+    let backoffs = || async {
+        let mut client = client(&server);
+        client.set_rng(std::sync::Arc::new(DeterministicRng::new(42)));
+        client.set_retry_policy(Some(RetryPolicy { jitter: 0.5, initial_backoff: std::time::Duration::from_millis(10), ..Default::default() }));
+        for _ in 0..3 {
+            server.respond(MockResponse::error(500, ApiErrorReason::InternalServerError));
+        }
+        let error = client.send(NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").build()).await.unwrap_err();
+        error.attempts().iter().map(|attempt| attempt.backoff).collect::<Vec<_>>()
+    };
+    let first = backoffs().await;
+    assert_eq!(first.len(), 3);
+    assert_eq!(first, backoffs().await);
+}
@@ -0,0 +1,74 @@
+//! End-to-end tests against the APNS development sandbox.
+//!
+//! These are ignored by default since they need real credentials. To run them:
+//!
+//! ```sh
+//! APNS_TEAM_ID=... APNS_KEY_ID=... APNS_KEY_PATH=AuthKey.p8 \
+//! APNS_TOPIC=com.example.app APNS_DEVICE_TOKEN=... \
+//!     cargo test --features client --test sandbox -- --ignored
+//! ```
+#![cfg(feature = "client")]
+
+use std::env;
+use std::fs;
+
+use apple_push::{ApplePushClient, NotificationBuilder};
+use hyper::client::HttpConnector;
+use hyper::Client;
+use hyper_tls::HttpsConnector;
+
+
+/// A client configured for the sandbox from environment variables, plus the
+/// topic and a known good device token to send to.
+struct SandboxHarness {
+    client: ApplePushClient<HttpsConnector<HttpConnector>>,
+    topic: String,
+    device_token: String,
+}
+
+impl SandboxHarness {
+    fn from_env() -> Self {
+        let team_id = var("APNS_TEAM_ID");
+        let key_id = var("APNS_KEY_ID");
+        let key = fs::read_to_string(var("APNS_KEY_PATH")).expect("failed to read APNS_KEY_PATH");
+        let key = base64::decode(
+            key.lines().filter(|line| !line.starts_with("-----")).collect::<String>()
+        ).expect("APNS_KEY_PATH is not a PEM encoded key");
+
+        let http = Client::builder().http2_only(true).build(HttpsConnector::new());
+        let mut client = ApplePushClient::new(http, &team_id, &key_id, &key).expect("failed to create client");
+        client.set_production(false);
+
+        SandboxHarness {
+            client,
+            topic: var("APNS_TOPIC"),
+            device_token: var("APNS_DEVICE_TOKEN"),
+        }
+    }
+}
+
+fn var(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{} must be set to run sandbox tests", name))
+}
+
+#[tokio::test]
+#[ignore]
+async fn sends_to_known_token() {
+    let harness = SandboxHarness::from_env();
+    let notification = NotificationBuilder::new(&harness.topic, &harness.device_token)
+        .title("apple-push")
+        .body("sandbox integration test")
+        .build();
+    harness.client.send(notification).await.expect("send failed");
+}
+
+#[tokio::test]
+#[ignore]
+async fn rejects_invalid_token() {
+    let harness = SandboxHarness::from_env();
+    let notification = NotificationBuilder::new(&harness.topic, "0000")
+        .body("sandbox integration test")
+        .build();
+    let error = harness.client.send(notification).await.expect_err("send to invalid token succeeded");
+    assert!(error.is_bad_device_token(), "unexpected error: {}", error);
+}
@@ -0,0 +1,238 @@
+//! Checks the JSON schemas of notifications against what serializes and
+//! deserializes.
+#![cfg(feature = "schema")]
+
+use apple_push::{ActivityEvent, Environment, InterruptionLevel, NotificationBuilder, Priority, PushType};
+
+const TOPIC: &str = "com.example.app";
+const DEVICE_TOKEN: &str = "8b2f85d2a1e74ef1f1ab2e9a6a4f4e0f3b0d2c6e5a7f9b1c3d5e7f9a1b3c5d7e";
+
+/// Whether `value` matches `schema`, for the parts of JSON Schema that the
+/// crate's schemas use, or `None` if that depends on a `pattern`, which
+/// isn't checked.
+fn matches(root: &serde_json::Value, schema: &serde_json::Value, value: &serde_json::Value) -> Option<bool> {
+    use serde_json::Value;
+
+    if let Some(path) = schema["$ref"].as_str() {
+        return matches(root, &root["definitions"][path.trim_start_matches("#/definitions/")], value);
+    }
+    let mut results = Vec::new();
+    if let Some(ty) = schema["type"].as_str() {
+        results.push(Some(match ty {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => panic!("unknown type {}", ty),
+        }));
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        results.push(Some(values.contains(value)));
+    }
+    if let Some(options) = schema["oneOf"].as_array() {
+        let found: Vec<_> = options.iter().map(|option| matches(root, option, value)).collect();
+        results.push(if found.contains(&None) { None } else { Some(found.iter().filter(|m| **m == Some(true)).count() == 1) });
+    }
+    if let Some(not) = schema.get("not") {
+        results.push(matches(root, not, value).map(|m| !m));
+    }
+    if let Some(n) = value.as_f64() {
+        results.extend(schema["minimum"].as_f64().map(|min| Some(n >= min)));
+        results.extend(schema["maximum"].as_f64().map(|max| Some(n <= max)));
+    }
+    if let Some(s) = value.as_str() {
+        results.extend(schema["maxLength"].as_u64().map(|max| Some(s.chars().count() as u64 <= max)));
+        results.extend(schema.get("pattern").map(|_| None));
+    }
+    if let (Some(items), Some(item)) = (value.as_array(), schema.get("items")) {
+        results.extend(items.iter().map(|v| matches(root, item, v)));
+    }
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema["required"].as_array() {
+            results.push(Some(required.iter().all(|key| object.contains_key(key.as_str().unwrap()))));
+        }
+        for (key, v) in object {
+            if let Some(property) = schema["properties"].get(key).or_else(|| schema.get("additionalProperties")) {
+                results.push(matches(root, property, v));
+            }
+            if let Some(names) = schema.get("propertyNames") {
+                results.push(matches(root, names, &Value::from(key.as_str())));
+            }
+        }
+    }
+    if results.contains(&Some(false)) {
+        Some(false)
+    } else if results.contains(&None) {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+/// A notification with every key the schema describes.
+fn every_key() -> serde_json::Value {
+    serde_json::json!({
+        "topic": TOPIC,
+        "device_token": DEVICE_TOKEN,
+        "payload": {
+            "alert": {
+                "title": "Hello", "body": "World", "title-loc-key": "T", "title-loc-args": ["a"], "subtitle": "Hi",
+                "subtitle-loc-key": "S", "subtitle-loc-args": ["b"], "action-loc-key": "A", "loc-key": "L",
+                "loc-args": ["c"], "launch-image": "launch.png", "summary-arg": "Anna", "summary-arg-count": 2,
+            },
+            "badge": 1,
+            "sound": { "critical": 1, "name": "default", "volume": 0.5 },
+            "content-available": 1,
+            "category": "MESSAGE",
+            "thread-id": "family",
+            "mutable-content": 1,
+            "interruption-level": "time-sensitive",
+            "target-content-id": "window",
+            "relevance-score": 0.5,
+            "filter-criteria": "work",
+            "timestamp": 1_700_000_000,
+            "event": "update",
+            "content-state": { "score": 1 },
+            "dismissal-date": 1_700_000_600,
+            "stale-date": 1_700_000_300,
+            "attributes-type": "MatchAttributes",
+            "attributes": { "home": "A" },
+        },
+        "id": "d6c2a8a4-3b8e-4b6f-9a4e-2c1f0e9b7a65",
+        "expiration": 2_000_000_000,
+        "ttl": { "secs": 60, "nanos": 0 },
+        "priority": "10",
+        "push_type": "alert",
+        "collapse_id": "greeting",
+        "media_url": "https://example.com/image.png",
+        "custom": { "conversation": 42 },
+        "environment": "Sandbox",
+        "extra_apns_headers": { "apns-unlisted": "yes" },
+    })
+}
+
+/// The keys of `doc` that hold the objects the schema has definitions of,
+/// with the schema of each.
+fn described_objects(schema: &serde_json::Value) -> Vec<(Vec<&'static str>, serde_json::Value)> {
+    let definitions = &schema["definitions"];
+    vec![
+        (vec![], schema.clone()),
+        (vec!["payload"], definitions["Payload"].clone()),
+        (vec!["payload", "alert"], definitions["AlertPayload"].clone()),
+        (vec!["payload", "sound"], definitions["CriticalSound"].clone()),
+    ]
+}
+
+fn object_at<'a>(doc: &'a mut serde_json::Value, path: &[&str]) -> &'a mut serde_json::Map<String, serde_json::Value> {
+    path.iter().fold(doc, |doc, key| &mut doc[*key]).as_object_mut().unwrap()
+}
+
+#[test]
+fn what_serializes_matches_the_schema() {
+    let schema = apple_push::schema::notification();
+    let n: apple_push::Notification = serde_json::from_value(every_key()).unwrap();
+    let serialized = serde_json::to_value(&n).unwrap();
+    // The apns-id and extra header names are only checked against their
+    // patterns by the round trip.
+    assert_ne!(matches(&schema, &schema, &serialized), Some(false), "{:#}", serialized);
+    let simple = NotificationBuilder::new(TOPIC, DEVICE_TOKEN).alert("Hello").sound("default").build();
+    assert_eq!(matches(&schema, &schema, &serde_json::to_value(&simple).unwrap()), Some(true));
+    let payload = apple_push::schema::payload();
+    assert_eq!(matches(&payload, &payload, &serialized["payload"]), Some(true));
+}
+
+#[test]
+fn schema_only_allows_what_deserializes() {
+    use serde_json::json;
+
+    let schema = apple_push::schema::notification();
+    let candidates = [
+        json!(null), json!(false), json!(true), json!(0), json!(1), json!(255), json!(256), json!(-1), json!(0.5),
+        json!(1.5), json!(""), json!("x"), json!("5"), json!("alert"), json!([]), json!(["x"]), json!([1]), json!({}),
+        json!({ "secs": 60, "nanos": 0 }), json!({ "critical": 1, "name": "default", "volume": 0.5 }),
+        json!({ "title": "Hello" }), json!({ "apns-unlisted": 1 }), json!({ "aps": {} }), json!("active"),
+        json!("update"), json!("Sandbox"),
+    ];
+    for (path, object) in described_objects(&schema) {
+        for key in object["properties"].as_object().unwrap().keys() {
+            let mut accepted = 0;
+            for candidate in &candidates {
+                // Without the keys checked against patterns, so that whether
+                // the document matches is known.
+                let mut doc = every_key();
+                object_at(&mut doc, &[]).remove("id");
+                object_at(&mut doc, &[]).remove("extra_apns_headers");
+                object_at(&mut doc, &path).insert(key.clone(), candidate.clone());
+                if matches(&schema, &schema, &doc) == Some(true) {
+                    let result = serde_json::from_value::<apple_push::Notification>(doc);
+                    assert!(result.is_ok(), "{:?} {} = {} matches the schema but doesn't deserialize", path, key, candidate);
+                    accepted += 1;
+                }
+            }
+            assert!(accepted > 0, "no value of {:?} {} was tried", path, key);
+        }
+    }
+}
+
+#[test]
+fn schema_requires_what_deserializing_requires() {
+    let schema = apple_push::schema::notification();
+    for (path, object) in described_objects(&schema) {
+        let required = object["required"].as_array().cloned().unwrap_or_default();
+        for key in object["properties"].as_object().unwrap().keys() {
+            let mut doc = every_key();
+            object_at(&mut doc, &path).remove(key);
+            let result = serde_json::from_value::<apple_push::Notification>(doc);
+            assert_eq!(result.is_err(), required.contains(&key.as_str().into()), "{:?} {}", path, key);
+        }
+    }
+}
+
+#[test]
+fn schema_enums_list_every_variant() {
+    // Matched without a wildcard, so that a new variant doesn't compile
+    // until it is added to the lists below.
+    let _ = |p: PushType, i: InterruptionLevel, e: ActivityEvent, env: Environment, priority: Priority| {
+        match p {
+            PushType::Alert | PushType::Background | PushType::Location | PushType::Voip | PushType::Complication
+            | PushType::FileProvider | PushType::Mdm | PushType::LiveActivity | PushType::PushToTalk => {}
+        }
+        match i {
+            InterruptionLevel::Passive | InterruptionLevel::Active | InterruptionLevel::TimeSensitive | InterruptionLevel::Critical => {}
+        }
+        match e {
+            ActivityEvent::Start | ActivityEvent::Update | ActivityEvent::End => {}
+        }
+        match env {
+            Environment::Production | Environment::Sandbox => {}
+        }
+        match priority {
+            Priority::Low | Priority::High => {}
+        }
+    };
+    fn values<T: serde::Serialize>(variants: &[T]) -> serde_json::Value {
+        serde_json::to_value(variants).unwrap()
+    }
+
+    let schema = apple_push::schema::notification();
+    let properties = &schema["properties"];
+    let payload = &schema["definitions"]["Payload"]["properties"];
+    let enums = [
+        (&properties["push_type"]["oneOf"][1]["enum"], values(&[
+            PushType::Alert, PushType::Background, PushType::Location, PushType::Voip, PushType::Complication,
+            PushType::FileProvider, PushType::Mdm, PushType::LiveActivity, PushType::PushToTalk,
+        ])),
+        (&payload["interruption-level"]["enum"], values(&[
+            InterruptionLevel::Passive, InterruptionLevel::Active, InterruptionLevel::TimeSensitive, InterruptionLevel::Critical,
+        ])),
+        (&payload["event"]["enum"], values(&[ActivityEvent::Start, ActivityEvent::Update, ActivityEvent::End])),
+        (&properties["environment"]["oneOf"][1]["enum"], values(&[Environment::Production, Environment::Sandbox])),
+        (&properties["priority"]["oneOf"][1]["enum"], values(&[Priority::Low, Priority::High])),
+    ];
+    for (listed, variants) in enums.iter() {
+        assert_eq!(*listed, variants);
+    }
+}